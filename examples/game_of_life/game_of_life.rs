@@ -10,6 +10,7 @@
 use std::sync::Arc;
 
 use bevy::{math::IVec2, prelude::Resource};
+use bevy_vulkano::ComputeTasks;
 use rand::Rng;
 use vulkano::{
     buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
@@ -48,13 +49,17 @@ pub struct GameOfLifeComputePipeline {
     image: Arc<ImageView>,
 }
 
-fn rand_grid(allocator: &Arc<StandardMemoryAllocator>, size: [u32; 2]) -> Subbuffer<[u32]> {
+fn rand_grid(
+    allocator: &Arc<StandardMemoryAllocator>,
+    compute_tasks: &ComputeTasks,
+    size: [u32; 2],
+) -> Subbuffer<[u32]> {
     Buffer::from_iter(
         allocator.clone(),
-        BufferCreateInfo {
+        compute_tasks.shared_buffer_create_info(BufferCreateInfo {
             usage: BufferUsage::STORAGE_BUFFER,
             ..Default::default()
-        },
+        }),
         AllocationCreateInfo {
             memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
                 | MemoryTypeFilter::HOST_RANDOM_ACCESS,
@@ -70,11 +75,12 @@ fn rand_grid(allocator: &Arc<StandardMemoryAllocator>, size: [u32; 2]) -> Subbuf
 impl GameOfLifeComputePipeline {
     pub fn new(
         allocator: &Arc<StandardMemoryAllocator>,
-        compute_queue: Arc<Queue>,
+        compute_tasks: &ComputeTasks,
         size: [u32; 2],
     ) -> GameOfLifeComputePipeline {
-        let life_in = rand_grid(allocator, size);
-        let life_out = rand_grid(allocator, size);
+        let compute_queue = compute_tasks.compute_queue();
+        let life_in = rand_grid(allocator, compute_tasks, size);
+        let life_out = rand_grid(allocator, compute_tasks, size);
 
         let compute_life_pipeline = {
             let cs = compute_life_cs::load(allocator.device().clone())
@@ -101,7 +107,8 @@ impl GameOfLifeComputePipeline {
         let image = ImageView::new_default(
             Image::new(
                 allocator.clone(),
-                ImageCreateInfo {
+                // The image is written on the compute queue, and sampled on the graphics queue.
+                compute_tasks.shared_image_create_info(ImageCreateInfo {
                     image_type: ImageType::Dim2d,
                     format: Format::R8G8B8A8_UNORM,
                     extent: [size[0], size[1], 1],
@@ -109,7 +116,7 @@ impl GameOfLifeComputePipeline {
                     usage: ImageUsage::SAMPLED | ImageUsage::STORAGE | ImageUsage::TRANSFER_DST,
 
                     ..Default::default()
-                },
+                }),
                 AllocationCreateInfo::default(),
             )
             .unwrap(),
@@ -173,7 +180,9 @@ impl GameOfLifeComputePipeline {
         let finished = before_future
             .then_execute(self.compute_queue.clone(), command_buffer)
             .unwrap();
-        let after_pipeline = finished.then_signal_fence_and_flush().unwrap().boxed();
+        // The render pass runs on the graphics queue, which might be a different queue, so it
+        // waits on a semaphore rather than a fence.
+        let after_pipeline = finished.then_signal_semaphore_and_flush().unwrap().boxed();
 
         // Swap input and output so the output becomes the input for next frame
         std::mem::swap(&mut self.life_in, &mut self.life_out);
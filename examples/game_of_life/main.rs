@@ -4,13 +4,10 @@ mod game_of_life;
 mod pixels_draw_pipeline;
 mod place_over_frame;
 
-use std::time::Duration;
-
-use bevy::{
-    app::PluginGroupBuilder, prelude::*, time::common_conditions::on_timer, window::WindowMode,
-    winit::WakeUp,
+use bevy::{app::PluginGroupBuilder, prelude::*, window::WindowMode, winit::WakeUp};
+use bevy_vulkano::{
+    BevyVulkanoContext, ComputeTasks, FramePacing, VulkanoPlugin, VulkanoRenderers,
 };
-use bevy_vulkano::{BevyVulkanoContext, VulkanoPlugin, VulkanoRenderers};
 
 use crate::{game_of_life::GameOfLifeComputePipeline, place_over_frame::RenderPassPlaceOverFrame};
 
@@ -37,6 +34,8 @@ impl PluginGroup for PluginBundle {
 
 fn main() {
     App::new()
+        // The simulation steps once per frame, so cap it at 60 steps per second.
+        .insert_resource(FramePacing::capped(60.0))
         .add_plugins(PluginBundle.set(WindowPlugin {
             primary_window: Some(Window {
                 resolution: (1024.0, 1024.0).into(),
@@ -52,10 +51,7 @@ fn main() {
         //.add_systems(Update, close_on_esc)
         .add_systems(Update, draw_life_system)
         .add_systems(Update, update_window_title_system)
-        .add_systems(
-            PostUpdate,
-            game_of_life_pipeline_system.run_if(on_timer(Duration::from_secs_f32(1.0 / 60.0))),
-        )
+        .add_systems(PostUpdate, game_of_life_pipeline_system)
         .run();
 }
 
@@ -71,16 +67,15 @@ fn create_pipelines(
     mut commands: Commands,
     window_query: Query<Entity, With<Window>>,
     context: Res<BevyVulkanoContext>,
+    compute_tasks: NonSend<ComputeTasks>,
     mut renderers: VulkanoRenderers,
 ) {
     let window_entity = window_query.single();
     let primary_window = renderers.get_renderer(window_entity).unwrap();
-    // Create compute pipeline to simulate game of life
-    let game_of_life_pipeline = GameOfLifeComputePipeline::new(
-        context.memory_allocator(),
-        primary_window.graphics_queue(),
-        [512, 512],
-    );
+    // Create compute pipeline to simulate game of life. It runs on the compute queue, which is
+    // separate from the graphics queue if the device has one.
+    let game_of_life_pipeline =
+        GameOfLifeComputePipeline::new(context.memory_allocator(), &compute_tasks, [512, 512]);
     // Create our render pass
     let place_over_frame = RenderPassPlaceOverFrame::new(
         context.memory_allocator().clone(),
@@ -0,0 +1,188 @@
+use std::{sync::Arc, time::Duration};
+
+use bevy::{
+    a11y::AccessibilityPlugin,
+    prelude::*,
+    window::WindowMode,
+    winit::{WakeUp, WinitPlugin},
+};
+use bevy_vulkano::{
+    gizmos::prepare_gizmos, BevyVulkanoContext, CameraPlugin, GizmoPlugin, PerSwapchainFormat,
+    VulkanoCamera, VulkanoGizmos, VulkanoPlugin, VulkanoRenderers,
+};
+use vulkano::{
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        RenderPassBeginInfo, SubpassBeginInfo, SubpassContents,
+    },
+    render_pass::RenderPass,
+    sync::GpuFuture,
+};
+
+/*
+* This example only clears the window, and draws debug gizmos over it from a camera orbiting the
+* origin: a grid, the world axes, a spinning box, a sphere, and 2D shapes standing on the xy plane.
+ */
+
+fn main() {
+    App::new()
+        .add_plugins((
+            bevy::log::LogPlugin::default(),
+            bevy::time::TimePlugin,
+            AccessibilityPlugin,
+            WindowPlugin {
+                primary_window: Some(Window {
+                    resolution: (1024.0, 768.0).into(),
+                    title: "Bevy Vulkano Gizmos".to_string(),
+                    present_mode: bevy::window::PresentMode::Fifo,
+                    resizable: true,
+                    mode: WindowMode::Windowed,
+                    ..default()
+                }),
+                ..default()
+            },
+            WinitPlugin::<WakeUp>::default(),
+            VulkanoPlugin::default(),
+            CameraPlugin,
+            GizmoPlugin,
+        ))
+        .add_systems(Startup, setup)
+        .add_systems(Update, (orbit_camera, draw_gizmos))
+        // Render once the gizmos are prepared, so they show up this frame.
+        .add_systems(PostUpdate, render.after(prepare_gizmos))
+        .run();
+}
+
+#[derive(Resource)]
+struct Stuff {
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    render_passes: PerSwapchainFormat<Arc<RenderPass>>,
+}
+
+fn setup(context: Res<BevyVulkanoContext>, mut commands: Commands) {
+    commands.insert_resource(Stuff {
+        command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
+            context.device().clone(),
+            Default::default(),
+        )),
+        render_passes: PerSwapchainFormat::default(),
+    });
+
+    commands.spawn((
+        VulkanoCamera::perspective(std::f32::consts::FRAC_PI_4, 0.1, 100.0),
+        TransformBundle::default(),
+    ));
+}
+
+fn orbit_camera(time: Res<Time>, mut cameras: Query<&mut Transform, With<VulkanoCamera>>) {
+    let angle = time.elapsed_seconds() * 0.2;
+    for mut transform in &mut cameras {
+        *transform = Transform::from_xyz(angle.cos() * 12.0, 6.0, angle.sin() * 12.0)
+            .looking_at(Vec3::ZERO, Vec3::Y);
+    }
+}
+
+fn draw_gizmos(time: Res<Time>, mut gizmos: ResMut<VulkanoGizmos>) {
+    let t = time.elapsed_seconds();
+
+    gizmos.grid(
+        Vec3::ZERO,
+        Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+        UVec2::splat(10),
+        Vec2::ONE,
+        [0.3, 0.3, 0.3, 1.0],
+    );
+    gizmos.axes(Transform::IDENTITY, 2.0);
+
+    gizmos.cuboid(
+        Transform::from_xyz(0.0, 1.0, 0.0)
+            .with_rotation(Quat::from_rotation_y(t))
+            .with_scale(Vec3::new(2.0, 2.0, 1.0)),
+        [1.0, 0.8, 0.2, 1.0],
+    );
+    gizmos.sphere(
+        Vec3::new(3.0, 1.0 + t.sin(), -2.0),
+        Quat::from_rotation_y(t * 0.5),
+        1.0,
+        [0.2, 0.8, 1.0, 1.0],
+    );
+    gizmos.arrow(Vec3::new(-3.0, 0.0, 3.0), Vec3::new(-3.0, 3.0, 3.0), [
+        1.0, 0.2, 0.8, 1.0,
+    ]);
+    gizmos.line_gradient(
+        Vec3::new(-4.0, 0.5, -4.0),
+        Vec3::new(4.0, 0.5, -4.0),
+        [1.0, 0.0, 0.0, 1.0],
+        [0.0, 0.0, 1.0, 1.0],
+    );
+
+    // 2D shapes are on the xy plane, standing up behind the grid.
+    gizmos.rect_2d(Vec2::new(0.0, 2.0), t, Vec2::splat(2.0), [
+        0.5, 1.0, 0.5, 1.0,
+    ]);
+    gizmos.circle_2d(Vec2::new(0.0, 2.0), 1.5, [0.5, 1.0, 0.5, 1.0]);
+}
+
+fn render(
+    context: Res<BevyVulkanoContext>,
+    mut stuff: ResMut<Stuff>,
+    mut vulkano_windows: VulkanoRenderers,
+) {
+    let Some(mut renderer) = vulkano_windows.get_primary_renderer() else {
+        return;
+    };
+    let Ok(before) = renderer.acquire(Some(Duration::from_millis(1000)), |_| {}) else {
+        return;
+    };
+
+    let device = context.device().clone();
+    let render_pass = stuff
+        .render_passes
+        .get_or_insert_with(renderer.swapchain_format(), |format| {
+            vulkano::single_pass_renderpass!(
+                device,
+                attachments: {
+                    color: {
+                        format: format,
+                        samples: 1,
+                        load_op: Clear,
+                        store_op: Store,
+                    },
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {},
+                },
+            )
+            .unwrap()
+        })
+        .clone();
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        stuff.command_buffer_allocator.clone(),
+        context.graphics_queue().queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    builder
+        .begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: vec![Some([0.05, 0.05, 0.08, 1.0].into())],
+                ..RenderPassBeginInfo::framebuffer(renderer.framebuffer(&render_pass, &[]).unwrap())
+            },
+            SubpassBeginInfo {
+                contents: SubpassContents::Inline,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .end_render_pass(Default::default())
+        .unwrap();
+
+    let after = before
+        .then_execute(context.graphics_queue().clone(), builder.build().unwrap())
+        .unwrap()
+        .boxed();
+    // The gizmos are drawn over the image here.
+    renderer.present(after, false);
+}
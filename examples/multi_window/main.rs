@@ -0,0 +1,328 @@
+use std::{sync::Arc, time::Duration};
+
+use bevy::{
+    a11y::AccessibilityPlugin,
+    prelude::*,
+    window::{PrimaryWindow, WindowMode},
+    winit::{WakeUp, WinitPlugin},
+};
+use bevy_vulkano::{
+    uploader::UploadHandle, BevyVulkanoContext, GpuUploader, PerSwapchainFormat, VulkanoPlugin,
+    VulkanoRenderers,
+};
+use vulkano::{
+    buffer::{BufferContents, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        RenderPassBeginInfo, SubpassBeginInfo, SubpassContents,
+    },
+    device::Device,
+    format::Format,
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::{Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        DynamicState, GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{RenderPass, Subpass},
+    shader::ShaderModule,
+    sync::GpuFuture,
+};
+
+/*
+* This example adds windows when pressing space. Windows can be closed as well, closing the primary
+* window exits. The same triangle is drawn into every window, with one pipeline per swapchain
+* format.
+ */
+
+fn main() {
+    App::new()
+        .add_plugins((
+            bevy::log::LogPlugin::default(),
+            bevy::input::InputPlugin,
+            AccessibilityPlugin,
+            WindowPlugin {
+                primary_window: Some(Window {
+                    resolution: (1024.0, 768.0).into(),
+                    title: "Bevy Vulkano Primary Window".to_string(),
+                    present_mode: bevy::window::PresentMode::Fifo,
+                    resizable: true,
+                    mode: WindowMode::Windowed,
+                    ..default()
+                }),
+                ..default()
+            },
+            WinitPlugin::<WakeUp>::default(),
+            VulkanoPlugin::default(),
+        ))
+        .add_systems(Startup, setup)
+        .add_systems(Update, (create_new_window_system, render))
+        .run();
+}
+
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct MyVertex {
+    #[format(R32G32_SFLOAT)]
+    position: [f32; 2],
+}
+
+/// A render pass and pipeline for one swapchain format.
+struct FormatPipeline {
+    render_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+}
+
+#[derive(Resource)]
+struct Stuff {
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    vertices: Subbuffer<[MyVertex]>,
+    vertices_upload: UploadHandle,
+    vs: Arc<ShaderModule>,
+    fs: Arc<ShaderModule>,
+    /// Shared between every window with the same swapchain format.
+    pipelines: PerSwapchainFormat<FormatPipeline>,
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec2 position;
+
+            void main() {
+                gl_Position = vec4(position, 0.0, 1.0);
+            }
+        ",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = vec4(1.0, 0.0, 0.0, 1.0);
+            }
+        ",
+    }
+}
+
+fn setup(
+    mut renderers: VulkanoRenderers,
+    context: Res<BevyVulkanoContext>,
+    mut uploader: NonSendMut<GpuUploader>,
+    mut commands: Commands,
+) {
+    let vertices = [
+        MyVertex {
+            position: [-0.5, -0.25],
+        },
+        MyVertex {
+            position: [0.0, 0.5],
+        },
+        MyVertex {
+            position: [0.25, -0.1],
+        },
+    ];
+    let (vertices, vertices_upload) =
+        uploader.create_buffer_from_iter(BufferUsage::VERTEX_BUFFER, vertices);
+
+    let mut stuff = Stuff {
+        command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
+            context.device().clone(),
+            default(),
+        )),
+        vertices,
+        vertices_upload,
+        vs: vs::load(context.device().clone()).unwrap(),
+        fs: fs::load(context.device().clone()).unwrap(),
+        pipelines: PerSwapchainFormat::default(),
+    };
+
+    // The primary window's pipeline can be made up front, the others are made when their windows
+    // show up.
+    let primary_format = renderers.get_primary_renderer().unwrap().swapchain_format();
+    stuff
+        .pipelines
+        .get_or_insert_with(primary_format, |format| {
+            create_pipeline(context.device(), &stuff.vs, &stuff.fs, format)
+        });
+
+    commands.insert_resource(stuff);
+}
+
+fn create_new_window_system(mut commands: Commands, keys: Res<ButtonInput<KeyCode>>) {
+    if keys.just_released(KeyCode::Space) {
+        commands.spawn(Window {
+            resolution: (512.0, 512.0).into(),
+            present_mode: bevy::window::PresentMode::Fifo,
+            title: "Secondary window".to_string(),
+            ..default()
+        });
+    }
+}
+
+fn render(
+    mut renderers: VulkanoRenderers,
+    primary_window: Query<(), With<PrimaryWindow>>,
+    stuff: Option<ResMut<Stuff>>,
+    context: Res<BevyVulkanoContext>,
+    mut uploader: NonSendMut<GpuUploader>,
+) {
+    let Some(mut stuff) = stuff else {
+        return;
+    };
+    let stuff = &mut *stuff;
+
+    for (entity, mut renderer) in renderers.iter_mut() {
+        let FormatPipeline {
+            render_pass,
+            pipeline,
+        } = &*stuff
+            .pipelines
+            .get_or_insert_with(renderer.swapchain_format(), |format| {
+                create_pipeline(context.device(), &stuff.vs, &stuff.fs, format)
+            });
+
+        let Ok(previous_frame_end) = renderer.acquire(Some(Duration::from_millis(1000)), |_| {})
+        else {
+            continue;
+        };
+        let previous_frame_end = uploader
+            .join(stuff.vertices_upload, previous_frame_end)
+            .unwrap();
+
+        // Tell the windows apart by their clear color.
+        let clear_color = if primary_window.contains(entity) {
+            [0.0, 0.0, 1.0, 1.0]
+        } else {
+            [0.0, 0.5, 0.0, 1.0]
+        };
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            stuff.command_buffer_allocator.clone(),
+            context.graphics_queue().queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![Some(clear_color.into())],
+                    ..RenderPassBeginInfo::framebuffer(
+                        renderer.framebuffer(render_pass, &[]).unwrap(),
+                    )
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .set_viewport(
+                0,
+                [Viewport {
+                    offset: [0.; 2],
+                    extent: renderer.window_size(),
+                    depth_range: 0.0..=1.,
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .bind_pipeline_graphics(pipeline.clone())
+            .unwrap()
+            .bind_vertex_buffers(0, stuff.vertices.clone())
+            .unwrap();
+
+        unsafe {
+            builder.draw(stuff.vertices.len() as u32, 1, 0, 0).unwrap();
+        }
+
+        builder.end_render_pass(Default::default()).unwrap();
+
+        let future = previous_frame_end
+            .then_execute(context.graphics_queue().clone(), builder.build().unwrap())
+            .unwrap()
+            .boxed();
+
+        renderer.present(future, false);
+    }
+}
+
+fn create_pipeline(
+    device: &Arc<Device>,
+    vs: &Arc<ShaderModule>,
+    fs: &Arc<ShaderModule>,
+    format: Format,
+) -> FormatPipeline {
+    let render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                format: format,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {},
+        },
+    )
+    .unwrap();
+
+    let vs = vs.entry_point("main").unwrap();
+    let fs = fs.entry_point("main").unwrap();
+    let vertex_input_state = MyVertex::per_vertex().definition(&vs).unwrap();
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+    let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+    let pipeline = GraphicsPipeline::new(device.clone(), None, GraphicsPipelineCreateInfo {
+        stages: stages.into_iter().collect(),
+        vertex_input_state: Some(vertex_input_state),
+        input_assembly_state: Some(InputAssemblyState::default()),
+        viewport_state: Some(ViewportState::default()),
+        rasterization_state: Some(RasterizationState::default()),
+        multisample_state: Some(MultisampleState::default()),
+        color_blend_state: Some(ColorBlendState::with_attachment_states(
+            subpass.num_color_attachments(),
+            ColorBlendAttachmentState::default(),
+        )),
+        dynamic_state: [DynamicState::Viewport].into_iter().collect(),
+        subpass: Some(subpass.into()),
+        ..GraphicsPipelineCreateInfo::layout(layout)
+    })
+    .unwrap();
+
+    FormatPipeline {
+        render_pass,
+        pipeline,
+    }
+}
@@ -1,59 +1,104 @@
-// TODO: Fix this.
+use std::{sync::Arc, time::Duration};
 
-// Temp, to get rid of the "no main" error.
-fn main() {
-    todo!()
-}
-
-/*
 use bevy::{
-    app::PluginGroupBuilder,
+    a11y::AccessibilityPlugin,
+    ecs::entity::EntityHashMap,
     prelude::*,
     window::{PrimaryWindow, WindowMode},
+    winit::{WakeUp, WinitPlugin},
 };
 use bevy_vulkano::{
-    egui_winit_vulkano::egui, BevyVulkanoSettings, BevyVulkanoWindows, VulkanoWinitPlugin,
+    egui, uploader::UploadHandle, BevyVulkanoContext, GpuUploader, GuiPlugin, Guis, VulkanoPlugin,
+    VulkanoRenderers,
+};
+use vulkano::{
+    format::Format,
+    image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage},
+    memory::allocator::AllocationCreateInfo,
 };
-
-pub struct PluginBundle;
-
-impl PluginGroup for PluginBundle {
-    fn build(self) -> PluginGroupBuilder {
-        PluginGroupBuilder::start::<PluginBundle>()
-            .add(bevy::input::InputPlugin)
-            .add(bevy::window::WindowPlugin::default())
-            .add(VulkanoWinitPlugin)
-    }
-}
 
 /*
-* This example adds windows when clicking space button. Windows can be closed as well
+* This example adds windows when pressing space. Every window has its own gui, drawn over a clear
+* color that can be changed from the gui. A checkerboard image is shown in each gui as a user
+* texture.
  */
 
 fn main() {
     App::new()
-        .insert_non_send_resource(BevyVulkanoSettings {
-            // Since we're only drawing gui, let's clear each frame
-            //is_gui_overlay: true,
-            ..BevyVulkanoSettings::default()
-        })
-        .add_plugins(PluginBundle.set(WindowPlugin {
-            primary_window: Some(Window {
-                resolution: (1920.0, 1080.0).into(),
-                title: "Bevy Vulkano Primary Window".to_string(),
-                present_mode: bevy::window::PresentMode::Fifo,
-                resizable: true,
-                mode: WindowMode::Windowed,
+        .add_plugins((
+            bevy::log::LogPlugin::default(),
+            bevy::time::TimePlugin,
+            bevy::input::InputPlugin,
+            AccessibilityPlugin,
+            WindowPlugin {
+                primary_window: Some(Window {
+                    resolution: (1280.0, 720.0).into(),
+                    title: "Bevy Vulkano Primary Window".to_string(),
+                    present_mode: bevy::window::PresentMode::Fifo,
+                    resizable: true,
+                    mode: WindowMode::Windowed,
+                    ..default()
+                }),
                 ..default()
-            }),
-            ..default()
-        }))
-        //.add_systems(Update, close_on_esc)
-        .add_systems(Update, create_new_window_system)
-        .add_systems(PostUpdate, main_render_system_primary_window)
+            },
+            WinitPlugin::<WakeUp>::default(),
+            VulkanoPlugin::default(),
+            GuiPlugin,
+        ))
+        .add_systems(Startup, setup)
+        .add_systems(Update, (create_new_window_system, (gui, render).chain()))
         .run();
 }
 
+#[derive(Resource)]
+struct Checkerboard {
+    view: Arc<ImageView>,
+    upload: UploadHandle,
+    /// The texture id of the image in each window's gui.
+    texture_ids: EntityHashMap<egui::TextureId>,
+}
+
+/// The clear color of each window, edited in its gui.
+#[derive(Resource, Default)]
+struct ClearColors(EntityHashMap<[f32; 3]>);
+
+fn setup(
+    context: Res<BevyVulkanoContext>,
+    mut uploader: NonSendMut<GpuUploader>,
+    mut commands: Commands,
+) {
+    const SIZE: u32 = 64;
+
+    let image = Image::new(
+        context.memory_allocator().clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::R8G8B8A8_SRGB,
+            extent: [SIZE, SIZE, 1],
+            usage: ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+    let pixels = (0..SIZE * SIZE).map(|i| {
+        let (x, y) = (i % SIZE / 8, i / SIZE / 8);
+        if (x + y) % 2 == 0 {
+            [255u8, 255, 255, 255]
+        } else {
+            [40, 40, 40, 255]
+        }
+    });
+    let upload = uploader.upload_image(pixels, image.clone());
+
+    commands.insert_resource(Checkerboard {
+        view: ImageView::new_default(image).unwrap(),
+        upload,
+        texture_ids: EntityHashMap::default(),
+    });
+    commands.init_resource::<ClearColors>();
+}
+
 fn create_new_window_system(mut commands: Commands, keys: Res<ButtonInput<KeyCode>>) {
     if keys.just_released(KeyCode::Space) {
         commands.spawn(Window {
@@ -65,37 +110,71 @@ fn create_new_window_system(mut commands: Commands, keys: Res<ButtonInput<KeyCod
     }
 }
 
-pub fn main_render_system_primary_window(
-    window_query: Query<(Entity, Option<&PrimaryWindow>), With<Window>>,
-    mut vulkano_windows: NonSendMut<BevyVulkanoWindows>,
+fn gui(
+    windows: Query<(Entity, Has<PrimaryWindow>), With<Window>>,
+    mut guis: ResMut<Guis>,
+    mut checkerboard: ResMut<Checkerboard>,
+    mut clear_colors: ResMut<ClearColors>,
 ) {
-    for (window, maybe_primary) in window_query.iter() {
-        if let Some(vulkano_window) = vulkano_windows.get_vulkano_window_mut(window) {
-            // Start Frame
-            let before = match vulkano_window.renderer.acquire() {
-                Err(e) => {
-                    bevy::log::error!("Failed to start frame: {}", e);
-                    return;
-                }
-                Ok(f) => f,
-            };
-            // Egui calls
-            let ctx = vulkano_window.gui.context();
-            egui::Area::new("Window Gui")
-                .fixed_pos(egui::pos2(10.0, 10.0))
-                .show(&ctx, |ui| {
-                    if maybe_primary.is_some() {
-                        ui.label("Primary Window");
-                    } else {
-                        ui.label("Secondary Window");
-                    }
+    let checkerboard = &mut *checkerboard;
+    checkerboard
+        .texture_ids
+        .retain(|entity, _| windows.contains(*entity));
+    clear_colors.0.retain(|entity, _| windows.contains(*entity));
+
+    for (entity, is_primary) in &windows {
+        // The gui is created in `PreUpdate`, so a window spawned this frame doesn't have one yet.
+        let Some(gui) = guis.get_mut(entity) else {
+            continue;
+        };
+        let texture_id = *checkerboard.texture_ids.entry(entity).or_insert_with(|| {
+            gui.register_user_image(checkerboard.view.clone(), egui::TextureOptions::NEAREST)
+        });
+        let clear_color = clear_colors.0.entry(entity).or_insert(if is_primary {
+            [0.0, 0.0, 0.5]
+        } else {
+            [0.0, 0.3, 0.0]
+        });
+
+        egui::Window::new("Gui")
+            .default_pos(egui::pos2(10.0, 10.0))
+            .show(&gui.context(), |ui| {
+                ui.label(if is_primary {
+                    "Primary window"
+                } else {
+                    "Secondary window, press space in any window to add one"
                 });
-            let final_image = vulkano_window.renderer.swapchain_image_view();
-            // Render egui
-            let after = vulkano_window.gui.draw_on_image(before, final_image);
-            // Finish frame
-            vulkano_window.renderer.present(after, true);
-        }
+                ui.horizontal(|ui| {
+                    ui.label("Clear color");
+                    ui.color_edit_button_rgb(clear_color);
+                });
+                ui.image(egui::load::SizedTexture::new(texture_id, [128.0, 128.0]));
+            });
+    }
+}
+
+fn render(
+    mut renderers: VulkanoRenderers,
+    mut guis: ResMut<Guis>,
+    checkerboard: Res<Checkerboard>,
+    clear_colors: Res<ClearColors>,
+    mut uploader: NonSendMut<GpuUploader>,
+) {
+    for (entity, mut renderer) in renderers.iter_mut() {
+        let Some(gui) = guis.get_mut(entity) else {
+            continue;
+        };
+
+        let Ok(before) = renderer.acquire(Some(Duration::from_millis(1000)), |_| {}) else {
+            continue;
+        };
+        // The gui samples the checkerboard, so wait for it to be uploaded.
+        let before = uploader.join(checkerboard.upload, before).unwrap();
+
+        let [r, g, b] = clear_colors.0.get(&entity).copied().unwrap_or_default();
+        let after =
+            gui.draw_on_cleared_image(before, renderer.swapchain_image_view(), [r, g, b, 1.0]);
+
+        renderer.present(after, true);
     }
 }
-*/
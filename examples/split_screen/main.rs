@@ -0,0 +1,409 @@
+use std::{sync::Arc, time::Duration};
+
+use bevy::{
+    a11y::AccessibilityPlugin,
+    prelude::*,
+    window::WindowMode,
+    winit::{WakeUp, WinitPlugin},
+};
+use bevy_vulkano::{
+    uploader::UploadHandle, BevyVulkanoContext, CameraMatrices, CameraPlugin, GpuUploader,
+    PerSwapchainFormat, VulkanoCamera, VulkanoPlugin, VulkanoRenderers,
+};
+use vulkano::{
+    buffer::{BufferContents, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, ClearAttachment,
+        ClearRect, CommandBufferUsage, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
+    },
+    device::Device,
+    format::Format,
+    pipeline::{
+        graphics::{
+            color_blend::{ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::ViewportState,
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        DynamicState, GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+    },
+    render_pass::{RenderPass, Subpass},
+    shader::ShaderModule,
+    sync::GpuFuture,
+};
+
+/*
+* This example renders the same field of triangles with three cameras sharing one window: two
+* players side by side, and a top-down minimap in the top right corner over them. Resize the window
+* to see the viewports follow.
+ */
+
+fn main() {
+    App::new()
+        .add_plugins((
+            bevy::log::LogPlugin::default(),
+            bevy::time::TimePlugin,
+            bevy::input::InputPlugin,
+            AccessibilityPlugin,
+            WindowPlugin {
+                primary_window: Some(Window {
+                    resolution: (1280.0, 720.0).into(),
+                    title: "Bevy Vulkano Split Screen".to_string(),
+                    present_mode: bevy::window::PresentMode::Fifo,
+                    resizable: true,
+                    mode: WindowMode::Windowed,
+                    ..default()
+                }),
+                ..default()
+            },
+            WinitPlugin::<WakeUp>::default(),
+            VulkanoPlugin::default(),
+            CameraPlugin,
+        ))
+        .add_systems(Startup, setup)
+        .add_systems(Update, move_players)
+        // Render once this frame's camera matrices are ready.
+        .add_systems(
+            PostUpdate,
+            render.after(bevy_vulkano::camera::update_cameras),
+        )
+        .run();
+}
+
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct MyVertex {
+    #[format(R32G32B32_SFLOAT)]
+    position: [f32; 3],
+    #[format(R32G32B32_SFLOAT)]
+    color: [f32; 3],
+}
+
+/// A render pass and pipeline for one swapchain format.
+struct FormatPipeline {
+    render_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+}
+
+#[derive(Resource)]
+struct Stuff {
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    vertices: Subbuffer<[MyVertex]>,
+    vertices_upload: UploadHandle,
+    vs: Arc<ShaderModule>,
+    fs: Arc<ShaderModule>,
+    pipelines: PerSwapchainFormat<FormatPipeline>,
+}
+
+/// A camera orbiting the center of the field.
+#[derive(Component)]
+struct Player {
+    angle: f32,
+    speed: f32,
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec3 color;
+
+            layout(location = 0) out vec3 v_color;
+
+            layout(set = 0, binding = 0) uniform Camera {
+                mat4 view;
+                mat4 projection;
+                mat4 view_proj;
+                mat4 inverse_view;
+                vec4 position;
+            } camera;
+
+            void main() {
+                gl_Position = camera.view_proj * vec4(position, 1.0);
+                v_color = color;
+            }
+        ",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+
+            layout(location = 0) in vec3 v_color;
+
+            layout(location = 0) out vec4 f_color;
+
+            void main() {
+                f_color = vec4(v_color, 1.0);
+            }
+        ",
+    }
+}
+
+fn setup(
+    context: Res<BevyVulkanoContext>,
+    mut uploader: NonSendMut<GpuUploader>,
+    mut commands: Commands,
+) {
+    // A field of triangles on the xz plane, colored by where they are.
+    const HALF_SIZE: i32 = 10;
+    let vertices = (-HALF_SIZE..=HALF_SIZE)
+        .flat_map(|x| (-HALF_SIZE..=HALF_SIZE).map(move |z| Vec2::new(x as f32, z as f32)))
+        .flat_map(|center| {
+            let color = ((center / HALF_SIZE as f32 + 1.0) / 2.0).extend(0.6);
+            [
+                Vec2::new(-0.4, 0.3),
+                Vec2::new(0.4, 0.3),
+                Vec2::new(0.0, -0.4),
+            ]
+            .map(|corner| MyVertex {
+                position: [center.x + corner.x, 0.0, center.y + corner.y],
+                color: color.to_array(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let (vertices, vertices_upload) =
+        uploader.create_buffer_from_iter(BufferUsage::VERTEX_BUFFER, vertices);
+
+    commands.insert_resource(Stuff {
+        command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
+            context.device().clone(),
+            default(),
+        )),
+        descriptor_set_allocator: Arc::new(StandardDescriptorSetAllocator::new(
+            context.device().clone(),
+            default(),
+        )),
+        vertices,
+        vertices_upload,
+        vs: vs::load(context.device().clone()).unwrap(),
+        fs: fs::load(context.device().clone()).unwrap(),
+        pipelines: PerSwapchainFormat::default(),
+    });
+
+    // Two players, side by side.
+    for (i, speed) in [0.3f32, -0.2].into_iter().enumerate() {
+        commands.spawn((
+            VulkanoCamera::perspective(std::f32::consts::FRAC_PI_3, 0.1, 100.0)
+                .with_viewport(Rect::new(i as f32 * 0.5, 0.0, (i + 1) as f32 * 0.5, 1.0)),
+            Player {
+                angle: i as f32 * std::f32::consts::PI,
+                speed,
+            },
+            TransformBundle::default(),
+        ));
+    }
+
+    // A minimap looking straight down, drawn after the players.
+    commands.spawn((
+        VulkanoCamera::orthographic(2.0 * HALF_SIZE as f32 + 2.0, 0.1, 100.0)
+            .with_viewport(Rect::new(0.75, 0.0, 1.0, 0.3))
+            .with_order(1),
+        TransformBundle::from_transform(
+            Transform::from_xyz(0.0, 20.0, 0.0).looking_at(Vec3::ZERO, Vec3::NEG_Z),
+        ),
+    ));
+}
+
+fn move_players(time: Res<Time>, mut players: Query<(&mut Player, &mut Transform)>) {
+    for (mut player, mut transform) in &mut players {
+        player.angle += player.speed * time.delta_seconds();
+        let position = Vec3::new(player.angle.cos(), 0.0, player.angle.sin()) * 12.0;
+        *transform =
+            Transform::from_translation(position + Vec3::Y * 4.0).looking_at(Vec3::ZERO, Vec3::Y);
+    }
+}
+
+fn render(
+    mut renderers: VulkanoRenderers,
+    stuff: Option<ResMut<Stuff>>,
+    cameras: Query<(&VulkanoCamera, &CameraMatrices)>,
+    context: Res<BevyVulkanoContext>,
+    mut uploader: NonSendMut<GpuUploader>,
+) {
+    let Some(mut stuff) = stuff else {
+        return;
+    };
+    let stuff = &mut *stuff;
+    let Some(mut renderer) = renderers.get_primary_renderer() else {
+        return;
+    };
+
+    let FormatPipeline {
+        render_pass,
+        pipeline,
+    } = &*stuff
+        .pipelines
+        .get_or_insert_with(renderer.swapchain_format(), |format| {
+            create_pipeline(context.device(), &stuff.vs, &stuff.fs, format)
+        });
+
+    let Ok(previous_frame_end) = renderer.acquire(Some(Duration::from_millis(1000)), |_| {}) else {
+        return;
+    };
+    let previous_frame_end = uploader
+        .join(stuff.vertices_upload, previous_frame_end)
+        .unwrap();
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        stuff.command_buffer_allocator.clone(),
+        context.graphics_queue().queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+
+    builder
+        .begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: vec![Some([0.05, 0.05, 0.1, 1.0].into())],
+                ..RenderPassBeginInfo::framebuffer(renderer.framebuffer(render_pass, &[]).unwrap())
+            },
+            SubpassBeginInfo {
+                contents: SubpassContents::Inline,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .bind_pipeline_graphics(pipeline.clone())
+        .unwrap()
+        .bind_vertex_buffers(0, stuff.vertices.clone())
+        .unwrap();
+
+    let mut cameras = cameras.iter().collect::<Vec<_>>();
+    cameras.sort_by_key(|(camera, _)| camera.order);
+    for (camera, matrices) in cameras {
+        // Cameras drawn over others clear their region first.
+        if camera.order > 0 {
+            builder
+                .clear_attachments(
+                    [ClearAttachment::Color {
+                        color_attachment: 0,
+                        clear_value: [0.0, 0.0, 0.0, 1.0].into(),
+                    }]
+                    .into_iter()
+                    .collect(),
+                    [ClearRect {
+                        offset: matrices.scissor.offset,
+                        extent: matrices.scissor.extent,
+                        array_layers: 0..1,
+                    }]
+                    .into_iter()
+                    .collect(),
+                )
+                .unwrap();
+        }
+
+        let descriptor_set = DescriptorSet::new(
+            stuff.descriptor_set_allocator.clone(),
+            pipeline.layout().set_layouts()[0].clone(),
+            [WriteDescriptorSet::buffer(
+                0,
+                matrices.uniform_buffer.clone(),
+            )],
+            [],
+        )
+        .unwrap();
+        matrices.set_viewport_and_scissor(&mut builder).unwrap();
+        builder
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                pipeline.layout().clone(),
+                0,
+                descriptor_set,
+            )
+            .unwrap();
+
+        unsafe {
+            builder.draw(stuff.vertices.len() as u32, 1, 0, 0).unwrap();
+        }
+    }
+
+    builder.end_render_pass(Default::default()).unwrap();
+
+    let future = previous_frame_end
+        .then_execute(context.graphics_queue().clone(), builder.build().unwrap())
+        .unwrap()
+        .boxed();
+
+    renderer.present(future, false);
+}
+
+fn create_pipeline(
+    device: &Arc<Device>,
+    vs: &Arc<ShaderModule>,
+    fs: &Arc<ShaderModule>,
+    format: Format,
+) -> FormatPipeline {
+    let render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                format: format,
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {},
+        },
+    )
+    .unwrap();
+
+    let vs = vs.entry_point("main").unwrap();
+    let fs = fs.entry_point("main").unwrap();
+    let vertex_input_state = MyVertex::per_vertex().definition(&vs).unwrap();
+    let stages = [
+        PipelineShaderStageCreateInfo::new(vs),
+        PipelineShaderStageCreateInfo::new(fs),
+    ];
+    let layout = PipelineLayout::new(
+        device.clone(),
+        PipelineDescriptorSetLayoutCreateInfo::from_stages(&stages)
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+    )
+    .unwrap();
+    let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+    let pipeline = GraphicsPipeline::new(device.clone(), None, GraphicsPipelineCreateInfo {
+        stages: stages.into_iter().collect(),
+        vertex_input_state: Some(vertex_input_state),
+        input_assembly_state: Some(InputAssemblyState::default()),
+        viewport_state: Some(ViewportState::default()),
+        rasterization_state: Some(RasterizationState::default()),
+        multisample_state: Some(MultisampleState::default()),
+        color_blend_state: Some(ColorBlendState::with_attachment_states(
+            subpass.num_color_attachments(),
+            ColorBlendAttachmentState::default(),
+        )),
+        // Every camera has its own viewport and scissor.
+        dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+            .into_iter()
+            .collect(),
+        subpass: Some(subpass.into()),
+        ..GraphicsPipelineCreateInfo::layout(layout)
+    })
+    .unwrap();
+
+    FormatPipeline {
+        render_pass,
+        pipeline,
+    }
+}
@@ -0,0 +1,196 @@
+use std::sync::Arc;
+
+use bevy::{
+    a11y::AccessibilityPlugin,
+    input::mouse::MouseWheel,
+    prelude::*,
+    window::WindowMode,
+    winit::{WakeUp, WinitPlugin},
+};
+use bevy_vulkano::{
+    BevyVulkanoContext, CameraProjection, GpuUploader, SpritePlugin, SpriteTextures,
+    TextureAtlasLayout, VulkanoCamera, VulkanoCamera2d, VulkanoPlugin, VulkanoSprite,
+    VulkanoTextureAtlas,
+};
+use vulkano::{
+    format::Format,
+    image::{sampler::Filter, view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage},
+    memory::allocator::AllocationCreateInfo,
+};
+
+/*
+* This example draws a grid of spinning sprites, animated through the tiles of a texture atlas, over
+* a plain colored background sprite. Scroll to zoom, and drag with the left mouse button to pan.
+ */
+
+const TILE_SIZE: u32 = 16;
+const TILES: u32 = 4;
+const COLORS: [[u8; 3]; 4] = [[230, 80, 80], [240, 200, 60], [80, 200, 120], [
+    80, 140, 240,
+]];
+
+fn main() {
+    App::new()
+        .add_plugins((
+            bevy::log::LogPlugin::default(),
+            bevy::time::TimePlugin,
+            bevy::input::InputPlugin,
+            AccessibilityPlugin,
+            WindowPlugin {
+                primary_window: Some(Window {
+                    resolution: (1024.0, 768.0).into(),
+                    title: "Bevy Vulkano Sprites".to_string(),
+                    present_mode: bevy::window::PresentMode::Fifo,
+                    resizable: true,
+                    mode: WindowMode::Windowed,
+                    ..default()
+                }),
+                ..default()
+            },
+            WinitPlugin::<WakeUp>::default(),
+            VulkanoPlugin::default(),
+            SpritePlugin,
+        ))
+        .add_systems(Startup, setup)
+        .add_systems(Update, (animate_sprites, move_camera))
+        .run();
+}
+
+#[derive(Component)]
+struct Spinning {
+    speed: f32,
+}
+
+fn setup(
+    context: Res<BevyVulkanoContext>,
+    mut uploader: NonSendMut<GpuUploader>,
+    mut textures: ResMut<SpriteTextures>,
+    mut commands: Commands,
+) {
+    // An atlas of tiles side by side, each a ring of a different color.
+    let image = Image::new(
+        context.memory_allocator().clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::R8G8B8A8_SRGB,
+            extent: [TILE_SIZE * TILES, TILE_SIZE, 1],
+            usage: ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+    let pixels = (0..TILE_SIZE)
+        .flat_map(|y| {
+            (0..TILE_SIZE * TILES).map(move |x| {
+                let [r, g, b] = COLORS[(x / TILE_SIZE) as usize];
+                let center = (TILE_SIZE as f32 - 1.0) / 2.0;
+                let distance =
+                    Vec2::new((x % TILE_SIZE) as f32 - center, y as f32 - center).length();
+                if (4.0..7.5).contains(&distance) {
+                    [r, g, b, 255]
+                } else {
+                    [0, 0, 0, 0]
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let upload = uploader.upload_image(pixels, image.clone());
+    let texture = textures.add(
+        ImageView::new_default(image).unwrap(),
+        Filter::Nearest,
+        Some(upload),
+    );
+    let layout = Arc::new(TextureAtlasLayout::from_grid(
+        UVec2::splat(TILE_SIZE),
+        TILES,
+        1,
+        None,
+        None,
+    ));
+
+    let (camera, mut camera_2d) = VulkanoCamera2d::bundle(1.0);
+    camera_2d.clear_color = Some([0.02, 0.02, 0.05, 1.0]);
+    commands.spawn((camera, camera_2d, TransformBundle::default()));
+
+    commands.spawn((
+        VulkanoSprite {
+            z_order: -1.0,
+            ..VulkanoSprite::from_color([0.1, 0.1, 0.2, 1.0], Vec2::new(700.0, 500.0))
+        },
+        TransformBundle::default(),
+    ));
+
+    for x in -10..=10 {
+        for y in -7..=7 {
+            commands.spawn((
+                VulkanoSprite {
+                    custom_size: Some(Vec2::splat(TILE_SIZE as f32 * 2.0)),
+                    ..VulkanoSprite::new(texture)
+                },
+                VulkanoTextureAtlas {
+                    layout: layout.clone(),
+                    index: (x + y).rem_euclid(TILES as i32) as usize,
+                },
+                Spinning {
+                    speed: (x - y) as f32 * 0.2,
+                },
+                TransformBundle::from_transform(Transform::from_xyz(
+                    x as f32 * 40.0,
+                    y as f32 * 40.0,
+                    0.0,
+                )),
+            ));
+        }
+    }
+}
+
+fn animate_sprites(
+    time: Res<Time>,
+    mut frame_timer: Local<f32>,
+    mut sprites: Query<(&mut Transform, &mut VulkanoTextureAtlas, &Spinning)>,
+) {
+    *frame_timer += time.delta_seconds();
+    let next_frame = *frame_timer > 0.25;
+    if next_frame {
+        *frame_timer = 0.0;
+    }
+
+    for (mut transform, mut atlas, spinning) in &mut sprites {
+        transform.rotate_z(spinning.speed * time.delta_seconds());
+        if next_frame {
+            atlas.index = (atlas.index + 1) % atlas.layout.len();
+        }
+    }
+}
+
+fn move_camera(
+    mut cursor_moved: EventReader<CursorMoved>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    buttons: Res<ButtonInput<MouseButton>>,
+    mut last_cursor: Local<Option<Vec2>>,
+    mut camera: Query<(&mut VulkanoCamera, &mut Transform), With<VulkanoCamera2d>>,
+) {
+    let Ok((mut camera, mut transform)) = camera.get_single_mut() else {
+        return;
+    };
+    let CameraProjection::Pixels {
+        scale, ..
+    } = &mut camera.projection
+    else {
+        return;
+    };
+
+    for event in mouse_wheel.read() {
+        *scale = (*scale * (1.0 - event.y * 0.1)).clamp(0.1, 10.0);
+    }
+
+    for event in cursor_moved.read() {
+        if let (Some(last), true) = (*last_cursor, buttons.pressed(MouseButton::Left)) {
+            // The cursor's y points down, the world's up.
+            let delta = (event.position - last) * Vec2::new(-1.0, 1.0) * *scale;
+            transform.translation += delta.extend(0.0);
+        }
+        *last_cursor = Some(event.position);
+    }
+}
@@ -5,15 +5,15 @@ use bevy::{
     prelude::*,
     winit::{WakeUp, WinitPlugin},
 };
-use bevy_vulkano::{BevyVulkanoContext, VulkanoPlugin, VulkanoRenderers};
+use bevy_vulkano::{
+    uploader::UploadHandle, BevyVulkanoContext, GpuUploader, VulkanoPlugin, VulkanoRenderers,
+};
 use vulkano::{
-    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    buffer::{BufferContents, BufferUsage, Subbuffer},
     command_buffer::{
         allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
         RenderPassBeginInfo, SubpassBeginInfo, SubpassContents,
     },
-    image::view::ImageView,
-    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
     pipeline::{
         graphics::{
             color_blend::{ColorBlendAttachmentState, ColorBlendState},
@@ -27,7 +27,7 @@ use vulkano::{
         layout::PipelineDescriptorSetLayoutCreateInfo,
         DynamicState, GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo,
     },
-    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    render_pass::{RenderPass, Subpass},
     sync::GpuFuture,
 };
 
@@ -37,7 +37,7 @@ fn main() {
             AccessibilityPlugin,
             WindowPlugin::default(),
             WinitPlugin::<WakeUp>::default(),
-            VulkanoPlugin,
+            VulkanoPlugin::default(),
         ))
         .add_systems(Startup, setup)
         .add_systems(Update, render)
@@ -55,14 +55,15 @@ struct MyVertex {
 struct Stuff {
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
     vertices: Subbuffer<[MyVertex]>,
+    vertices_upload: UploadHandle,
     render_pass: Arc<RenderPass>,
-    framebuffers: Vec<Arc<Framebuffer>>,
     pipeline: Arc<GraphicsPipeline>,
 }
 
 fn setup(
     mut renderers: VulkanoRenderers,
     context: Res<BevyVulkanoContext>,
+    mut uploader: NonSendMut<GpuUploader>,
     mut commands: Commands,
 ) {
     let renderer = renderers.get_renderer_single().unwrap();
@@ -108,20 +109,9 @@ fn setup(
             position: [0.25, -0.1],
         },
     ];
-    let vertex_buffer = Buffer::from_iter(
-        context.memory_allocator().clone(),
-        BufferCreateInfo {
-            usage: BufferUsage::VERTEX_BUFFER,
-            ..Default::default()
-        },
-        AllocationCreateInfo {
-            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
-                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
-            ..Default::default()
-        },
-        vertices,
-    )
-    .unwrap();
+    // The vertices are copied into device local memory through a staging buffer.
+    let (vertex_buffer, vertices_upload) =
+        uploader.create_buffer_from_iter(BufferUsage::VERTEX_BUFFER, vertices);
 
     let render_pass = vulkano::single_pass_renderpass!(
         context.device().clone(),
@@ -140,8 +130,6 @@ fn setup(
     )
     .unwrap();
 
-    let framebuffers = on_swapchain_recreation(renderer.swapchain_image_views(), &render_pass);
-
     let pipeline = {
         // First, we load the shaders that the pipeline will use: the vertex shader and the
         // fragment shader.
@@ -232,27 +220,30 @@ fn setup(
             default(),
         )),
         vertices: vertex_buffer,
+        vertices_upload,
         render_pass,
-        framebuffers,
         pipeline,
     });
 }
 
 fn render(
     mut renderers: VulkanoRenderers,
-    stuff: Option<ResMut<Stuff>>,
+    stuff: Option<Res<Stuff>>,
     context: Res<BevyVulkanoContext>,
+    mut uploader: NonSendMut<GpuUploader>,
 ) {
-    let Some(mut stuff) = stuff else {
+    let Some(stuff) = stuff else {
         return;
     };
 
     let mut renderer = renderers.get_renderer_single().unwrap();
 
-    let previous_frame_end = renderer
-        .acquire(Some(Duration::from_millis(1000)), |swapchain_images| {
-            stuff.framebuffers = on_swapchain_recreation(swapchain_images, &stuff.render_pass);
-        })
+    let Ok(previous_frame_end) = renderer.acquire(Some(Duration::from_millis(1000)), |_| {}) else {
+        return;
+    };
+    // Wait for the vertices to arrive. Once the upload is complete, this does nothing.
+    let previous_frame_end = uploader
+        .join(stuff.vertices_upload, previous_frame_end)
         .unwrap();
 
     let mut builder = AutoCommandBufferBuilder::primary(
@@ -274,8 +265,10 @@ fn render(
                 // with clear values, any others should use `None` as the clear value.
                 clear_values: vec![Some([0.0, 0.0, 1.0, 1.0].into())],
 
+                // The renderer makes a framebuffer for each swapchain image, and remakes them
+                // when the swapchain is recreated.
                 ..RenderPassBeginInfo::framebuffer(
-                    stuff.framebuffers[renderer.image_index() as usize].clone(),
+                    renderer.framebuffer(&stuff.render_pass, &[]).unwrap(),
                 )
             },
             SubpassBeginInfo {
@@ -337,20 +330,3 @@ fn render(
     // the triangle.
     renderer.present(future, false);
 }
-
-/// This function is called whenever a swapchain is created.
-fn on_swapchain_recreation(
-    swapchain_images: &[Arc<ImageView>],
-    render_pass: &Arc<RenderPass>,
-) -> Vec<Arc<Framebuffer>> {
-    swapchain_images
-        .iter()
-        .map(|swapchain_image| {
-            Framebuffer::new(render_pass.clone(), FramebufferCreateInfo {
-                attachments: vec![swapchain_image.clone()],
-                ..Default::default()
-            })
-            .unwrap()
-        })
-        .collect::<Vec<_>>()
-}
@@ -0,0 +1,481 @@
+use std::sync::Arc;
+
+use bevy::{prelude::*, transform::TransformSystem, window::PrimaryWindow};
+use vulkano::{
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        BufferContents, BufferUsage, Subbuffer,
+    },
+    command_buffer::AutoCommandBufferBuilder,
+    image::view::ImageView,
+    memory::allocator::MemoryTypeFilter,
+    pipeline::graphics::viewport::{Scissor, Viewport},
+    ValidationError,
+};
+
+use crate::{
+    vulkano_renderers::{reconcile_renderers, update_offscreen_targets},
+    BevyVulkanoContext, VulkanoRenderers,
+};
+
+/// Computes the matrices of every [`VulkanoCamera`] in [`PostUpdate`], once transforms are
+/// propagated, into its [`CameraMatrices`]. Add it after [`crate::VulkanoPlugin`]. Adds bevy's
+/// `TransformPlugin` if it isn't added yet.
+///
+/// Render systems in [`PostUpdate`] should run after [`update_cameras`] to see this frame's
+/// matrices. Systems in [`Update`] see last frame's.
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<TransformPlugin>() {
+            app.add_plugins(TransformPlugin);
+        }
+        app.init_non_send_resource::<CameraUniformAllocator>()
+            .add_systems(
+                PostUpdate,
+                update_cameras
+                    .after(TransformSystem::TransformPropagate)
+                    .after(reconcile_renderers)
+                    .after(update_offscreen_targets),
+            );
+    }
+}
+
+/// How a camera projects the world onto its target. All look down `-z`, with `+y` up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraProjection {
+    Perspective {
+        /// The vertical field of view, in radians.
+        fov_y: f32,
+        near: f32,
+        far: f32,
+    },
+    Orthographic {
+        /// How many world units fit vertically, the width follows the aspect ratio.
+        height: f32,
+        near: f32,
+        far: f32,
+    },
+    /// Orthographic, sized to the viewport so that world units are a fixed number of logical
+    /// pixels, as used for 2D.
+    Pixels {
+        /// World units per logical pixel. Above one zooms out.
+        scale: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+impl Default for CameraProjection {
+    fn default() -> Self {
+        CameraProjection::Perspective {
+            fov_y: std::f32::consts::FRAC_PI_4,
+            near: 0.1,
+            far: 1000.0,
+        }
+    }
+}
+
+impl CameraProjection {
+    /// The projection matrix for a viewport of `logical_size`, into Vulkan's clip space: `+y`
+    /// down and depth from 0 at `near` to 1 at `far`.
+    pub fn matrix(&self, logical_size: Vec2) -> Mat4 {
+        let aspect_ratio = logical_size.x / logical_size.y;
+        let projection = match *self {
+            CameraProjection::Perspective {
+                fov_y,
+                near,
+                far,
+            } => Mat4::perspective_rh(fov_y, aspect_ratio, near, far),
+            CameraProjection::Orthographic {
+                height,
+                near,
+                far,
+            } => {
+                let half_height = height / 2.0;
+                let half_width = half_height * aspect_ratio;
+                Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                )
+            }
+            CameraProjection::Pixels {
+                scale,
+                near,
+                far,
+            } => {
+                let half_size = logical_size * scale / 2.0;
+                Mat4::orthographic_rh(
+                    -half_size.x,
+                    half_size.x,
+                    -half_size.y,
+                    half_size.y,
+                    near,
+                    far,
+                )
+            }
+        };
+
+        // glam's clip space has `+y` up.
+        Mat4::from_scale(Vec3::new(1.0, -1.0, 1.0)) * projection
+    }
+}
+
+/// What a camera renders into.
+#[derive(Clone, Debug, Default)]
+pub enum CameraTarget {
+    /// The window marked with [`PrimaryWindow`].
+    #[default]
+    PrimaryWindow,
+    /// A window, by entity.
+    Window(Entity),
+    /// An additional image view of a window, by key, see
+    /// [`crate::renderer::VulkanoWindowRenderer::add_additional_image_view`].
+    AdditionalImageView { window: Entity, key: usize },
+    /// The [`crate::OffscreenTarget`] of an entity.
+    Offscreen(Entity),
+    /// An image, its size sets the aspect ratio.
+    Image(Arc<ImageView>),
+}
+
+/// A camera, placed by its entity's [`GlobalTransform`]. Its matrices are in [`CameraMatrices`],
+/// added to the entity while it's active and its target exists, and removed otherwise.
+#[derive(Component, Clone, Debug)]
+pub struct VulkanoCamera {
+    pub projection: CameraProjection,
+    pub target: CameraTarget,
+    /// The region of the target rendered into, from `(0, 0)` at the top left to `(1, 1)` at the
+    /// bottom right. The aspect ratio is the region's. Give cameras of the same target different
+    /// regions for split-screen, or a small region over another camera for picture-in-picture.
+    pub viewport: Rect,
+    /// Cameras of the same target render from the lowest order to the highest.
+    pub order: isize,
+    /// Inactive cameras have no [`CameraMatrices`], so they aren't rendered.
+    pub is_active: bool,
+}
+
+impl Default for VulkanoCamera {
+    fn default() -> Self {
+        Self {
+            projection: CameraProjection::default(),
+            target: CameraTarget::default(),
+            viewport: Rect::new(0.0, 0.0, 1.0, 1.0),
+            order: 0,
+            is_active: true,
+        }
+    }
+}
+
+impl VulkanoCamera {
+    pub fn perspective(fov_y: f32, near: f32, far: f32) -> Self {
+        Self {
+            projection: CameraProjection::Perspective {
+                fov_y,
+                near,
+                far,
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn orthographic(height: f32, near: f32, far: f32) -> Self {
+        Self {
+            projection: CameraProjection::Orthographic {
+                height,
+                near,
+                far,
+            },
+            ..Default::default()
+        }
+    }
+
+    /// A camera in logical pixels, see [`CameraProjection::Pixels`].
+    pub fn pixels(scale: f32, near: f32, far: f32) -> Self {
+        Self {
+            projection: CameraProjection::Pixels {
+                scale,
+                near,
+                far,
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn with_target(mut self, target: CameraTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    pub fn with_viewport(mut self, viewport: Rect) -> Self {
+        self.viewport = viewport;
+        self
+    }
+
+    pub fn with_order(mut self, order: isize) -> Self {
+        self.order = order;
+        self
+    }
+}
+
+/// The camera data in a uniform buffer. In GLSL:
+///
+/// ```glsl
+/// layout(set = 0, binding = 0) uniform Camera {
+///     mat4 view;
+///     mat4 projection;
+///     mat4 view_proj;
+///     mat4 inverse_view;
+///     vec4 position;
+/// } camera;
+/// ```
+#[repr(C)]
+#[derive(BufferContents, Clone, Copy, Debug)]
+pub struct CameraUniform {
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+    pub view_proj: [[f32; 4]; 4],
+    pub inverse_view: [[f32; 4]; 4],
+    /// The camera's position in world space, `w` is 1.
+    pub position: [f32; 4],
+}
+
+/// The matrices of a [`VulkanoCamera`] this frame, kept up to date by [`update_cameras`].
+#[derive(Component, Clone, Debug)]
+pub struct CameraMatrices {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_proj: Mat4,
+    pub position: Vec3,
+    pub aspect_ratio: f32,
+    /// The window whose swapchain image or additional image view is rendered into, `None` when
+    /// rendering into an offscreen target or image.
+    pub window: Option<Entity>,
+    /// The size of the target in pixels.
+    pub target_size: [u32; 2],
+    /// [`VulkanoCamera::viewport`] in pixels of the target.
+    pub viewport: Viewport,
+    /// Clips rendering to the viewport, so cameras sharing a target don't draw over each other.
+    pub scissor: Scissor,
+    /// The matrices in a uniform buffer, made anew every frame so frames in flight keep theirs.
+    pub uniform_buffer: Subbuffer<CameraUniform>,
+}
+
+impl CameraMatrices {
+    /// Sets the camera's viewport and scissor, for pipelines with the `Viewport` and `Scissor`
+    /// dynamic states.
+    pub fn set_viewport_and_scissor<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+    ) -> Result<(), Box<ValidationError>> {
+        builder
+            .set_viewport(0, [self.viewport.clone()].into_iter().collect())?
+            .set_scissor(0, [self.scissor].into_iter().collect())?;
+
+        Ok(())
+    }
+}
+
+/// The viewport and scissor of `rect`, normalized from `(0, 0)` at the top left to `(1, 1)` at
+/// the bottom right, in a target of `target_size` pixels. `None` if it covers no pixels.
+pub fn viewport_and_scissor(rect: Rect, target_size: [u32; 2]) -> Option<(Viewport, Scissor)> {
+    let target_size = UVec2::from(target_size);
+    // Rounding both corners keeps neighbouring regions from overlapping or leaving gaps.
+    let to_pixels = |point: Vec2| {
+        (point.clamp(Vec2::ZERO, Vec2::ONE) * target_size.as_vec2())
+            .round()
+            .as_uvec2()
+    };
+    let min = to_pixels(rect.min);
+    let max = to_pixels(rect.max);
+    if max.x <= min.x || max.y <= min.y {
+        return None;
+    }
+    let extent = max - min;
+
+    Some((
+        Viewport {
+            offset: min.as_vec2().to_array(),
+            extent: extent.as_vec2().to_array(),
+            depth_range: 0.0..=1.0,
+        },
+        Scissor {
+            offset: min.to_array(),
+            extent: extent.to_array(),
+        },
+    ))
+}
+
+/// Allocates the per-frame camera uniform buffers.
+pub struct CameraUniformAllocator(SubbufferAllocator);
+
+impl FromWorld for CameraUniformAllocator {
+    fn from_world(world: &mut World) -> Self {
+        let context = world.resource::<BevyVulkanoContext>();
+
+        Self(SubbufferAllocator::new(
+            context.memory_allocator().clone(),
+            SubbufferAllocatorCreateInfo {
+                buffer_usage: BufferUsage::UNIFORM_BUFFER,
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+        ))
+    }
+}
+
+/// Computes the matrices of every active camera from its transform and target, and uploads them.
+/// Removes the matrices of inactive cameras, and of cameras without a target to render into.
+pub fn update_cameras(
+    mut commands: Commands,
+    allocator: NonSend<CameraUniformAllocator>,
+    mut cameras: Query<(
+        Entity,
+        &VulkanoCamera,
+        &GlobalTransform,
+        Option<&mut CameraMatrices>,
+    )>,
+    primary_window: Query<Entity, With<PrimaryWindow>>,
+    mut renderers: VulkanoRenderers,
+) {
+    for (entity, camera, transform, matrices) in &mut cameras {
+        let new_matrices = 'matrices: {
+            if !camera.is_active {
+                break 'matrices None;
+            }
+
+            let window = match &camera.target {
+                CameraTarget::PrimaryWindow => primary_window.get_single().ok(),
+                CameraTarget::Window(window) => Some(*window),
+                CameraTarget::AdditionalImageView {
+                    window, ..
+                } => Some(*window),
+                CameraTarget::Offscreen(_) | CameraTarget::Image(_) => None,
+            };
+            let (target_size, scale_factor) = match (&camera.target, window) {
+                (CameraTarget::Offscreen(target), _) => {
+                    match renderers.get_offscreen_target(*target) {
+                        Some(offscreen) => (offscreen.extent(), 1.0),
+                        None => break 'matrices None,
+                    }
+                }
+                (CameraTarget::Image(image), _) => {
+                    (image.image().extent()[0..2].try_into().unwrap(), 1.0)
+                }
+                (_, Some(window)) => {
+                    let Some(renderer) = renderers.get_renderer(window) else {
+                        break 'matrices None;
+                    };
+                    if let CameraTarget::AdditionalImageView {
+                        key, ..
+                    } = &camera.target
+                    {
+                        if !renderer.additional_image_views().contains_key(key) {
+                            break 'matrices None;
+                        }
+                    }
+                    // What the swapchain images and additional image views are, which can differ
+                    // from the window's physical size when the surface clamps the extent. After a
+                    // resize, this is the old size until the next acquire recreates them.
+                    (renderer.swapchain_image_size(), renderer.scale_factor())
+                }
+                (_, None) => break 'matrices None,
+            };
+            // Minimized windows and empty viewports have nothing to render into.
+            let Some((viewport, scissor)) = viewport_and_scissor(camera.viewport, target_size)
+            else {
+                break 'matrices None;
+            };
+            let logical_size = Vec2::from(viewport.extent) / scale_factor;
+
+            let inverse_view = transform.compute_matrix();
+            let view = inverse_view.inverse();
+            let projection = camera.projection.matrix(logical_size);
+            let view_proj = projection * view;
+            let position = transform.translation();
+
+            let uniform_buffer = allocator.0.allocate_sized().unwrap();
+            *uniform_buffer.write().unwrap() = CameraUniform {
+                view: view.to_cols_array_2d(),
+                projection: projection.to_cols_array_2d(),
+                view_proj: view_proj.to_cols_array_2d(),
+                inverse_view: inverse_view.to_cols_array_2d(),
+                position: position.extend(1.0).to_array(),
+            };
+
+            Some(CameraMatrices {
+                view,
+                projection,
+                view_proj,
+                position,
+                aspect_ratio: logical_size.x / logical_size.y,
+                window,
+                target_size,
+                viewport,
+                scissor,
+                uniform_buffer,
+            })
+        };
+
+        match (matrices, new_matrices) {
+            (Some(mut matrices), Some(new_matrices)) => *matrices = new_matrices,
+            (None, Some(new_matrices)) => {
+                commands.entity(entity).insert(new_matrices);
+            }
+            (Some(_), None) => {
+                commands.entity(entity).remove::<CameraMatrices>();
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_screen_regions_do_not_overlap() {
+        // An odd size, so the middle falls between two pixels.
+        let target_size = [1001, 501];
+        let (_, left) = viewport_and_scissor(Rect::new(0.0, 0.0, 0.5, 1.0), target_size).unwrap();
+        let (_, right) = viewport_and_scissor(Rect::new(0.5, 0.0, 1.0, 1.0), target_size).unwrap();
+        assert_eq!(left.offset[0] + left.extent[0], right.offset[0]);
+        assert_eq!(right.offset[0] + right.extent[0], target_size[0]);
+
+        let (_, top) = viewport_and_scissor(Rect::new(0.0, 0.0, 1.0, 0.5), target_size).unwrap();
+        let (_, bottom) = viewport_and_scissor(Rect::new(0.0, 0.5, 1.0, 1.0), target_size).unwrap();
+        assert_eq!(top.offset[1] + top.extent[1], bottom.offset[1]);
+        assert_eq!(bottom.offset[1] + bottom.extent[1], target_size[1]);
+    }
+
+    #[test]
+    fn viewport_matches_scissor() {
+        let (viewport, scissor) =
+            viewport_and_scissor(Rect::new(0.25, 0.5, 0.75, 1.0), [800, 600]).unwrap();
+        assert_eq!(scissor.offset, [200, 300]);
+        assert_eq!(scissor.extent, [400, 300]);
+        assert_eq!(viewport.offset, [200.0, 300.0]);
+        assert_eq!(viewport.extent, [400.0, 300.0]);
+    }
+
+    #[test]
+    fn zero_size_target_has_no_viewport() {
+        let full = Rect::new(0.0, 0.0, 1.0, 1.0);
+        assert!(viewport_and_scissor(full, [0, 0]).is_none());
+        assert!(viewport_and_scissor(full, [800, 0]).is_none());
+        assert!(viewport_and_scissor(full, [0, 600]).is_none());
+    }
+
+    #[test]
+    fn empty_region_has_no_viewport() {
+        assert!(viewport_and_scissor(Rect::new(0.5, 0.5, 0.5, 1.0), [800, 600]).is_none());
+        // Entirely outside the target.
+        assert!(viewport_and_scissor(Rect::new(1.0, 0.0, 2.0, 1.0), [800, 600]).is_none());
+    }
+}
@@ -0,0 +1,893 @@
+use std::{ptr, sync::Arc};
+
+use bevy::prelude::*;
+use vulkano::{
+    ash::vk,
+    buffer::{Buffer, BufferContents, BufferCreateInfo, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        PrimaryAutoCommandBuffer,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
+        WriteDescriptorSetElements,
+    },
+    device::{Device, DeviceOwned, Queue},
+    image::{view::ImageView, ImageCreateInfo},
+    pipeline::{
+        compute::ComputePipelineCreateInfo, layout::PipelineDescriptorSetLayoutCreateInfo,
+        ComputePipeline, Pipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+    },
+    shader::{spirv::ExecutionModel, EntryPoint},
+    sync::{self, future::FenceSignalFuture, GpuFuture, Sharing},
+    Validated, ValidationError, VulkanError, VulkanObject,
+};
+
+use crate::BevyVulkanoContext;
+
+/// The future of a submission of compute tasks. It can be cloned and joined into as many frames as
+/// you like.
+pub type ComputeFuture = Arc<FenceSignalFuture<Box<dyn GpuFuture>>>;
+
+type PushConstantsFn =
+    Box<dyn FnOnce(&mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>, &Arc<PipelineLayout>)>;
+
+/// A compute pipeline and the workgroup size of its shader, read from the shader's `local_size`.
+/// Make it once, and tasks from it with [`ComputeTask::new`].
+#[derive(Clone, Debug)]
+pub struct ComputeKernel {
+    pipeline: Arc<ComputePipeline>,
+    local_size: [u32; 3],
+}
+
+impl ComputeKernel {
+    /// Makes a pipeline running `entry_point`, with a layout made from its bindings. Fails if
+    /// it isn't a compute shader, or the device can't make the layout or pipeline.
+    pub fn new(entry_point: EntryPoint) -> Result<Self, Validated<VulkanError>> {
+        let local_size = local_size(&entry_point)?;
+        let device = entry_point.module().device().clone();
+        let stage = PipelineShaderStageCreateInfo::new(entry_point);
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages([&stage])
+                .into_pipeline_layout_create_info(device.clone())
+                .map_err(|e| e.error)?,
+        )?;
+        let pipeline = ComputePipeline::new(
+            device,
+            None,
+            ComputePipelineCreateInfo::stage_layout(stage, layout),
+        )?;
+
+        Ok(Self {
+            pipeline,
+            local_size,
+        })
+    }
+
+    /// Pairs `pipeline` with the workgroup size of `entry_point`, the shader it was made from.
+    pub fn from_pipeline(
+        pipeline: Arc<ComputePipeline>,
+        entry_point: &EntryPoint,
+    ) -> Result<Self, Box<ValidationError>> {
+        Ok(Self {
+            pipeline,
+            local_size: local_size(entry_point)?,
+        })
+    }
+
+    #[inline]
+    pub fn pipeline(&self) -> &Arc<ComputePipeline> {
+        &self.pipeline
+    }
+
+    /// The `local_size_x`, `local_size_y` and `local_size_z` of the shader.
+    #[inline]
+    pub fn local_size(&self) -> [u32; 3] {
+        self.local_size
+    }
+}
+
+/// The workgroup size of a compute shader, none of it zero.
+fn local_size(entry_point: &EntryPoint) -> Result<[u32; 3], Box<ValidationError>> {
+    let info = entry_point.info();
+    if info.execution_model != ExecutionModel::GLCompute {
+        return Err(Box::new(ValidationError {
+            context: "entry_point".into(),
+            problem: "is not a compute shader".into(),
+            ..Default::default()
+        }));
+    }
+
+    match info.local_size.into() {
+        Some(local_size) if !local_size.contains(&0) => Ok(local_size),
+        _ => Err(Box::new(ValidationError {
+            context: "entry_point".into(),
+            problem: "has no workgroup size".into(),
+            ..Default::default()
+        })),
+    }
+}
+
+/// A compute pipeline, its bindings and how many workgroups to dispatch.
+/// Queue it with [`ComputeTasks::enqueue`].
+pub struct ComputeTask {
+    pipeline: Arc<ComputePipeline>,
+    descriptor_sets: Vec<(u32, Vec<WriteDescriptorSet>)>,
+    push_constants: Option<PushConstantsFn>,
+    group_counts: [u32; 3],
+}
+
+impl ComputeTask {
+    /// Creates a task that runs `kernel` at least once for each of `invocations`. The workgroup
+    /// count is derived from the kernel's workgroup size.
+    pub fn new(kernel: &ComputeKernel, invocations: [u32; 3]) -> Self {
+        let local_size = kernel.local_size;
+        Self {
+            pipeline: kernel.pipeline.clone(),
+            descriptor_sets: Vec::new(),
+            push_constants: None,
+            group_counts: [
+                invocations[0].div_ceil(local_size[0]),
+                invocations[1].div_ceil(local_size[1]),
+                invocations[2].div_ceil(local_size[2]),
+            ],
+        }
+    }
+
+    /// Binds a descriptor set made of `writes` at index `set` of the pipeline layout.
+    pub fn with_descriptor_set(
+        mut self,
+        set: u32,
+        writes: impl IntoIterator<Item = WriteDescriptorSet>,
+    ) -> Self {
+        self.descriptor_sets
+            .push((set, writes.into_iter().collect()));
+        self
+    }
+
+    /// Pushes `push_constants` at offset 0 before dispatching.
+    pub fn with_push_constants<Pc: BufferContents>(mut self, push_constants: Pc) -> Self {
+        self.push_constants = Some(Box::new(move |builder, layout| {
+            builder
+                .push_constants(layout.clone(), 0, push_constants)
+                .unwrap();
+        }));
+        self
+    }
+
+    /// The number of workgroups that will be dispatched.
+    #[inline]
+    pub fn group_counts(&self) -> [u32; 3] {
+        self.group_counts
+    }
+}
+
+/// Compute tasks queued from any system. Once per frame in [`PreUpdate`] they are recorded into one
+/// command buffer, in the order they were queued, and submitted on the compute queue.
+/// To run them concurrently with your rendering instead, call [`ComputeTasks::submit_in_frame`]
+/// from your render system.
+///
+/// Tasks queued in [`Update`] or later are only submitted in the next frame's [`PreUpdate`], so
+/// their results are a frame late. Call [`ComputeTasks::submit`] yourself to submit them sooner.
+///
+/// Barriers between tasks that use the same resources are inserted by vulkano for you. When the
+/// compute queue is of a different family than the graphics queue, buffers with exclusive sharing
+/// that tasks bind are moved to the compute family before the tasks run, and back to the graphics
+/// family after, see [`ComputeTasks::enqueue`].
+pub struct ComputeTasks {
+    graphics_queue: Arc<Queue>,
+    compute_queue: Arc<Queue>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    queued: Vec<ComputeTask>,
+    /// The buffers with exclusive sharing bound by the queued tasks.
+    exclusive_buffers: Vec<Arc<Buffer>>,
+    /// `None` when both queues are of the same family.
+    ownership_transfers: Option<OwnershipTransfers>,
+    last_submission: Option<ComputeFuture>,
+}
+
+impl FromWorld for ComputeTasks {
+    fn from_world(world: &mut World) -> Self {
+        let context = world.resource::<BevyVulkanoContext>();
+        let graphics_queue = context.graphics_queue().clone();
+        let compute_queue = context.compute_queue().clone();
+        let ownership_transfers = (graphics_queue.queue_family_index()
+            != compute_queue.queue_family_index())
+        .then(|| OwnershipTransfers::new(graphics_queue.clone(), compute_queue.clone()))
+        .and_then(|transfers| {
+            transfers
+                .map_err(|e| {
+                    error!("Failed to set up queue family ownership transfers for compute: {e}")
+                })
+                .ok()
+        });
+
+        Self {
+            graphics_queue,
+            compute_queue,
+            command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
+                context.device().clone(),
+                Default::default(),
+            )),
+            descriptor_set_allocator: Arc::new(StandardDescriptorSetAllocator::new(
+                context.device().clone(),
+                Default::default(),
+            )),
+            queued: Vec::new(),
+            exclusive_buffers: Vec::new(),
+            ownership_transfers,
+            last_submission: None,
+        }
+    }
+}
+
+impl ComputeTasks {
+    /// Queues a task to be submitted with the rest. Fails if a descriptor set is bound at an index
+    /// the pipeline layout doesn't have.
+    ///
+    /// When the compute and graphics queue families differ, the buffers the task binds with
+    /// exclusive sharing are released by the graphics family and acquired by the compute family
+    /// before the tasks run, then given back after. Images are laid out by vulkano, so their
+    /// ownership can't be transferred: it fails if the task binds an image that isn't shared
+    /// between the families, see [`ComputeTasks::shared_image_create_info`].
+    pub fn enqueue(&mut self, task: ComputeTask) -> Result<(), Box<ValidationError>> {
+        let mut exclusive_buffers = Vec::new();
+        for (_, writes) in &task.descriptor_sets {
+            for write in writes {
+                self.check_sharing(write, &mut exclusive_buffers)?;
+            }
+        }
+
+        let set_count = task.pipeline.layout().set_layouts().len();
+        if let Some((set, _)) = task
+            .descriptor_sets
+            .iter()
+            .find(|(set, _)| *set as usize >= set_count)
+        {
+            return Err(Box::new(ValidationError {
+                context: "task.descriptor_sets".into(),
+                problem: format!(
+                    "binds set {set}, but the pipeline layout only has {set_count} sets"
+                )
+                .into(),
+                ..Default::default()
+            }));
+        }
+
+        for buffer in exclusive_buffers {
+            if !self
+                .exclusive_buffers
+                .iter()
+                .any(|queued| Arc::ptr_eq(queued, &buffer))
+            {
+                self.exclusive_buffers.push(buffer);
+            }
+        }
+        self.queued.push(task);
+        Ok(())
+    }
+
+    /// The future of the last submission, if there was one.
+    #[inline]
+    pub fn future(&self) -> Option<ComputeFuture> {
+        self.last_submission.clone()
+    }
+
+    /// Joins `future` with the last submission, so that whatever comes after can safely use the
+    /// results of the tasks.
+    pub fn join(&self, future: Box<dyn GpuFuture>) -> Box<dyn GpuFuture> {
+        match self.future() {
+            Some(compute_future) => future.join(compute_future).boxed(),
+            None => future,
+        }
+    }
+
+    /// Records and submits every queued task. This is called for you in [`PreUpdate`], but you can
+    /// call it yourself if you need the results sooner.
+    /// Returns the future of the submission, which is also stored for [`ComputeTasks::join`].
+    pub fn submit(&mut self) -> Box<dyn GpuFuture> {
+        let device = self.compute_queue.device().clone();
+
+        let Some(command_buffer) = self.record() else {
+            return sync::now(device).boxed();
+        };
+        let exclusive_buffers = self.transfer_to_compute();
+
+        // Chain onto the previous submission while it's in flight, so that vulkano knows about the
+        // resources it still uses. Once it has finished, waiting on it doesn't block and lets it
+        // clean up.
+        let before = match self.last_submission.take() {
+            Some(previous) if !previous.is_signaled().unwrap_or(false) => previous.boxed(),
+            Some(previous) => {
+                previous.wait(None).unwrap_or_else(|e| error!("{e}"));
+                sync::now(device.clone()).boxed()
+            }
+            None => sync::now(device.clone()).boxed(),
+        };
+
+        match before
+            .then_execute(self.compute_queue.clone(), command_buffer)
+            .unwrap()
+            .boxed()
+            .then_signal_fence_and_flush()
+        {
+            Ok(future) => {
+                self.transfer_to_graphics(&exclusive_buffers);
+                let future = Arc::new(future);
+                self.last_submission = Some(future.clone());
+                future.boxed()
+            }
+            Err(e) => {
+                error!("Failed to submit compute tasks: {e}");
+                self.transfer_to_graphics(&exclusive_buffers);
+                sync::now(device).boxed()
+            }
+        }
+    }
+
+    /// Records every queued task and executes it on the compute queue after `before`, which is
+    /// usually the future returned by [`crate::renderer::VulkanoWindowRenderer::acquire`].
+    ///
+    /// The returned future can be chained on the graphics queue. When the compute queue is a
+    /// different queue, a semaphore is signaled for the graphics queue to wait on, so the two
+    /// queues run concurrently until the graphics work actually needs the results.
+    pub fn submit_in_frame(&mut self, before: Box<dyn GpuFuture>) -> Box<dyn GpuFuture> {
+        let Some(command_buffer) = self.record() else {
+            return before;
+        };
+        // The graphics work in `before` has to be submitted ahead of the release, as it may still
+        // use the buffers from the graphics queue.
+        if !self.exclusive_buffers.is_empty() {
+            if let Err(e) = before.flush() {
+                error!("Failed to submit the work before the compute tasks: {e}");
+            }
+        }
+        let exclusive_buffers = self.transfer_to_compute();
+
+        let after_compute = before
+            .then_execute(self.compute_queue.clone(), command_buffer)
+            .unwrap();
+
+        if !self.is_async() {
+            return after_compute.boxed();
+        }
+
+        // Flushing now starts the compute work early. If it fails, the future still holds
+        // `before`, such as the swapchain acquire, so the frame can go on.
+        let future = after_compute.then_signal_semaphore();
+        if let Err(e) = future.flush() {
+            error!("Failed to submit compute tasks: {e}");
+        }
+        // Submitted on the graphics queue now, so it comes before the graphics work of the frame.
+        self.transfer_to_graphics(&exclusive_buffers);
+        future.boxed()
+    }
+
+    /// Whether compute tasks run on a different queue than graphics. If the device has no
+    /// separate compute queue, [`BevyVulkanoContext`] falls back to the graphics queue, and this
+    /// returns false.
+    #[inline]
+    pub fn is_async(&self) -> bool {
+        !Arc::ptr_eq(&self.compute_queue, &self.graphics_queue)
+    }
+
+    /// The queue compute tasks are submitted on.
+    #[inline]
+    pub fn compute_queue(&self) -> Arc<Queue> {
+        self.compute_queue.clone()
+    }
+
+    /// Makes a buffer usable from both the graphics and compute queues, with concurrent sharing
+    /// when their families differ. Nothing is changed if they are the same family.
+    ///
+    /// Buffers with exclusive sharing work too, as their ownership is transferred for you, but
+    /// that costs a submission on each queue whenever tasks bind them.
+    pub fn shared_buffer_create_info(&self, create_info: BufferCreateInfo) -> BufferCreateInfo {
+        BufferCreateInfo {
+            sharing: self.sharing(),
+            ..create_info
+        }
+    }
+
+    /// Makes an image usable from both the graphics and compute queues, with concurrent sharing
+    /// when their families differ. Images bound by tasks have to be made this way, see
+    /// [`ComputeTasks::enqueue`].
+    pub fn shared_image_create_info(&self, create_info: ImageCreateInfo) -> ImageCreateInfo {
+        ImageCreateInfo {
+            sharing: self.sharing(),
+            ..create_info
+        }
+    }
+
+    /// Checks that the images of `write` can be used from both queue families, and adds its
+    /// buffers with exclusive sharing to `exclusive_buffers`, to have their ownership transferred.
+    fn check_sharing(
+        &self,
+        write: &WriteDescriptorSet,
+        exclusive_buffers: &mut Vec<Arc<Buffer>>,
+    ) -> Result<(), Box<ValidationError>> {
+        let graphics_family = self.graphics_queue.queue_family_index();
+        let compute_family = self.compute_queue.queue_family_index();
+        if graphics_family == compute_family {
+            return Ok(());
+        }
+
+        let is_shared = |queue_family_indices: &[u32]| {
+            queue_family_indices.contains(&graphics_family)
+                && queue_family_indices.contains(&compute_family)
+        };
+        let mut check_buffer = |buffer: &Subbuffer<[u8]>| match buffer.buffer().sharing() {
+            Sharing::Exclusive => {
+                exclusive_buffers.push(buffer.buffer().clone());
+                true
+            }
+            Sharing::Concurrent(queue_family_indices) => is_shared(queue_family_indices),
+        };
+        let image_is_shared = |image_view: &Arc<ImageView>| match image_view.image().sharing() {
+            Sharing::Exclusive => false,
+            Sharing::Concurrent(queue_family_indices) => is_shared(queue_family_indices),
+        };
+
+        let all_shared = match write.elements() {
+            WriteDescriptorSetElements::Buffer(elements) => {
+                elements.iter().all(|element| check_buffer(&element.buffer))
+            }
+            WriteDescriptorSetElements::BufferView(elements) => elements
+                .iter()
+                .all(|buffer_view| check_buffer(buffer_view.buffer())),
+            WriteDescriptorSetElements::ImageView(elements) => elements
+                .iter()
+                .all(|element| image_is_shared(&element.image_view)),
+            WriteDescriptorSetElements::ImageViewSampler(elements) => elements
+                .iter()
+                .all(|(element, _)| image_is_shared(&element.image_view)),
+            _ => true,
+        };
+
+        if all_shared {
+            Ok(())
+        } else {
+            Err(Box::new(ValidationError {
+                context: format!("binding {}", write.binding()).into(),
+                problem: "is an image with exclusive sharing, or not shared with both the \
+                          graphics and compute queue families"
+                    .into(),
+                ..Default::default()
+            }))
+        }
+    }
+
+    /// Moves the exclusive buffers of the recorded tasks to the compute queue family. Returns
+    /// them, to be moved back with [`Self::transfer_to_graphics`] once the tasks are submitted.
+    fn transfer_to_compute(&mut self) -> Vec<Arc<Buffer>> {
+        let exclusive_buffers = std::mem::take(&mut self.exclusive_buffers);
+        if let Some(transfers) = &mut self.ownership_transfers {
+            if let Err(e) = transfers.transfer(&exclusive_buffers, Transfer::ToCompute) {
+                error!("Failed to transfer buffers to the compute queue family: {e}");
+            }
+        }
+        exclusive_buffers
+    }
+
+    fn transfer_to_graphics(&mut self, exclusive_buffers: &[Arc<Buffer>]) {
+        if let Some(transfers) = &mut self.ownership_transfers {
+            if let Err(e) = transfers.transfer(exclusive_buffers, Transfer::ToGraphics) {
+                error!("Failed to transfer buffers to the graphics queue family: {e}");
+            }
+        }
+    }
+
+    fn sharing<C: FromIterator<u32>>(&self) -> Sharing<C> {
+        let graphics_family = self.graphics_queue.queue_family_index();
+        let compute_family = self.compute_queue.queue_family_index();
+
+        if graphics_family == compute_family {
+            Sharing::Exclusive
+        } else {
+            Sharing::Concurrent([graphics_family, compute_family].into_iter().collect())
+        }
+    }
+
+    /// Records every queued task into one command buffer, or returns `None` if there are none.
+    fn record(&mut self) -> Option<Arc<PrimaryAutoCommandBuffer>> {
+        if self.queued.is_empty() {
+            return None;
+        }
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.clone(),
+            self.compute_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        'tasks: for task in self.queued.drain(..) {
+            let layout = task.pipeline.layout().clone();
+
+            let mut descriptor_sets = Vec::with_capacity(task.descriptor_sets.len());
+            for (set, writes) in task.descriptor_sets {
+                // The set index was checked in `enqueue`.
+                match DescriptorSet::new(
+                    self.descriptor_set_allocator.clone(),
+                    layout.set_layouts()[set as usize].clone(),
+                    writes,
+                    [],
+                ) {
+                    Ok(descriptor_set) => descriptor_sets.push((set, descriptor_set)),
+                    Err(e) => {
+                        error!("Skipping compute task, failed to create its descriptor set: {e}");
+                        continue 'tasks;
+                    }
+                }
+            }
+
+            builder
+                .bind_pipeline_compute(task.pipeline.clone())
+                .unwrap();
+
+            for (set, descriptor_set) in descriptor_sets {
+                builder
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Compute,
+                        layout.clone(),
+                        set,
+                        descriptor_set,
+                    )
+                    .unwrap();
+            }
+
+            if let Some(push_constants) = task.push_constants {
+                push_constants(&mut builder, &layout);
+            }
+
+            unsafe {
+                builder.dispatch(task.group_counts).unwrap();
+            }
+        }
+
+        Some(builder.build().unwrap())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Transfer {
+    ToCompute,
+    ToGraphics,
+}
+
+/// Transfers the ownership of buffers between the graphics and compute queue families. vulkano's
+/// command buffers can't record the release and acquire barriers, so they are recorded into
+/// command buffers of our own, and submitted right before and after the compute tasks. Queue
+/// submission order keeps them ordered with the work around them.
+struct OwnershipTransfers {
+    device: Arc<Device>,
+    graphics_queue: Arc<Queue>,
+    compute_queue: Arc<Queue>,
+    graphics_pool: vk::CommandPool,
+    compute_pool: vk::CommandPool,
+    in_flight: Vec<InFlightTransfer>,
+}
+
+/// What a transfer uses on the gpu, kept until its fence is signaled.
+struct InFlightTransfer {
+    /// Signaled by the release, waited on by the acquire.
+    semaphore: vk::Semaphore,
+    /// Signaled by the acquire, which comes after the release.
+    fence: vk::Fence,
+    graphics_command_buffer: vk::CommandBuffer,
+    compute_command_buffer: vk::CommandBuffer,
+}
+
+impl OwnershipTransfers {
+    fn new(graphics_queue: Arc<Queue>, compute_queue: Arc<Queue>) -> Result<Self, VulkanError> {
+        let device = graphics_queue.device().clone();
+        let create_pool = |queue_family_index: u32| unsafe {
+            let create_info = vk::CommandPoolCreateInfo::default()
+                .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+                .queue_family_index(queue_family_index);
+            let mut pool = vk::CommandPool::null();
+            (device.fns().v1_0.create_command_pool)(
+                device.handle(),
+                &create_info,
+                ptr::null(),
+                &mut pool,
+            )
+            .result()
+            .map_err(VulkanError::from)?;
+            Ok::<_, VulkanError>(pool)
+        };
+        let graphics_pool = create_pool(graphics_queue.queue_family_index())?;
+        let compute_pool = match create_pool(compute_queue.queue_family_index()) {
+            Ok(pool) => pool,
+            Err(e) => {
+                unsafe {
+                    (device.fns().v1_0.destroy_command_pool)(
+                        device.handle(),
+                        graphics_pool,
+                        ptr::null(),
+                    );
+                }
+                return Err(e);
+            }
+        };
+
+        Ok(Self {
+            device,
+            graphics_queue,
+            compute_queue,
+            graphics_pool,
+            compute_pool,
+            in_flight: Vec::new(),
+        })
+    }
+
+    /// Releases `buffers` from one family and acquires them in the other. Does nothing without
+    /// buffers.
+    fn transfer(&mut self, buffers: &[Arc<Buffer>], transfer: Transfer) -> Result<(), VulkanError> {
+        self.free_finished();
+        if buffers.is_empty() {
+            return Ok(());
+        }
+
+        let in_flight = self.create_in_flight()?;
+        let result = unsafe { self.record_and_submit(&in_flight, buffers, transfer) };
+        match result {
+            Ok(()) => {
+                self.in_flight.push(in_flight);
+                Ok(())
+            }
+            Err(e) => {
+                // The release may have been submitted without the acquire.
+                unsafe {
+                    for queue in [&self.graphics_queue, &self.compute_queue] {
+                        let _ = queue
+                            .with(|_| (self.device.fns().v1_0.queue_wait_idle)(queue.handle()));
+                    }
+                }
+                self.destroy(in_flight);
+                Err(e)
+            }
+        }
+    }
+
+    fn create_in_flight(&self) -> Result<InFlightTransfer, VulkanError> {
+        let fns = &self.device.fns().v1_0;
+        let mut in_flight = InFlightTransfer {
+            semaphore: vk::Semaphore::null(),
+            fence: vk::Fence::null(),
+            graphics_command_buffer: vk::CommandBuffer::null(),
+            compute_command_buffer: vk::CommandBuffer::null(),
+        };
+
+        let result = unsafe {
+            (fns.create_semaphore)(
+                self.device.handle(),
+                &vk::SemaphoreCreateInfo::default(),
+                ptr::null(),
+                &mut in_flight.semaphore,
+            )
+            .result()
+            .and_then(|()| {
+                (fns.create_fence)(
+                    self.device.handle(),
+                    &vk::FenceCreateInfo::default(),
+                    ptr::null(),
+                    &mut in_flight.fence,
+                )
+                .result()
+            })
+            .and_then(|()| {
+                let allocate_info = vk::CommandBufferAllocateInfo::default()
+                    .command_pool(self.graphics_pool)
+                    .level(vk::CommandBufferLevel::PRIMARY)
+                    .command_buffer_count(1);
+                (fns.allocate_command_buffers)(
+                    self.device.handle(),
+                    &allocate_info,
+                    &mut in_flight.graphics_command_buffer,
+                )
+                .result()
+            })
+            .and_then(|()| {
+                let allocate_info = vk::CommandBufferAllocateInfo::default()
+                    .command_pool(self.compute_pool)
+                    .level(vk::CommandBufferLevel::PRIMARY)
+                    .command_buffer_count(1);
+                (fns.allocate_command_buffers)(
+                    self.device.handle(),
+                    &allocate_info,
+                    &mut in_flight.compute_command_buffer,
+                )
+                .result()
+            })
+        };
+
+        match result {
+            Ok(()) => Ok(in_flight),
+            Err(e) => {
+                self.destroy(in_flight);
+                Err(VulkanError::from(e))
+            }
+        }
+    }
+
+    /// Records the release into the command buffer of the source family and the acquire into the
+    /// other's, and submits them in that order.
+    ///
+    /// # Safety
+    ///
+    /// The command buffers of `in_flight` must be unused.
+    unsafe fn record_and_submit(
+        &self,
+        in_flight: &InFlightTransfer,
+        buffers: &[Arc<Buffer>],
+        transfer: Transfer,
+    ) -> Result<(), VulkanError> {
+        let fns = &self.device.fns().v1_0;
+        let (source_queue, source_command_buffer, destination_queue, destination_command_buffer) =
+            match transfer {
+                Transfer::ToCompute => (
+                    &self.graphics_queue,
+                    in_flight.graphics_command_buffer,
+                    &self.compute_queue,
+                    in_flight.compute_command_buffer,
+                ),
+                Transfer::ToGraphics => (
+                    &self.compute_queue,
+                    in_flight.compute_command_buffer,
+                    &self.graphics_queue,
+                    in_flight.graphics_command_buffer,
+                ),
+            };
+
+        // The release makes the writes before it available, the acquire makes them visible to
+        // whatever comes after. Their barriers have to match but for the access masks.
+        let barriers = |src_access_mask, dst_access_mask| {
+            buffers
+                .iter()
+                .map(|buffer| {
+                    vk::BufferMemoryBarrier::default()
+                        .src_access_mask(src_access_mask)
+                        .dst_access_mask(dst_access_mask)
+                        .src_queue_family_index(source_queue.queue_family_index())
+                        .dst_queue_family_index(destination_queue.queue_family_index())
+                        .buffer(buffer.handle())
+                        .offset(0)
+                        .size(vk::WHOLE_SIZE)
+                })
+                .collect::<Vec<_>>()
+        };
+        let record = |command_buffer, barriers: Vec<vk::BufferMemoryBarrier>| {
+            let begin_info = vk::CommandBufferBeginInfo::default()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            (fns.begin_command_buffer)(command_buffer, &begin_info).result()?;
+            (fns.cmd_pipeline_barrier)(
+                command_buffer,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::PipelineStageFlags::ALL_COMMANDS,
+                vk::DependencyFlags::empty(),
+                0,
+                ptr::null(),
+                barriers.len() as u32,
+                barriers.as_ptr(),
+                0,
+                ptr::null(),
+            );
+            (fns.end_command_buffer)(command_buffer).result()
+        };
+        record(
+            source_command_buffer,
+            barriers(vk::AccessFlags::MEMORY_WRITE, vk::AccessFlags::empty()),
+        )?;
+        record(
+            destination_command_buffer,
+            barriers(
+                vk::AccessFlags::empty(),
+                vk::AccessFlags::MEMORY_READ | vk::AccessFlags::MEMORY_WRITE,
+            ),
+        )?;
+
+        let semaphores = [in_flight.semaphore];
+        let wait_stages = [vk::PipelineStageFlags::ALL_COMMANDS];
+        let source_command_buffers = [source_command_buffer];
+        let release = vk::SubmitInfo::default()
+            .command_buffers(&source_command_buffers)
+            .signal_semaphores(&semaphores);
+        source_queue.with(|_| {
+            (fns.queue_submit)(source_queue.handle(), 1, &release, vk::Fence::null()).result()
+        })?;
+
+        let destination_command_buffers = [destination_command_buffer];
+        let acquire = vk::SubmitInfo::default()
+            .wait_semaphores(&semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(&destination_command_buffers);
+        destination_queue.with(|_| {
+            (fns.queue_submit)(destination_queue.handle(), 1, &acquire, in_flight.fence).result()
+        })?;
+
+        Ok(())
+    }
+
+    /// Destroys the transfers that have finished.
+    fn free_finished(&mut self) {
+        let mut index = 0;
+        while index < self.in_flight.len() {
+            let status = unsafe {
+                (self.device.fns().v1_0.get_fence_status)(
+                    self.device.handle(),
+                    self.in_flight[index].fence,
+                )
+            };
+            if status == vk::Result::SUCCESS {
+                let in_flight = self.in_flight.swap_remove(index);
+                self.destroy(in_flight);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    /// Destroys what `in_flight` holds. It must not be in use by the gpu.
+    fn destroy(&self, in_flight: InFlightTransfer) {
+        let fns = &self.device.fns().v1_0;
+        let device = self.device.handle();
+        unsafe {
+            if in_flight.graphics_command_buffer != vk::CommandBuffer::null() {
+                (fns.free_command_buffers)(
+                    device,
+                    self.graphics_pool,
+                    1,
+                    &in_flight.graphics_command_buffer,
+                );
+            }
+            if in_flight.compute_command_buffer != vk::CommandBuffer::null() {
+                (fns.free_command_buffers)(
+                    device,
+                    self.compute_pool,
+                    1,
+                    &in_flight.compute_command_buffer,
+                );
+            }
+            (fns.destroy_fence)(device, in_flight.fence, ptr::null());
+            (fns.destroy_semaphore)(device, in_flight.semaphore, ptr::null());
+        }
+    }
+}
+
+impl Drop for OwnershipTransfers {
+    fn drop(&mut self) {
+        let fences = self
+            .in_flight
+            .iter()
+            .map(|in_flight| in_flight.fence)
+            .collect::<Vec<_>>();
+        let fns = &self.device.fns().v1_0;
+        unsafe {
+            if !fences.is_empty() {
+                let _ = (fns.wait_for_fences)(
+                    self.device.handle(),
+                    fences.len() as u32,
+                    fences.as_ptr(),
+                    vk::TRUE,
+                    u64::MAX,
+                );
+            }
+        }
+        for in_flight in std::mem::take(&mut self.in_flight) {
+            self.destroy(in_flight);
+        }
+        unsafe {
+            (fns.destroy_command_pool)(self.device.handle(), self.graphics_pool, ptr::null());
+            (fns.destroy_command_pool)(self.device.handle(), self.compute_pool, ptr::null());
+        }
+    }
+}
+
+/// Submits the compute tasks queued since the last frame.
+pub fn submit_compute_tasks(mut compute_tasks: NonSendMut<ComputeTasks>) {
+    compute_tasks.submit();
+}
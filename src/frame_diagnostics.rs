@@ -0,0 +1,118 @@
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticMeasurement, DiagnosticPath, DiagnosticsStore},
+    ecs::entity::EntityHashMap,
+    prelude::*,
+    utils::Instant,
+};
+
+use crate::renderer::{FrameStats, VulkanoWindowRendererWithoutWindow};
+
+/// Publishes the [`FrameStats`] of every window renderer as diagnostics, under
+/// `vulkano_window/<window entity>/`. Add it after [`crate::VulkanoPlugin`] and
+/// [`bevy::diagnostic::DiagnosticsPlugin`].
+pub struct WindowFrameDiagnosticsPlugin;
+
+impl Plugin for WindowFrameDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WindowDiagnosticPaths>()
+            .add_systems(Last, publish_window_frame_stats);
+    }
+}
+
+/// The diagnostic paths of one window.
+struct WindowPaths {
+    acquire_wait: DiagnosticPath,
+    present_interval: DiagnosticPath,
+    swapchain_recreations: DiagnosticPath,
+    out_of_date: DiagnosticPath,
+    suboptimal: DiagnosticPath,
+    skipped_frames: DiagnosticPath,
+}
+
+impl WindowPaths {
+    fn new(entity: Entity) -> Self {
+        let path = |name: &str| DiagnosticPath::new(format!("vulkano_window/{entity}/{name}"));
+
+        Self {
+            acquire_wait: path("acquire_wait"),
+            present_interval: path("present_interval"),
+            swapchain_recreations: path("swapchain_recreations"),
+            out_of_date: path("out_of_date"),
+            suboptimal: path("suboptimal"),
+            skipped_frames: path("skipped_frames"),
+        }
+    }
+
+    /// Pairs each path with its value, and whether the value is a duration in milliseconds.
+    fn measurements(&self, stats: &FrameStats) -> [(&DiagnosticPath, f64, bool); 6] {
+        [
+            (
+                &self.acquire_wait,
+                stats.acquire_wait.as_secs_f64() * 1000.0,
+                true,
+            ),
+            (
+                &self.present_interval,
+                stats.present_interval.as_secs_f64() * 1000.0,
+                true,
+            ),
+            (
+                &self.swapchain_recreations,
+                stats.swapchain_recreations as f64,
+                false,
+            ),
+            (&self.out_of_date, stats.out_of_date as f64, false),
+            (&self.suboptimal, stats.suboptimal as f64, false),
+            (&self.skipped_frames, stats.skipped_frames as f64, false),
+        ]
+    }
+}
+
+#[derive(Resource, Default)]
+struct WindowDiagnosticPaths(EntityHashMap<WindowPaths>);
+
+fn publish_window_frame_stats(
+    renderers: NonSend<EntityHashMap<VulkanoWindowRendererWithoutWindow>>,
+    mut paths: ResMut<WindowDiagnosticPaths>,
+    mut diagnostics: ResMut<DiagnosticsStore>,
+) {
+    let time = Instant::now();
+
+    // Diagnostics can't be removed, so the ones of closed windows are disabled instead.
+    paths.0.retain(|entity, window_paths| {
+        let open = renderers.contains_key(entity);
+        if !open {
+            for (path, _, _) in window_paths.measurements(&FrameStats::default()) {
+                if let Some(diagnostic) = diagnostics.get_mut(path) {
+                    diagnostic.is_enabled = false;
+                }
+            }
+        }
+        open
+    });
+
+    for (entity, renderer) in renderers.iter() {
+        let window_paths = paths
+            .0
+            .entry(*entity)
+            .or_insert_with(|| WindowPaths::new(*entity));
+
+        for (path, value, is_duration) in window_paths.measurements(&renderer.frame_stats()) {
+            if diagnostics.get(path).is_none() {
+                let diagnostic = Diagnostic::new(path.clone());
+                diagnostics.add(if is_duration {
+                    diagnostic.with_suffix("ms")
+                } else {
+                    diagnostic.with_smoothing_factor(0.0)
+                });
+            }
+
+            let diagnostic = diagnostics.get_mut(path).unwrap();
+            diagnostic.is_enabled = true;
+            diagnostic.add_measurement(DiagnosticMeasurement {
+                time,
+                value,
+            });
+        }
+    }
+}
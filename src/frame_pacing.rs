@@ -0,0 +1,104 @@
+use std::time::{Duration, Instant};
+
+use bevy::{ecs::entity::EntityHashMap, prelude::*};
+
+use crate::renderer::VulkanoWindowRendererWithoutWindow;
+
+/// How frames are paced. Insert it before adding [`crate::VulkanoPlugin`] to start with your own,
+/// and change it at any time through the resource.
+///
+/// Pacing happens at the very end of the frame in [`Last`], so that the next frame's input is
+/// sampled after it, and before the next [`crate::renderer::VulkanoWindowRenderer::acquire`].
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct FramePacing {
+    /// The most frames per second to run at, or `None` to not limit them. Vsync still applies on
+    /// top of this, depending on the present mode.
+    pub target_fps: Option<f64>,
+    /// Wait for the previous frame of every window to finish before starting the next one. The cpu
+    /// never runs ahead of the gpu, so input reaches the screen sooner, at the cost of throughput.
+    pub low_latency: bool,
+    /// In low latency mode, wait for the previous frame to be shown rather than just rendered,
+    /// using `VK_KHR_present_wait`. [`crate::VulkanoPlugin`] requests the `present_id` and
+    /// `present_wait` device features when this is set in low latency mode. With your own
+    /// [`crate::BevyVulkanoContext`], use [`crate::request_present_wait`] on its config, otherwise
+    /// this does nothing.
+    pub present_wait: bool,
+    /// How long before the target frame time to stop sleeping and spin instead, as sleeps tend to
+    /// overshoot.
+    pub spin_threshold: Duration,
+}
+
+impl Default for FramePacing {
+    fn default() -> Self {
+        Self {
+            target_fps: None,
+            low_latency: false,
+            present_wait: false,
+            spin_threshold: Duration::from_millis(1),
+        }
+    }
+}
+
+impl FramePacing {
+    /// Limits the frame rate to `target_fps`.
+    pub fn capped(target_fps: f64) -> Self {
+        Self {
+            target_fps: Some(target_fps),
+            ..default()
+        }
+    }
+
+    /// Waits for the previous frame before starting the next one.
+    pub fn low_latency() -> Self {
+        Self {
+            low_latency: true,
+            ..default()
+        }
+    }
+}
+
+/// When the current frame should end, if the frame rate is limited.
+#[derive(Default)]
+pub(crate) struct FrameDeadline(Option<Instant>);
+
+/// Waits for the previous frames in low latency mode, then sleeps until the target frame time.
+pub(crate) fn pace_frames(
+    pacing: Res<FramePacing>,
+    mut deadline: Local<FrameDeadline>,
+    renderers: NonSend<EntityHashMap<VulkanoWindowRendererWithoutWindow>>,
+) {
+    if pacing.low_latency {
+        for renderer in renderers.values() {
+            renderer.wait_for_previous_frame(pacing.present_wait);
+        }
+    }
+
+    let Some(target_fps) = pacing.target_fps.filter(|fps| *fps > 0.0) else {
+        deadline.0 = None;
+        return;
+    };
+    let frame_time = Duration::from_secs_f64(1.0 / target_fps);
+    let now = Instant::now();
+
+    // Deadlines follow each other, so frames don't drift. If we fell behind by more than a frame,
+    // start over from now rather than rushing to catch up.
+    let frame_end = deadline
+        .0
+        .filter(|frame_end| *frame_end + frame_time > now)
+        .unwrap_or(now);
+    sleep_until(frame_end, pacing.spin_threshold);
+    deadline.0 = Some(frame_end + frame_time);
+}
+
+fn sleep_until(deadline: Instant, spin_threshold: Duration) {
+    if let Some(sleep) = deadline
+        .checked_duration_since(Instant::now())
+        .and_then(|remaining| remaining.checked_sub(spin_threshold))
+    {
+        std::thread::sleep(sleep);
+    }
+
+    while Instant::now() < deadline {
+        std::hint::spin_loop();
+    }
+}
@@ -0,0 +1,755 @@
+//! Immediate-mode debug drawing, enabled with the `gizmos` feature.
+//!
+//! Any system can draw lines, boxes, spheres, arrows, grids and 2D shapes through
+//! [`VulkanoGizmos`]. They are drawn for one frame only, so draw them again every frame to keep
+//! them on screen. [`GizmoPlugin`] draws them on top of each window's swapchain image, right before
+//! the window is presented, through [`crate::renderer::VulkanoWindowRenderer::set_present_overlay`].
+//!
+//! Every window is seen through its active [`VulkanoCamera`]s rendering into its swapchain image,
+//! in their viewports. Without one, it's seen in logical pixels, with the origin at the center and
+//! `+y` up.
+//!
+//! The gizmos drawn before [`PostUpdate`] are collected there, after cameras are updated. Windows
+//! rendered by plugins in [`PostUpdate`] show them the same frame, while windows rendered by your
+//! own systems in [`Update`] show them the next frame.
+
+use std::sync::Arc;
+
+use bevy::{ecs::entity::EntityHashMap, prelude::*, utils::HashMap};
+use vulkano::{
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        BufferContents, BufferUsage, Subbuffer,
+    },
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        RenderPassBeginInfo, SubpassBeginInfo, SubpassContents,
+    },
+    device::{Device, Queue},
+    format::{Format, NumericFormat},
+    image::{view::ImageView, SampleCount},
+    memory::allocator::MemoryTypeFilter,
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::ViewportState,
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        DynamicState, GraphicsPipeline, PipelineLayout, PipelineShaderStageCreateInfo,
+    },
+    render_pass::{RenderPass, Subpass},
+    shader::EntryPoint,
+    sync::GpuFuture,
+};
+
+use crate::{
+    camera::{update_cameras, CameraMatrices, CameraTarget, VulkanoCamera},
+    render_graph::execute_render_graph,
+    renderer::VulkanoWindowRenderer,
+    viewport_and_scissor,
+    vulkano_renderers::reconcile_renderers,
+    BevyVulkanoContext, VulkanoRenderers,
+};
+
+/// The segments of circles and spheres.
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Draws [`VulkanoGizmos`] on top of every window. Add it after [`crate::VulkanoPlugin`], and
+/// after [`crate::CameraPlugin`] to see the gizmos through its cameras.
+pub struct GizmoPlugin;
+
+impl Plugin for GizmoPlugin {
+    fn build(&self, app: &mut App) {
+        let prepare = prepare_gizmos
+            .after(update_cameras)
+            .after(reconcile_renderers)
+            .before(execute_render_graph);
+        #[cfg(feature = "sprite")]
+        let prepare = prepare.before(crate::sprite::render_sprites);
+
+        app.init_resource::<GizmoConfig>()
+            .init_resource::<VulkanoGizmos>()
+            .init_non_send_resource::<GizmoRenderer>()
+            .add_systems(PostUpdate, prepare);
+    }
+}
+
+/// How gizmos are drawn.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct GizmoConfig {
+    /// Whether gizmos are drawn. They are still cleared every frame when they aren't.
+    pub enabled: bool,
+    /// Whether gizmos are hidden behind what's in the window's depth attachment, see
+    /// [`crate::WindowAttachments`]. While it's enabled, the depth attachment of every window is
+    /// kept with [`VulkanoWindowRenderer::set_depth_stored`]: your last render pass of the frame
+    /// has to store the depth with `Store` instead of `DontCare`, and use Vulkan's depth range with
+    /// `LessOrEqual`, like [`VulkanoCamera`] does. Disabling it makes the depth attachments
+    /// transient again. Ignored for windows without a depth attachment, or with multisampled
+    /// attachments.
+    pub depth_test: bool,
+}
+
+impl Default for GizmoConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            depth_test: false,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(BufferContents, Vertex, Clone, Copy)]
+struct GizmoVertex {
+    #[format(R32G32B32_SFLOAT)]
+    position: [f32; 3],
+    #[format(R32G32B32A32_SFLOAT)]
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(BufferContents)]
+struct PushConstants {
+    view_proj: [[f32; 4]; 4],
+    output_in_linear_colorspace: u32,
+}
+
+/// Lines to draw this frame, in world space. Colors are linear RGBA. 2D shapes are drawn on the
+/// `z = 0` plane.
+#[derive(Resource, Default)]
+pub struct VulkanoGizmos {
+    /// Pairs of vertices, one line each.
+    vertices: Vec<GizmoVertex>,
+}
+
+impl VulkanoGizmos {
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: [f32; 4]) {
+        self.line_gradient(start, end, color, color);
+    }
+
+    /// A line fading from `start_color` to `end_color`.
+    pub fn line_gradient(
+        &mut self,
+        start: Vec3,
+        end: Vec3,
+        start_color: [f32; 4],
+        end_color: [f32; 4],
+    ) {
+        self.vertices.extend([
+            GizmoVertex {
+                position: start.to_array(),
+                color: start_color,
+            },
+            GizmoVertex {
+                position: end.to_array(),
+                color: end_color,
+            },
+        ]);
+    }
+
+    /// Lines through `points`, in order.
+    pub fn linestrip(&mut self, points: impl IntoIterator<Item = Vec3>, color: [f32; 4]) {
+        let mut points = points.into_iter();
+        let Some(mut previous) = points.next() else {
+            return;
+        };
+        for point in points {
+            self.line(previous, point, color);
+            previous = point;
+        }
+    }
+
+    /// A line from `origin` to `origin + vector`.
+    pub fn ray(&mut self, origin: Vec3, vector: Vec3, color: [f32; 4]) {
+        self.line(origin, origin + vector, color);
+    }
+
+    /// A line from `start` to `end`, with a head at `end` a tenth of its length.
+    pub fn arrow(&mut self, start: Vec3, end: Vec3, color: [f32; 4]) {
+        self.line(start, end, color);
+
+        let vector = end - start;
+        let length = vector.length();
+        if length == 0.0 {
+            return;
+        }
+        let direction = vector / length;
+        let (side, up) = direction.any_orthonormal_pair();
+        let back = end - direction * length / 10.0;
+        for offset in [side, -side, up, -up] {
+            self.line(end, back + offset * length / 20.0, color);
+        }
+    }
+
+    /// The edges of the cube from `-0.5` to `0.5`, placed by `transform`. Scale it for other
+    /// sizes.
+    pub fn cuboid(&mut self, transform: Transform, color: [f32; 4]) {
+        let corners = [
+            Vec3::new(-0.5, -0.5, -0.5),
+            Vec3::new(0.5, -0.5, -0.5),
+            Vec3::new(0.5, 0.5, -0.5),
+            Vec3::new(-0.5, 0.5, -0.5),
+            Vec3::new(-0.5, -0.5, 0.5),
+            Vec3::new(0.5, -0.5, 0.5),
+            Vec3::new(0.5, 0.5, 0.5),
+            Vec3::new(-0.5, 0.5, 0.5),
+        ]
+        .map(|corner| transform.transform_point(corner));
+
+        for i in 0..4 {
+            // The back face, the front face, and the edges between them.
+            self.line(corners[i], corners[(i + 1) % 4], color);
+            self.line(corners[i + 4], corners[(i + 1) % 4 + 4], color);
+            self.line(corners[i], corners[i + 4], color);
+        }
+    }
+
+    /// The edges of the axis-aligned box from `min` to `max`.
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: [f32; 4]) {
+        self.cuboid(
+            Transform::from_translation((min + max) / 2.0).with_scale(max - min),
+            color,
+        );
+    }
+
+    /// A circle around `position`, facing `normal`.
+    pub fn circle(&mut self, position: Vec3, normal: Vec3, radius: f32, color: [f32; 4]) {
+        let (u, v) = normal
+            .try_normalize()
+            .unwrap_or(Vec3::Z)
+            .any_orthonormal_pair();
+        self.linestrip(
+            circle_points().map(|point| position + (u * point.x + v * point.y) * radius),
+            color,
+        );
+    }
+
+    /// A sphere as three circles, around the axes of `rotation`.
+    pub fn sphere(&mut self, position: Vec3, rotation: Quat, radius: f32, color: [f32; 4]) {
+        for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+            self.circle(position, rotation * axis, radius, color);
+        }
+    }
+
+    /// A grid of `cell_count` cells of `spacing`, centered on `position`, on the `xy` plane
+    /// rotated by `rotation`.
+    pub fn grid(
+        &mut self,
+        position: Vec3,
+        rotation: Quat,
+        cell_count: UVec2,
+        spacing: Vec2,
+        color: [f32; 4],
+    ) {
+        let half_size = cell_count.as_vec2() * spacing / 2.0;
+        let point = |x: f32, y: f32| position + rotation * Vec3::new(x, y, 0.0);
+
+        for i in 0..=cell_count.x {
+            let x = i as f32 * spacing.x - half_size.x;
+            self.line(point(x, -half_size.y), point(x, half_size.y), color);
+        }
+        for i in 0..=cell_count.y {
+            let y = i as f32 * spacing.y - half_size.y;
+            self.line(point(-half_size.x, y), point(half_size.x, y), color);
+        }
+    }
+
+    /// The `x`, `y` and `z` axes of `transform`, in red, green and blue, `length` long.
+    pub fn axes(&mut self, transform: Transform, length: f32) {
+        let origin = transform.translation;
+        for (axis, color) in [
+            (transform.right(), [1.0, 0.0, 0.0, 1.0]),
+            (transform.up(), [0.0, 1.0, 0.0, 1.0]),
+            (transform.back(), [0.0, 0.0, 1.0, 1.0]),
+        ] {
+            self.arrow(origin, origin + axis * length, color);
+        }
+    }
+
+    pub fn line_2d(&mut self, start: Vec2, end: Vec2, color: [f32; 4]) {
+        self.line(start.extend(0.0), end.extend(0.0), color);
+    }
+
+    /// Lines through `points`, in order.
+    pub fn linestrip_2d(&mut self, points: impl IntoIterator<Item = Vec2>, color: [f32; 4]) {
+        self.linestrip(points.into_iter().map(|point| point.extend(0.0)), color);
+    }
+
+    /// A line from `origin` to `origin + vector`.
+    pub fn ray_2d(&mut self, origin: Vec2, vector: Vec2, color: [f32; 4]) {
+        self.line_2d(origin, origin + vector, color);
+    }
+
+    /// A line from `start` to `end`, with a head at `end` a tenth of its length.
+    pub fn arrow_2d(&mut self, start: Vec2, end: Vec2, color: [f32; 4]) {
+        self.line_2d(start, end, color);
+
+        let vector = end - start;
+        let back = end - vector / 10.0;
+        let side = vector.perp() / 20.0;
+        self.line_2d(end, back + side, color);
+        self.line_2d(end, back - side, color);
+    }
+
+    /// A rectangle of `size` centered on `position`, rotated by `rotation` radians.
+    pub fn rect_2d(&mut self, position: Vec2, rotation: f32, size: Vec2, color: [f32; 4]) {
+        let rotation = Vec2::from_angle(rotation);
+        let half_size = size / 2.0;
+        let corners = [
+            Vec2::new(-half_size.x, -half_size.y),
+            Vec2::new(half_size.x, -half_size.y),
+            Vec2::new(half_size.x, half_size.y),
+            Vec2::new(-half_size.x, half_size.y),
+        ]
+        .map(|corner| position + rotation.rotate(corner));
+        self.linestrip_2d(corners.into_iter().chain([corners[0]]), color);
+    }
+
+    pub fn circle_2d(&mut self, position: Vec2, radius: f32, color: [f32; 4]) {
+        self.linestrip_2d(
+            circle_points().map(|point| position + point * radius),
+            color,
+        );
+    }
+
+    /// A grid of `cell_count` cells of `spacing`, centered on `position`.
+    pub fn grid_2d(&mut self, position: Vec2, cell_count: UVec2, spacing: Vec2, color: [f32; 4]) {
+        self.grid(
+            position.extend(0.0),
+            Quat::IDENTITY,
+            cell_count,
+            spacing,
+            color,
+        );
+    }
+
+    /// How many lines have been drawn this frame.
+    pub fn line_count(&self) -> usize {
+        self.vertices.len() / 2
+    }
+
+    /// Removes the lines drawn this frame.
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+}
+
+/// The points of a closed unit circle, the first one repeated at the end.
+fn circle_points() -> impl Iterator<Item = Vec2> {
+    (0..=CIRCLE_SEGMENTS)
+        .map(|i| Vec2::from_angle(i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU))
+}
+
+/// A render pass and pipeline drawing onto a swapchain image of some format, over a depth
+/// attachment of some format if depth testing.
+#[derive(Clone)]
+struct GizmoPipeline {
+    render_pass: Arc<RenderPass>,
+    pipeline: Arc<GraphicsPipeline>,
+}
+
+/// The pipelines and buffers drawing gizmos.
+pub struct GizmoRenderer {
+    device: Arc<Device>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    vertex_allocator: SubbufferAllocator,
+    vs: EntryPoint,
+    fs: EntryPoint,
+    layout: Arc<PipelineLayout>,
+    /// Pipelines by swapchain format and depth format, if depth testing.
+    pipelines: HashMap<(Format, Option<Format>), GizmoPipeline>,
+}
+
+impl FromWorld for GizmoRenderer {
+    fn from_world(world: &mut World) -> Self {
+        let context = world.resource::<BevyVulkanoContext>();
+        let device = context.device().clone();
+
+        let vs = vs::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let fs = fs::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&[
+                PipelineShaderStageCreateInfo::new(vs.clone()),
+                PipelineShaderStageCreateInfo::new(fs.clone()),
+            ])
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+        )
+        .unwrap();
+
+        Self {
+            command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
+                device.clone(),
+                Default::default(),
+            )),
+            vertex_allocator: SubbufferAllocator::new(
+                context.memory_allocator().clone(),
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage: BufferUsage::VERTEX_BUFFER,
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+            ),
+            device,
+            vs,
+            fs,
+            layout,
+            pipelines: HashMap::default(),
+        }
+    }
+}
+
+impl GizmoRenderer {
+    fn pipeline(&mut self, format: Format, depth_format: Option<Format>) -> GizmoPipeline {
+        let (device, vs, fs, layout) = (&self.device, &self.vs, &self.fs, &self.layout);
+
+        self.pipelines
+            .entry((format, depth_format))
+            .or_insert_with(|| {
+                let render_pass = match depth_format {
+                    Some(depth_format) => vulkano::single_pass_renderpass!(
+                        device.clone(),
+                        attachments: {
+                            color: {
+                                format: format,
+                                samples: 1,
+                                load_op: Load,
+                                store_op: Store,
+                            },
+                            depth_stencil: {
+                                format: depth_format,
+                                samples: 1,
+                                load_op: Load,
+                                store_op: DontCare,
+                            },
+                        },
+                        pass: {
+                            color: [color],
+                            depth_stencil: {depth_stencil},
+                        },
+                    )
+                    .unwrap(),
+                    None => vulkano::single_pass_renderpass!(
+                        device.clone(),
+                        attachments: {
+                            color: {
+                                format: format,
+                                samples: 1,
+                                load_op: Load,
+                                store_op: Store,
+                            },
+                        },
+                        pass: {
+                            color: [color],
+                            depth_stencil: {},
+                        },
+                    )
+                    .unwrap(),
+                };
+
+                let vertex_input_state = GizmoVertex::per_vertex().definition(vs).unwrap();
+                let stages = [
+                    PipelineShaderStageCreateInfo::new(vs.clone()),
+                    PipelineShaderStageCreateInfo::new(fs.clone()),
+                ];
+                let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+
+                let pipeline =
+                    GraphicsPipeline::new(device.clone(), None, GraphicsPipelineCreateInfo {
+                        stages: stages.into_iter().collect(),
+                        vertex_input_state: Some(vertex_input_state),
+                        input_assembly_state: Some(InputAssemblyState {
+                            topology: PrimitiveTopology::LineList,
+                            ..Default::default()
+                        }),
+                        viewport_state: Some(ViewportState::default()),
+                        rasterization_state: Some(RasterizationState::default()),
+                        // Gizmos are tested against the depth, but don't write it.
+                        depth_stencil_state: depth_format.map(|_| DepthStencilState {
+                            depth: Some(DepthState {
+                                write_enable: false,
+                                compare_op: CompareOp::LessOrEqual,
+                            }),
+                            ..Default::default()
+                        }),
+                        multisample_state: Some(MultisampleState::default()),
+                        color_blend_state: Some(ColorBlendState::with_attachment_states(
+                            1,
+                            ColorBlendAttachmentState {
+                                blend: Some(AttachmentBlend::alpha()),
+                                ..Default::default()
+                            },
+                        )),
+                        dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                            .into_iter()
+                            .collect(),
+                        subpass: Some(subpass.into()),
+                        ..GraphicsPipelineCreateInfo::layout(layout.clone())
+                    })
+                    .unwrap();
+
+                GizmoPipeline {
+                    render_pass,
+                    pipeline,
+                }
+            })
+            .clone()
+    }
+}
+
+/// What a window's gizmos are seen through: a view projection matrix, and the region of the
+/// window it renders into, normalized.
+type GizmoView = (Mat4, Rect);
+
+/// Uploads the gizmos drawn this frame, and sets every window to draw them before it's presented.
+/// Your render systems in [`PostUpdate`] should run after it to show them the same frame.
+pub fn prepare_gizmos(
+    context: Res<BevyVulkanoContext>,
+    config: Res<GizmoConfig>,
+    mut gizmos: ResMut<VulkanoGizmos>,
+    mut gizmo_renderer: NonSendMut<GizmoRenderer>,
+    cameras: Query<(&VulkanoCamera, &CameraMatrices)>,
+    mut renderers: VulkanoRenderers,
+) {
+    let vertex_buffer = (config.enabled && !gizmos.vertices.is_empty()).then(|| {
+        let vertex_buffer = gizmo_renderer
+            .vertex_allocator
+            .allocate_slice(gizmos.vertices.len() as u64)
+            .unwrap();
+        vertex_buffer
+            .write()
+            .unwrap()
+            .copy_from_slice(&gizmos.vertices);
+        vertex_buffer
+    });
+    gizmos.clear();
+
+    let mut views_by_window = EntityHashMap::<Vec<_>>::default();
+    for (camera, matrices) in &cameras {
+        // Cameras rendering into additional image views have a window too.
+        let renders_into_swapchain = matches!(
+            camera.target,
+            CameraTarget::PrimaryWindow | CameraTarget::Window(_)
+        );
+        let Some(window) = matrices.window.filter(|_| renders_into_swapchain) else {
+            continue;
+        };
+        views_by_window
+            .entry(window)
+            .or_default()
+            .push((camera.order, (matrices.view_proj, camera.viewport)));
+    }
+
+    for (window, mut renderer) in renderers.iter_mut() {
+        // Takes effect when the attachments are recreated, on the next acquire.
+        renderer.set_depth_stored(config.depth_test);
+
+        let Some(vertex_buffer) = vertex_buffer.clone() else {
+            // Don't draw last frame's gizmos.
+            renderer.set_present_overlay(None);
+            continue;
+        };
+
+        let logical_size = Vec2::from(renderer.logical_size());
+        let mut views = views_by_window.remove(&window).unwrap_or_default();
+        if views.is_empty() {
+            // Logical pixels, with `+y` up.
+            let half_size = logical_size / 2.0;
+            let view_proj = Mat4::orthographic_rh(
+                -half_size.x,
+                half_size.x,
+                half_size.y,
+                -half_size.y,
+                -1.0,
+                1.0,
+            );
+            views.push((0, (view_proj, Rect::new(0.0, 0.0, 1.0, 1.0))));
+        }
+        views.sort_by_key(|(order, _)| *order);
+        let views = views.into_iter().map(|(_, view)| view).collect::<Vec<_>>();
+
+        let format = renderer.swapchain_format();
+        let depth_format = renderer.depth_stencil_format().filter(|_| {
+            config.depth_test
+                && renderer.is_depth_stored()
+                && renderer.samples() == SampleCount::Sample1
+        });
+        let draw = GizmoDraw {
+            command_buffer_allocator: gizmo_renderer.command_buffer_allocator.clone(),
+            queue: context.graphics_queue().clone(),
+            layout: gizmo_renderer.layout.clone(),
+            pipeline: gizmo_renderer.pipeline(format, depth_format),
+            format,
+            depth_format,
+            vertex_buffer,
+            views,
+        };
+
+        renderer.set_present_overlay(Some(Box::new(
+            move |renderer: &mut VulkanoWindowRenderer, before: Box<dyn GpuFuture>| {
+                draw_gizmos(renderer, before, &draw)
+            },
+        )));
+    }
+}
+
+/// What a window needs to draw the gizmos when it's presented.
+struct GizmoDraw {
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    queue: Arc<Queue>,
+    layout: Arc<PipelineLayout>,
+    pipeline: GizmoPipeline,
+    format: Format,
+    depth_format: Option<Format>,
+    vertex_buffer: Subbuffer<[GizmoVertex]>,
+    views: Vec<GizmoView>,
+}
+
+/// Draws the gizmos onto the image about to be presented, after `before`.
+fn draw_gizmos(
+    renderer: &mut VulkanoWindowRenderer,
+    before: Box<dyn GpuFuture>,
+    draw: &GizmoDraw,
+) -> Box<dyn GpuFuture> {
+    // The swapchain and attachments may have been recreated since the pipeline was picked.
+    if renderer.swapchain_format() != draw.format
+        || (draw.depth_format.is_some() && renderer.depth_stencil_format() != draw.depth_format)
+    {
+        return before;
+    }
+    let depth_attachment = draw
+        .depth_format
+        .and_then(|_| renderer.depth_stencil_image_view())
+        .into_iter()
+        .collect::<Vec<Arc<ImageView>>>();
+    let framebuffer = match renderer.framebuffer(&draw.pipeline.render_pass, &depth_attachment) {
+        Ok(framebuffer) => framebuffer,
+        Err(e) => {
+            error!("Failed to create the gizmo framebuffer: {e}");
+            return before;
+        }
+    };
+    let target_size = renderer.swapchain_image_size();
+    let output_in_linear_colorspace =
+        draw.format.numeric_format_color() == Some(NumericFormat::SRGB);
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        draw.command_buffer_allocator.clone(),
+        draw.queue.queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    builder
+        .begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: vec![None; 1 + depth_attachment.len()],
+                ..RenderPassBeginInfo::framebuffer(framebuffer)
+            },
+            SubpassBeginInfo {
+                contents: SubpassContents::Inline,
+                ..Default::default()
+            },
+        )
+        .unwrap()
+        .bind_pipeline_graphics(draw.pipeline.pipeline.clone())
+        .unwrap()
+        .bind_vertex_buffers(0, draw.vertex_buffer.clone())
+        .unwrap();
+
+    for (view_proj, rect) in &draw.views {
+        let Some((viewport, scissor)) = viewport_and_scissor(*rect, target_size) else {
+            continue;
+        };
+        builder
+            .set_viewport(0, [viewport].into_iter().collect())
+            .unwrap()
+            .set_scissor(0, [scissor].into_iter().collect())
+            .unwrap()
+            .push_constants(draw.layout.clone(), 0, PushConstants {
+                view_proj: view_proj.to_cols_array_2d(),
+                output_in_linear_colorspace: output_in_linear_colorspace as u32,
+            })
+            .unwrap();
+
+        unsafe {
+            builder
+                .draw(draw.vertex_buffer.len() as u32, 1, 0, 0)
+                .unwrap();
+        }
+    }
+
+    builder.end_render_pass(Default::default()).unwrap();
+
+    before
+        .then_execute(draw.queue.clone(), builder.build().unwrap())
+        .unwrap()
+        .boxed()
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+#version 450
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec4 color;
+
+layout(location = 0) out vec4 v_color;
+
+layout(push_constant) uniform PushConstants {
+    mat4 view_proj;
+    uint output_in_linear_colorspace;
+} push_constants;
+
+void main() {
+    gl_Position = push_constants.view_proj * vec4(position, 1.0);
+    v_color = color;
+}",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+#version 450
+layout(location = 0) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+layout(push_constant) uniform PushConstants {
+    mat4 view_proj;
+    uint output_in_linear_colorspace;
+} push_constants;
+
+vec3 srgb_from_linear(vec3 linear) {
+    bvec3 cutoff = lessThan(linear, vec3(0.0031308));
+    vec3 lower = linear * vec3(12.92);
+    vec3 higher = vec3(1.055) * pow(linear, vec3(1.0 / 2.4)) - vec3(0.055);
+    return mix(higher, lower, vec3(cutoff));
+}
+
+void main() {
+    vec4 color = v_color;
+    // Without an sRGB target, the conversion has to be done here.
+    if (push_constants.output_in_linear_colorspace == 0) {
+        color.rgb = srgb_from_linear(color.rgb);
+    }
+    f_color = color;
+}",
+    }
+}
@@ -0,0 +1,575 @@
+//! [egui](https://github.com/emilk/egui) for every window, enabled with the `gui` feature.
+//!
+//! [`GuiPlugin`] gives each window a [`Gui`], kept in the [`Guis`] resource. Build your ui with
+//! [`Gui::context`] anywhere in [`Update`], then draw it in your render system, between
+//! [`crate::renderer::VulkanoWindowRenderer::acquire`] and
+//! [`crate::renderer::VulkanoWindowRenderer::present`], with [`Gui::draw_on_image`] or
+//! [`Gui::draw_in_subpass`].
+
+mod painter;
+
+use std::sync::Arc;
+
+use bevy::{
+    ecs::entity::EntityHashMap,
+    input::{
+        keyboard::{Key, KeyboardInput},
+        mouse::{MouseButtonInput, MouseScrollUnit, MouseWheel},
+        ButtonState, InputSystem,
+    },
+    prelude::*,
+    window::{CursorIcon, CursorLeft, CursorMoved, WindowFocused},
+};
+pub use egui;
+use egui::{ClippedPrimitive, TexturesDelta};
+use painter::Painter;
+use vulkano::{
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        PrimaryAutoCommandBuffer,
+    },
+    descriptor_set::allocator::StandardDescriptorSetAllocator,
+    image::view::ImageView,
+    render_pass::Subpass,
+    sync::GpuFuture,
+};
+
+use crate::BevyVulkanoContext;
+
+/// Adds a [`Gui`] to every window. Add it after [`crate::VulkanoPlugin`].
+pub struct GuiPlugin;
+
+impl Plugin for GuiPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Guis>()
+            .add_systems(PreUpdate, begin_gui_frames.after(InputSystem))
+            .add_systems(Last, apply_gui_output);
+    }
+}
+
+/// The [`Gui`] of each window, keyed by window entity.
+#[derive(Resource)]
+pub struct Guis {
+    guis: EntityHashMap<Gui>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+}
+
+impl FromWorld for Guis {
+    fn from_world(world: &mut World) -> Self {
+        let context = world.resource::<BevyVulkanoContext>();
+
+        Self {
+            guis: EntityHashMap::default(),
+            command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
+                context.device().clone(),
+                Default::default(),
+            )),
+            descriptor_set_allocator: Arc::new(StandardDescriptorSetAllocator::new(
+                context.device().clone(),
+                Default::default(),
+            )),
+        }
+    }
+}
+
+impl Guis {
+    #[inline]
+    pub fn get(&self, window: Entity) -> Option<&Gui> {
+        self.guis.get(&window)
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self, window: Entity) -> Option<&mut Gui> {
+        self.guis.get_mut(&window)
+    }
+
+    /// Returns the egui context of a window.
+    #[inline]
+    pub fn context(&self, window: Entity) -> Option<egui::Context> {
+        self.guis.get(&window).map(Gui::context)
+    }
+}
+
+/// The egui context of a window, and what's needed to draw it.
+pub struct Gui {
+    context: egui::Context,
+    painter: Painter,
+    /// Input gathered since the last frame began.
+    events: Vec<egui::Event>,
+    modifiers: egui::Modifiers,
+    pointer_position: Option<egui::Pos2>,
+    focused: bool,
+    frame_running: bool,
+    /// Texture changes not yet drawn. They add up if a frame isn't drawn.
+    textures_delta: TexturesDelta,
+    primitives: Vec<ClippedPrimitive>,
+    pixels_per_point: f32,
+    cursor_icon: egui::CursorIcon,
+    /// The window's cursor visibility and icon from before egui took over the pointer, to be put
+    /// back once egui lets it go.
+    app_cursor: Option<(bool, CursorIcon)>,
+}
+
+impl Gui {
+    fn new(context: &BevyVulkanoContext, guis: &Guis) -> Self {
+        Self {
+            context: egui::Context::default(),
+            painter: Painter::new(
+                context.graphics_queue().clone(),
+                context.memory_allocator().clone(),
+                guis.command_buffer_allocator.clone(),
+                guis.descriptor_set_allocator.clone(),
+            ),
+            events: Vec::new(),
+            modifiers: egui::Modifiers::default(),
+            pointer_position: None,
+            focused: true,
+            frame_running: false,
+            textures_delta: TexturesDelta::default(),
+            primitives: Vec::new(),
+            pixels_per_point: 1.0,
+            cursor_icon: egui::CursorIcon::Default,
+            app_cursor: None,
+        }
+    }
+
+    /// The egui context, for building this frame's ui.
+    #[inline]
+    pub fn context(&self) -> egui::Context {
+        self.context.clone()
+    }
+
+    /// Ends the egui frame and records texture uploads into `builder`, then draws the ui inside a
+    /// render pass with [`Gui::draw_in_subpass`]. This must be called outside of the render pass.
+    pub fn prepare(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        self.end_frame();
+        let textures_delta = std::mem::take(&mut self.textures_delta);
+        self.painter.update_textures(builder, textures_delta);
+    }
+
+    /// Draws the ui into the current subpass of `builder`, which must be `subpass`, recorded
+    /// inline. `extent` is the size of the framebuffer. Call [`Gui::prepare`] before beginning the
+    /// render pass.
+    pub fn draw_in_subpass(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        subpass: Subpass,
+        extent: [u32; 2],
+    ) {
+        self.painter.draw_in_subpass(
+            builder,
+            subpass,
+            extent,
+            self.pixels_per_point,
+            &self.primitives,
+        );
+    }
+
+    /// Draws the ui over `image` after `before`, which is usually the swapchain image and the
+    /// future of your own rendering.
+    pub fn draw_on_image(
+        &mut self,
+        before: Box<dyn GpuFuture>,
+        image: Arc<ImageView>,
+    ) -> Box<dyn GpuFuture> {
+        self.draw(before, image, None)
+    }
+
+    /// Clears `image` to `clear_color`, then draws the ui on it after `before`.
+    pub fn draw_on_cleared_image(
+        &mut self,
+        before: Box<dyn GpuFuture>,
+        image: Arc<ImageView>,
+        clear_color: [f32; 4],
+    ) -> Box<dyn GpuFuture> {
+        self.draw(before, image, Some(clear_color))
+    }
+
+    /// Makes `view` usable in egui, for example with [`egui::Image`]. The image must have been
+    /// created with [`vulkano::image::ImageUsage::SAMPLED`]. It is sampled with the filters and
+    /// wrap mode of `options`.
+    ///
+    /// Srgb and float formats are taken to hold linear colors, and other formats gamma encoded
+    /// ones, like egui's own textures.
+    pub fn register_user_image(
+        &mut self,
+        view: Arc<ImageView>,
+        options: egui::TextureOptions,
+    ) -> egui::TextureId {
+        self.painter.register_user_image(view, options)
+    }
+
+    /// Forgets an image registered with [`Gui::register_user_image`].
+    pub fn unregister_user_image(&mut self, id: egui::TextureId) {
+        self.painter.unregister_user_image(id);
+    }
+
+    fn draw(
+        &mut self,
+        before: Box<dyn GpuFuture>,
+        image: Arc<ImageView>,
+        clear_color: Option<[f32; 4]>,
+    ) -> Box<dyn GpuFuture> {
+        self.end_frame();
+        let textures_delta = std::mem::take(&mut self.textures_delta);
+        self.painter.draw_on_image(
+            before,
+            image,
+            clear_color,
+            self.pixels_per_point,
+            textures_delta,
+            &self.primitives,
+        )
+    }
+
+    fn begin_frame(&mut self, window: &Window, time: Option<f64>) {
+        // A frame that was never drawn still has to end, its texture changes are kept.
+        self.end_frame();
+
+        let zoom_factor = self.context.zoom_factor();
+        let mut raw_input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(window.width(), window.height()) / zoom_factor,
+            )),
+            max_texture_side: Some(self.painter.max_texture_side()),
+            time,
+            modifiers: self.modifiers,
+            events: std::mem::take(&mut self.events),
+            focused: self.focused,
+            ..Default::default()
+        };
+        raw_input
+            .viewports
+            .entry(raw_input.viewport_id)
+            .or_default()
+            .native_pixels_per_point = Some(window.scale_factor());
+
+        self.context.begin_frame(raw_input);
+        self.frame_running = true;
+    }
+
+    fn end_frame(&mut self) {
+        if !self.frame_running {
+            return;
+        }
+        self.frame_running = false;
+
+        let output = self.context.end_frame();
+        self.textures_delta.append(output.textures_delta);
+        self.cursor_icon = output.platform_output.cursor_icon;
+        self.pixels_per_point = output.pixels_per_point;
+        self.primitives = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+    }
+
+    /// Converts a position in logical pixels to egui points.
+    fn to_points(&self, position: Vec2) -> egui::Pos2 {
+        egui::pos2(position.x, position.y) / self.context.zoom_factor()
+    }
+}
+
+/// Creates and removes guis with their windows, feeds them this frame's input, and begins their
+/// frames.
+#[allow(clippy::too_many_arguments)]
+fn begin_gui_frames(
+    context: Res<BevyVulkanoContext>,
+    mut guis: ResMut<Guis>,
+    windows: Query<(Entity, &Window)>,
+    time: Option<Res<Time>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut cursor_moved: EventReader<CursorMoved>,
+    mut cursor_left: EventReader<CursorLeft>,
+    mut mouse_buttons: EventReader<MouseButtonInput>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    mut keyboard: EventReader<KeyboardInput>,
+    mut focus: EventReader<WindowFocused>,
+) {
+    guis.guis.retain(|entity, _| windows.contains(*entity));
+    for (entity, _) in &windows {
+        if !guis.guis.contains_key(&entity) {
+            let gui = Gui::new(&context, &guis);
+            guis.guis.insert(entity, gui);
+        }
+    }
+
+    let modifiers = modifiers(&keys);
+    for gui in guis.guis.values_mut() {
+        gui.modifiers = modifiers;
+    }
+
+    for event in cursor_moved.read() {
+        if let Some(gui) = guis.guis.get_mut(&event.window) {
+            let position = gui.to_points(event.position);
+            gui.pointer_position = Some(position);
+            gui.events.push(egui::Event::PointerMoved(position));
+        }
+    }
+
+    for event in cursor_left.read() {
+        if let Some(gui) = guis.guis.get_mut(&event.window) {
+            gui.pointer_position = None;
+            gui.events.push(egui::Event::PointerGone);
+        }
+    }
+
+    for event in mouse_buttons.read() {
+        let Some(gui) = guis.guis.get_mut(&event.window) else {
+            continue;
+        };
+        let (Some(pos), Some(button)) = (gui.pointer_position, pointer_button(event.button)) else {
+            continue;
+        };
+        gui.events.push(egui::Event::PointerButton {
+            pos,
+            button,
+            pressed: event.state == ButtonState::Pressed,
+            modifiers,
+        });
+    }
+
+    for event in mouse_wheel.read() {
+        if let Some(gui) = guis.guis.get_mut(&event.window) {
+            gui.events.push(egui::Event::MouseWheel {
+                unit: match event.unit {
+                    MouseScrollUnit::Line => egui::MouseWheelUnit::Line,
+                    MouseScrollUnit::Pixel => egui::MouseWheelUnit::Point,
+                },
+                delta: egui::vec2(event.x, event.y),
+                modifiers,
+            });
+        }
+    }
+
+    for event in keyboard.read() {
+        let Some(gui) = guis.guis.get_mut(&event.window) else {
+            continue;
+        };
+        let pressed = event.state == ButtonState::Pressed;
+
+        if let Some(key) = egui_key(event.key_code) {
+            // There's no clipboard, but copying and cutting still work within egui.
+            if pressed && modifiers.command && key == egui::Key::C {
+                gui.events.push(egui::Event::Copy);
+            } else if pressed && modifiers.command && key == egui::Key::X {
+                gui.events.push(egui::Event::Cut);
+            }
+
+            gui.events.push(egui::Event::Key {
+                key,
+                physical_key: None,
+                pressed,
+                repeat: false,
+                modifiers,
+            });
+        }
+
+        if let Key::Character(text) = &event.logical_key {
+            let is_shortcut = modifiers.ctrl || modifiers.mac_cmd;
+            if pressed && !is_shortcut && !text.chars().any(char::is_control) {
+                gui.events.push(egui::Event::Text(text.to_string()));
+            }
+        }
+    }
+
+    for event in focus.read() {
+        if let Some(gui) = guis.guis.get_mut(&event.window) {
+            gui.focused = event.focused;
+            gui.events.push(egui::Event::WindowFocused(event.focused));
+        }
+    }
+
+    let time = time.map(|time| time.elapsed_seconds_f64());
+    for (entity, window) in &windows {
+        if let Some(gui) = guis.guis.get_mut(&entity) {
+            gui.begin_frame(window, time);
+        }
+    }
+}
+
+/// Shows the cursor egui asked for while the pointer is over egui, or egui is using it, for
+/// example to drag a slider. The window's own cursor is put back after, so apps can set
+/// [`Window::cursor`] as usual elsewhere.
+fn apply_gui_output(mut guis: ResMut<Guis>, mut windows: Query<(Entity, &mut Window)>) {
+    for (entity, mut window) in &mut windows {
+        let Some(gui) = guis.guis.get_mut(&entity) else {
+            continue;
+        };
+
+        let wants_pointer = gui.context.wants_pointer_input() || gui.context.is_pointer_over_area();
+        let (visible, icon) = if wants_pointer {
+            gui.app_cursor
+                .get_or_insert((window.cursor.visible, window.cursor.icon));
+            (
+                gui.cursor_icon != egui::CursorIcon::None,
+                cursor_icon(gui.cursor_icon),
+            )
+        } else if let Some(app_cursor) = gui.app_cursor.take() {
+            app_cursor
+        } else {
+            continue;
+        };
+        // Only write when something changed, so `Window` isn't marked as changed every frame.
+        if window.cursor.visible != visible {
+            window.cursor.visible = visible;
+        }
+        if window.cursor.icon != icon {
+            window.cursor.icon = icon;
+        }
+    }
+}
+
+fn modifiers(keys: &ButtonInput<KeyCode>) -> egui::Modifiers {
+    let alt = keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    let mac_cmd =
+        cfg!(target_os = "macos") && keys.any_pressed([KeyCode::SuperLeft, KeyCode::SuperRight]);
+
+    egui::Modifiers {
+        alt,
+        ctrl,
+        shift,
+        mac_cmd,
+        command: if cfg!(target_os = "macos") {
+            mac_cmd
+        } else {
+            ctrl
+        },
+    }
+}
+
+fn pointer_button(button: MouseButton) -> Option<egui::PointerButton> {
+    Some(match button {
+        MouseButton::Left => egui::PointerButton::Primary,
+        MouseButton::Right => egui::PointerButton::Secondary,
+        MouseButton::Middle => egui::PointerButton::Middle,
+        MouseButton::Back => egui::PointerButton::Extra1,
+        MouseButton::Forward => egui::PointerButton::Extra2,
+        MouseButton::Other(_) => return None,
+    })
+}
+
+fn egui_key(key_code: KeyCode) -> Option<egui::Key> {
+    use egui::Key;
+
+    Some(match key_code {
+        KeyCode::ArrowDown => Key::ArrowDown,
+        KeyCode::ArrowLeft => Key::ArrowLeft,
+        KeyCode::ArrowRight => Key::ArrowRight,
+        KeyCode::ArrowUp => Key::ArrowUp,
+        KeyCode::Escape => Key::Escape,
+        KeyCode::Tab => Key::Tab,
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Enter | KeyCode::NumpadEnter => Key::Enter,
+        KeyCode::Space => Key::Space,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Comma => Key::Comma,
+        KeyCode::Backslash => Key::Backslash,
+        KeyCode::Slash => Key::Slash,
+        KeyCode::BracketLeft => Key::OpenBracket,
+        KeyCode::BracketRight => Key::CloseBracket,
+        KeyCode::Backquote => Key::Backtick,
+        KeyCode::Minus | KeyCode::NumpadSubtract => Key::Minus,
+        KeyCode::Period => Key::Period,
+        KeyCode::NumpadAdd => Key::Plus,
+        KeyCode::Equal => Key::Equals,
+        KeyCode::Semicolon => Key::Semicolon,
+        KeyCode::Quote => Key::Quote,
+        KeyCode::Digit0 | KeyCode::Numpad0 => Key::Num0,
+        KeyCode::Digit1 | KeyCode::Numpad1 => Key::Num1,
+        KeyCode::Digit2 | KeyCode::Numpad2 => Key::Num2,
+        KeyCode::Digit3 | KeyCode::Numpad3 => Key::Num3,
+        KeyCode::Digit4 | KeyCode::Numpad4 => Key::Num4,
+        KeyCode::Digit5 | KeyCode::Numpad5 => Key::Num5,
+        KeyCode::Digit6 | KeyCode::Numpad6 => Key::Num6,
+        KeyCode::Digit7 | KeyCode::Numpad7 => Key::Num7,
+        KeyCode::Digit8 | KeyCode::Numpad8 => Key::Num8,
+        KeyCode::Digit9 | KeyCode::Numpad9 => Key::Num9,
+        KeyCode::KeyA => Key::A,
+        KeyCode::KeyB => Key::B,
+        KeyCode::KeyC => Key::C,
+        KeyCode::KeyD => Key::D,
+        KeyCode::KeyE => Key::E,
+        KeyCode::KeyF => Key::F,
+        KeyCode::KeyG => Key::G,
+        KeyCode::KeyH => Key::H,
+        KeyCode::KeyI => Key::I,
+        KeyCode::KeyJ => Key::J,
+        KeyCode::KeyK => Key::K,
+        KeyCode::KeyL => Key::L,
+        KeyCode::KeyM => Key::M,
+        KeyCode::KeyN => Key::N,
+        KeyCode::KeyO => Key::O,
+        KeyCode::KeyP => Key::P,
+        KeyCode::KeyQ => Key::Q,
+        KeyCode::KeyR => Key::R,
+        KeyCode::KeyS => Key::S,
+        KeyCode::KeyT => Key::T,
+        KeyCode::KeyU => Key::U,
+        KeyCode::KeyV => Key::V,
+        KeyCode::KeyW => Key::W,
+        KeyCode::KeyX => Key::X,
+        KeyCode::KeyY => Key::Y,
+        KeyCode::KeyZ => Key::Z,
+        KeyCode::F1 => Key::F1,
+        KeyCode::F2 => Key::F2,
+        KeyCode::F3 => Key::F3,
+        KeyCode::F4 => Key::F4,
+        KeyCode::F5 => Key::F5,
+        KeyCode::F6 => Key::F6,
+        KeyCode::F7 => Key::F7,
+        KeyCode::F8 => Key::F8,
+        KeyCode::F9 => Key::F9,
+        KeyCode::F10 => Key::F10,
+        KeyCode::F11 => Key::F11,
+        KeyCode::F12 => Key::F12,
+        _ => return None,
+    })
+}
+
+fn cursor_icon(icon: egui::CursorIcon) -> CursorIcon {
+    match icon {
+        egui::CursorIcon::Default | egui::CursorIcon::None => CursorIcon::Default,
+        egui::CursorIcon::ContextMenu => CursorIcon::ContextMenu,
+        egui::CursorIcon::Help => CursorIcon::Help,
+        egui::CursorIcon::PointingHand => CursorIcon::Pointer,
+        egui::CursorIcon::Progress => CursorIcon::Progress,
+        egui::CursorIcon::Wait => CursorIcon::Wait,
+        egui::CursorIcon::Cell => CursorIcon::Cell,
+        egui::CursorIcon::Crosshair => CursorIcon::Crosshair,
+        egui::CursorIcon::Text => CursorIcon::Text,
+        egui::CursorIcon::VerticalText => CursorIcon::VerticalText,
+        egui::CursorIcon::Alias => CursorIcon::Alias,
+        egui::CursorIcon::Copy => CursorIcon::Copy,
+        egui::CursorIcon::Move => CursorIcon::Move,
+        egui::CursorIcon::NoDrop => CursorIcon::NoDrop,
+        egui::CursorIcon::NotAllowed => CursorIcon::NotAllowed,
+        egui::CursorIcon::Grab => CursorIcon::Grab,
+        egui::CursorIcon::Grabbing => CursorIcon::Grabbing,
+        egui::CursorIcon::AllScroll => CursorIcon::AllScroll,
+        egui::CursorIcon::ResizeHorizontal => CursorIcon::EwResize,
+        egui::CursorIcon::ResizeNeSw => CursorIcon::NeswResize,
+        egui::CursorIcon::ResizeNwSe => CursorIcon::NwseResize,
+        egui::CursorIcon::ResizeVertical => CursorIcon::NsResize,
+        egui::CursorIcon::ResizeEast => CursorIcon::EResize,
+        egui::CursorIcon::ResizeSouthEast => CursorIcon::SeResize,
+        egui::CursorIcon::ResizeSouth => CursorIcon::SResize,
+        egui::CursorIcon::ResizeSouthWest => CursorIcon::SwResize,
+        egui::CursorIcon::ResizeWest => CursorIcon::WResize,
+        egui::CursorIcon::ResizeNorthWest => CursorIcon::NwResize,
+        egui::CursorIcon::ResizeNorth => CursorIcon::NResize,
+        egui::CursorIcon::ResizeNorthEast => CursorIcon::NeResize,
+        egui::CursorIcon::ResizeColumn => CursorIcon::ColResize,
+        egui::CursorIcon::ResizeRow => CursorIcon::RowResize,
+        egui::CursorIcon::ZoomIn => CursorIcon::ZoomIn,
+        egui::CursorIcon::ZoomOut => CursorIcon::ZoomOut,
+    }
+}
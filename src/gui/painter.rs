@@ -0,0 +1,692 @@
+use std::sync::Arc;
+
+use bevy::{log::error, utils::HashMap};
+use egui::{epaint::Primitive, ClippedPrimitive, ImageData, TextureFilter, TextureId};
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BufferImageCopy,
+        CommandBufferUsage, CopyBufferToImageInfo, PrimaryAutoCommandBuffer, RenderPassBeginInfo,
+        SubpassBeginInfo, SubpassContents,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
+    },
+    device::Queue,
+    format::{Format, NumericFormat},
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+        Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            color_blend::{
+                AttachmentBlend, BlendFactor, BlendOp, ColorBlendAttachmentState, ColorBlendState,
+            },
+            input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::{Scissor, Viewport, ViewportState},
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        DynamicState, GraphicsPipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    shader::EntryPoint,
+    sync::GpuFuture,
+};
+
+use crate::vulkano_renderers::PerSwapchainFormat;
+
+#[repr(C)]
+#[derive(BufferContents, Vertex)]
+struct GuiVertex {
+    #[format(R32G32_SFLOAT)]
+    position: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    tex_coords: [f32; 2],
+    #[format(R8G8B8A8_UNORM)]
+    color: [u8; 4],
+}
+
+#[repr(C)]
+#[derive(BufferContents, Clone, Copy)]
+struct PushConstants {
+    screen_size: [f32; 2],
+    output_in_linear_colorspace: u32,
+    texture_in_linear_colorspace: u32,
+}
+
+struct Texture {
+    view: Arc<ImageView>,
+    descriptor_set: Arc<DescriptorSet>,
+    /// Whether sampling the texture gives linear colors, rather than the gamma encoded ones egui
+    /// works with.
+    in_linear_colorspace: bool,
+}
+
+impl Texture {
+    fn new(view: Arc<ImageView>, descriptor_set: Arc<DescriptorSet>) -> Self {
+        // Srgb formats are decoded when sampled, and float formats hold linear colors. Unorm
+        // formats are taken to hold gamma encoded colors, like egui's own.
+        let in_linear_colorspace = matches!(
+            view.format().numeric_format_color(),
+            Some(NumericFormat::SRGB | NumericFormat::SFLOAT | NumericFormat::UFLOAT)
+        );
+
+        Self {
+            view,
+            descriptor_set,
+            in_linear_colorspace,
+        }
+    }
+}
+
+/// The render passes used to draw straight onto an image of some format.
+struct ImageRenderPasses {
+    load: Arc<RenderPass>,
+    clear: Arc<RenderPass>,
+}
+
+/// Draws egui meshes with vulkano.
+pub(crate) struct Painter {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    vs: EntryPoint,
+    fs: EntryPoint,
+    /// Shared by every pipeline, so textures can be bound to any of them.
+    layout: Arc<PipelineLayout>,
+    /// Pipelines by the render pass and subpass index they were made for.
+    pipelines: Vec<(Subpass, Arc<GraphicsPipeline>)>,
+    image_render_passes: PerSwapchainFormat<ImageRenderPasses>,
+    textures: HashMap<TextureId, Texture>,
+    next_user_texture: u64,
+}
+
+impl Painter {
+    pub(crate) fn new(
+        queue: Arc<Queue>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    ) -> Self {
+        let device = queue.device().clone();
+        let vs = vs::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let fs = fs::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&[
+                PipelineShaderStageCreateInfo::new(vs.clone()),
+                PipelineShaderStageCreateInfo::new(fs.clone()),
+            ])
+            .into_pipeline_layout_create_info(device)
+            .unwrap(),
+        )
+        .unwrap();
+
+        Self {
+            queue,
+            memory_allocator,
+            command_buffer_allocator,
+            descriptor_set_allocator,
+            vs,
+            fs,
+            layout,
+            pipelines: Vec::new(),
+            image_render_passes: PerSwapchainFormat::default(),
+            textures: HashMap::default(),
+            next_user_texture: 0,
+        }
+    }
+
+    pub(crate) fn max_texture_side(&self) -> usize {
+        self.queue
+            .device()
+            .physical_device()
+            .properties()
+            .max_image_dimension2_d as usize
+    }
+
+    /// Applies texture changes, recording any uploads into `builder`. Must be called outside of a
+    /// render pass.
+    pub(crate) fn update_textures(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        textures_delta: egui::TexturesDelta,
+    ) {
+        for (id, delta) in textures_delta.set {
+            let (size, pixels): ([usize; 2], Vec<[u8; 4]>) = match &delta.image {
+                ImageData::Color(image) => (
+                    image.size,
+                    image.pixels.iter().map(|color| color.to_array()).collect(),
+                ),
+                ImageData::Font(image) => (
+                    image.size,
+                    image
+                        .srgba_pixels(None)
+                        .map(|color| color.to_array())
+                        .collect(),
+                ),
+            };
+            if size.contains(&0) {
+                continue;
+            }
+
+            let staging = Buffer::from_iter(
+                self.memory_allocator.clone(),
+                BufferCreateInfo {
+                    usage: BufferUsage::TRANSFER_SRC,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+                pixels,
+            )
+            .unwrap();
+
+            let (image, offset) = match delta.pos {
+                // A partial update of an existing texture.
+                Some([x, y]) => {
+                    let Some(texture) = self.textures.get(&id) else {
+                        error!("Gui texture {id:?} was updated before it was created.");
+                        continue;
+                    };
+                    (texture.view.image().clone(), [x as u32, y as u32, 0])
+                }
+                None => {
+                    let image = Image::new(
+                        self.memory_allocator.clone(),
+                        ImageCreateInfo {
+                            image_type: ImageType::Dim2d,
+                            format: Format::R8G8B8A8_SRGB,
+                            extent: [size[0] as u32, size[1] as u32, 1],
+                            usage: ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+                            ..Default::default()
+                        },
+                        AllocationCreateInfo::default(),
+                    )
+                    .unwrap();
+                    let view = ImageView::new_default(image.clone()).unwrap();
+                    let descriptor_set = self.descriptor_set(view.clone(), delta.options);
+                    self.textures.insert(id, Texture::new(view, descriptor_set));
+                    (image, [0; 3])
+                }
+            };
+
+            builder
+                .copy_buffer_to_image(CopyBufferToImageInfo {
+                    regions: [BufferImageCopy {
+                        image_subresource: image.subresource_layers(),
+                        image_offset: offset,
+                        image_extent: [size[0] as u32, size[1] as u32, 1],
+                        ..Default::default()
+                    }]
+                    .into_iter()
+                    .collect(),
+                    ..CopyBufferToImageInfo::buffer_image(staging, image)
+                })
+                .unwrap();
+        }
+
+        // The command buffers of frames in flight keep their own references, so textures can be
+        // dropped right away.
+        for id in textures_delta.free {
+            self.textures.remove(&id);
+        }
+    }
+
+    /// Makes `view` available to egui.
+    pub(crate) fn register_user_image(
+        &mut self,
+        view: Arc<ImageView>,
+        options: egui::TextureOptions,
+    ) -> TextureId {
+        let id = TextureId::User(self.next_user_texture);
+        self.next_user_texture += 1;
+
+        let descriptor_set = self.descriptor_set(view.clone(), options);
+        self.textures.insert(id, Texture::new(view, descriptor_set));
+        id
+    }
+
+    pub(crate) fn unregister_user_image(&mut self, id: TextureId) {
+        self.textures.remove(&id);
+    }
+
+    /// Records `primitives` into the current subpass of `builder`, which must be `subpass`.
+    pub(crate) fn draw_in_subpass(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        subpass: Subpass,
+        extent: [u32; 2],
+        pixels_per_point: f32,
+        primitives: &[ClippedPrimitive],
+    ) {
+        let meshes = primitives
+            .iter()
+            .filter_map(|primitive| match &primitive.primitive {
+                Primitive::Mesh(mesh) if !mesh.indices.is_empty() => {
+                    Some((primitive.clip_rect, mesh))
+                }
+                // Paint callbacks aren't supported.
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if meshes.is_empty() || extent.contains(&0) {
+            return;
+        }
+
+        let vertices = meshes
+            .iter()
+            .flat_map(|(_, mesh)| {
+                mesh.vertices.iter().map(|vertex| GuiVertex {
+                    position: [vertex.pos.x, vertex.pos.y],
+                    tex_coords: [vertex.uv.x, vertex.uv.y],
+                    color: vertex.color.to_array(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let vertex_buffer = self.per_frame_buffer(BufferUsage::VERTEX_BUFFER, vertices);
+        let indices = meshes
+            .iter()
+            .flat_map(|(_, mesh)| mesh.indices.iter().copied())
+            .collect::<Vec<_>>();
+        let index_buffer = self.per_frame_buffer(BufferUsage::INDEX_BUFFER, indices);
+
+        let output_in_linear_colorspace = subpass_color_format(&subpass)
+            .and_then(|format| format.numeric_format_color())
+            == Some(NumericFormat::SRGB);
+        let pipeline = self.pipeline(subpass);
+        let layout = self.layout.clone();
+        let push_constants = PushConstants {
+            screen_size: [
+                extent[0] as f32 / pixels_per_point,
+                extent[1] as f32 / pixels_per_point,
+            ],
+            output_in_linear_colorspace: output_in_linear_colorspace as u32,
+            texture_in_linear_colorspace: 0,
+        };
+
+        builder
+            .set_viewport(
+                0,
+                [Viewport {
+                    offset: [0.0, 0.0],
+                    extent: [extent[0] as f32, extent[1] as f32],
+                    depth_range: 0.0..=1.0,
+                }]
+                .into_iter()
+                .collect(),
+            )
+            .unwrap()
+            .bind_pipeline_graphics(pipeline)
+            .unwrap()
+            .bind_vertex_buffers(0, vertex_buffer)
+            .unwrap()
+            .bind_index_buffer(index_buffer)
+            .unwrap();
+
+        let mut first_index = 0;
+        let mut vertex_offset = 0;
+        for (clip_rect, mesh) in meshes {
+            let index_count = mesh.indices.len() as u32;
+            let vertex_count = mesh.vertices.len() as i32;
+
+            // The clip rect is in points, clamp it to the framebuffer in pixels.
+            let min_x = (clip_rect.min.x * pixels_per_point).round().max(0.0) as u32;
+            let min_y = (clip_rect.min.y * pixels_per_point).round().max(0.0) as u32;
+            let max_x = ((clip_rect.max.x * pixels_per_point).round() as u32).min(extent[0]);
+            let max_y = ((clip_rect.max.y * pixels_per_point).round() as u32).min(extent[1]);
+
+            let texture = self.textures.get(&mesh.texture_id);
+            if let (true, Some(texture)) = (max_x > min_x && max_y > min_y, texture) {
+                builder
+                    .set_scissor(
+                        0,
+                        [Scissor {
+                            offset: [min_x, min_y],
+                            extent: [max_x - min_x, max_y - min_y],
+                        }]
+                        .into_iter()
+                        .collect(),
+                    )
+                    .unwrap()
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        layout.clone(),
+                        0,
+                        texture.descriptor_set.clone(),
+                    )
+                    .unwrap()
+                    .push_constants(layout.clone(), 0, PushConstants {
+                        texture_in_linear_colorspace: texture.in_linear_colorspace as u32,
+                        ..push_constants
+                    })
+                    .unwrap();
+
+                unsafe {
+                    builder
+                        .draw_indexed(index_count, 1, first_index, vertex_offset, 0)
+                        .unwrap();
+                }
+            }
+
+            first_index += index_count;
+            vertex_offset += vertex_count;
+        }
+    }
+
+    /// Records texture updates and `primitives` into a command buffer drawing onto `image`, and
+    /// executes it after `before`. The image is cleared first if `clear_color` is given.
+    pub(crate) fn draw_on_image(
+        &mut self,
+        before: Box<dyn GpuFuture>,
+        image: Arc<ImageView>,
+        clear_color: Option<[f32; 4]>,
+        pixels_per_point: f32,
+        textures_delta: egui::TexturesDelta,
+        primitives: &[ClippedPrimitive],
+    ) -> Box<dyn GpuFuture> {
+        let device = self.queue.device().clone();
+        let render_passes = self
+            .image_render_passes
+            .get_or_insert_with(image.format(), |format| ImageRenderPasses {
+                load: vulkano::single_pass_renderpass!(
+                    device.clone(),
+                    attachments: {
+                        color: {
+                            format: format,
+                            samples: 1,
+                            load_op: Load,
+                            store_op: Store,
+                        },
+                    },
+                    pass: {
+                        color: [color],
+                        depth_stencil: {},
+                    },
+                )
+                .unwrap(),
+                clear: vulkano::single_pass_renderpass!(
+                    device.clone(),
+                    attachments: {
+                        color: {
+                            format: format,
+                            samples: 1,
+                            load_op: Clear,
+                            store_op: Store,
+                        },
+                    },
+                    pass: {
+                        color: [color],
+                        depth_stencil: {},
+                    },
+                )
+                .unwrap(),
+            });
+        let render_pass = match clear_color {
+            Some(_) => render_passes.clear.clone(),
+            None => render_passes.load.clone(),
+        };
+
+        let extent: [u32; 2] = image.image().extent()[0..2].try_into().unwrap();
+        let framebuffer = Framebuffer::new(render_pass.clone(), FramebufferCreateInfo {
+            attachments: vec![image],
+            ..Default::default()
+        })
+        .unwrap();
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.clone(),
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        self.update_textures(&mut builder, textures_delta);
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![clear_color.map(Into::into)],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        self.draw_in_subpass(
+            &mut builder,
+            Subpass::from(render_pass, 0).unwrap(),
+            extent,
+            pixels_per_point,
+            primitives,
+        );
+        builder.end_render_pass(Default::default()).unwrap();
+
+        before
+            .then_execute(self.queue.clone(), builder.build().unwrap())
+            .unwrap()
+            .boxed()
+    }
+
+    /// A buffer that's only used for one frame.
+    fn per_frame_buffer<T: BufferContents>(
+        &self,
+        usage: BufferUsage,
+        data: Vec<T>,
+    ) -> Subbuffer<[T]> {
+        Buffer::from_iter(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            data,
+        )
+        .unwrap()
+    }
+
+    fn descriptor_set(
+        &self,
+        view: Arc<ImageView>,
+        options: egui::TextureOptions,
+    ) -> Arc<DescriptorSet> {
+        let filter = |filter| match filter {
+            TextureFilter::Nearest => Filter::Nearest,
+            TextureFilter::Linear => Filter::Linear,
+        };
+        let address_mode = match options.wrap_mode {
+            egui::TextureWrapMode::ClampToEdge => SamplerAddressMode::ClampToEdge,
+            egui::TextureWrapMode::Repeat => SamplerAddressMode::Repeat,
+            egui::TextureWrapMode::MirroredRepeat => SamplerAddressMode::MirroredRepeat,
+        };
+        let sampler = Sampler::new(self.queue.device().clone(), SamplerCreateInfo {
+            mag_filter: filter(options.magnification),
+            min_filter: filter(options.minification),
+            address_mode: [address_mode; 3],
+            ..Default::default()
+        })
+        .unwrap();
+
+        DescriptorSet::new(
+            self.descriptor_set_allocator.clone(),
+            self.layout.set_layouts()[0].clone(),
+            [WriteDescriptorSet::image_view_sampler(0, view, sampler)],
+            [],
+        )
+        .unwrap()
+    }
+
+    fn pipeline(&mut self, subpass: Subpass) -> Arc<GraphicsPipeline> {
+        if let Some((_, pipeline)) = self.pipelines.iter().find(|(existing, _)| {
+            Arc::ptr_eq(existing.render_pass(), subpass.render_pass())
+                && existing.index() == subpass.index()
+        }) {
+            return pipeline.clone();
+        }
+
+        let vertex_input_state = GuiVertex::per_vertex().definition(&self.vs).unwrap();
+        let stages = [
+            PipelineShaderStageCreateInfo::new(self.vs.clone()),
+            PipelineShaderStageCreateInfo::new(self.fs.clone()),
+        ];
+
+        let pipeline = GraphicsPipeline::new(
+            self.queue.device().clone(),
+            None,
+            GraphicsPipelineCreateInfo {
+                stages: stages.into_iter().collect(),
+                vertex_input_state: Some(vertex_input_state),
+                input_assembly_state: Some(InputAssemblyState::default()),
+                viewport_state: Some(ViewportState::default()),
+                rasterization_state: Some(RasterizationState::default()),
+                multisample_state: Some(MultisampleState {
+                    rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+                    ..Default::default()
+                }),
+                // egui outputs premultiplied alpha.
+                color_blend_state: Some(ColorBlendState::with_attachment_states(
+                    subpass.num_color_attachments(),
+                    ColorBlendAttachmentState {
+                        blend: Some(AttachmentBlend {
+                            src_color_blend_factor: BlendFactor::One,
+                            dst_color_blend_factor: BlendFactor::OneMinusSrcAlpha,
+                            color_blend_op: BlendOp::Add,
+                            src_alpha_blend_factor: BlendFactor::OneMinusDstAlpha,
+                            dst_alpha_blend_factor: BlendFactor::One,
+                            alpha_blend_op: BlendOp::Add,
+                        }),
+                        ..Default::default()
+                    },
+                )),
+                dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                    .into_iter()
+                    .collect(),
+                subpass: Some(subpass.clone().into()),
+                ..GraphicsPipelineCreateInfo::layout(self.layout.clone())
+            },
+        )
+        .unwrap();
+
+        self.pipelines.push((subpass, pipeline.clone()));
+        pipeline
+    }
+}
+
+/// The format of the first color attachment of `subpass`.
+fn subpass_color_format(subpass: &Subpass) -> Option<Format> {
+    let attachment = subpass
+        .subpass_desc()
+        .color_attachments
+        .first()?
+        .as_ref()?
+        .attachment;
+    Some(subpass.render_pass().attachments()[attachment as usize].format)
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+#version 450
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 tex_coords;
+layout(location = 2) in vec4 color;
+
+layout(location = 0) out vec4 v_color;
+layout(location = 1) out vec2 v_tex_coords;
+
+layout(push_constant) uniform PushConstants {
+    vec2 screen_size;
+    uint output_in_linear_colorspace;
+    uint texture_in_linear_colorspace;
+} push_constants;
+
+void main() {
+    gl_Position = vec4(
+        2.0 * position.x / push_constants.screen_size.x - 1.0,
+        2.0 * position.y / push_constants.screen_size.y - 1.0,
+        0.0,
+        1.0
+    );
+    v_color = color;
+    v_tex_coords = tex_coords;
+}
+"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+#version 450
+layout(location = 0) in vec4 v_color;
+layout(location = 1) in vec2 v_tex_coords;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D tex;
+
+layout(push_constant) uniform PushConstants {
+    vec2 screen_size;
+    uint output_in_linear_colorspace;
+    uint texture_in_linear_colorspace;
+} push_constants;
+
+vec3 linear_from_gamma(vec3 gamma) {
+    bvec3 cutoff = lessThan(gamma, vec3(0.04045));
+    vec3 lower = gamma / vec3(12.92);
+    vec3 higher = pow((gamma + vec3(0.055)) / vec3(1.055), vec3(2.4));
+    return mix(higher, lower, vec3(cutoff));
+}
+
+vec3 gamma_from_linear(vec3 linear) {
+    bvec3 cutoff = lessThan(linear, vec3(0.0031308));
+    vec3 lower = linear * vec3(12.92);
+    vec3 higher = vec3(1.055) * pow(linear, vec3(1.0 / 2.4)) - vec3(0.055);
+    return mix(higher, lower, vec3(cutoff));
+}
+
+void main() {
+    // egui blends in gamma space, so linear textures are encoded first.
+    vec4 texture_color = texture(tex, v_tex_coords);
+    vec4 texture_gamma = texture_color;
+    if (push_constants.texture_in_linear_colorspace == 1) {
+        texture_gamma = vec4(gamma_from_linear(texture_color.rgb), texture_color.a);
+    }
+    vec4 color_gamma = v_color * texture_gamma;
+
+    if (push_constants.output_in_linear_colorspace == 1) {
+        f_color = vec4(linear_from_gamma(color_gamma.rgb), color_gamma.a);
+    } else {
+        f_color = color_gamma;
+    }
+}
+"
+    }
+}
@@ -1,30 +1,197 @@
+use std::sync::Arc;
+
 use bevy::{ecs::entity::EntityHashMap, prelude::*};
-use renderer::VulkanoWindowRendererWithoutWindow;
-use vulkano_renderers::{create_renderer, destroy_renderer, resize, update_present_mode};
-use vulkano_util::context::VulkanoContext;
+use compute::submit_compute_tasks;
+use frame_pacing::pace_frames;
+use readback::{poll_readbacks, ReadbackFailed};
+use renderer::{OffscreenRenderer, VulkanoWindowRendererWithoutWindow};
+use transient_images::free_unused_transient_images;
+use uploader::flush_uploads;
+use vulkano::{device::physical::PhysicalDevice, Version};
+use vulkano_renderers::{
+    create_renderer, destroy_renderer, reconcile_renderers, update_effective_present_mode,
+    update_offscreen_targets,
+};
+use vulkano_util::context::{VulkanoConfig, VulkanoContext};
 
+pub mod camera;
+pub mod compute;
+pub mod frame_diagnostics;
+pub mod frame_pacing;
+#[cfg(feature = "gizmos")]
+pub mod gizmos;
+#[cfg(feature = "gui")]
+pub mod gui;
+pub mod memory_diagnostics;
+pub mod profiler;
+pub mod readback;
+pub mod render_graph;
 pub mod renderer;
+#[cfg(feature = "sprite")]
+pub mod sprite;
+pub mod transient_images;
+pub mod uploader;
 pub mod vulkano_renderers;
 
-pub use vulkano_renderers::VulkanoRenderers;
+pub use camera::{
+    viewport_and_scissor, CameraMatrices, CameraPlugin, CameraProjection, CameraTarget,
+    CameraUniform, VulkanoCamera,
+};
+pub use compute::{ComputeKernel, ComputeTask, ComputeTasks};
+pub use frame_diagnostics::WindowFrameDiagnosticsPlugin;
+pub use frame_pacing::FramePacing;
+#[cfg(feature = "gizmos")]
+pub use gizmos::{GizmoConfig, GizmoPlugin, VulkanoGizmos};
+#[cfg(feature = "gui")]
+pub use gui::{egui, Gui, GuiPlugin, Guis};
+pub use memory_diagnostics::{GpuMemoryDiagnosticsPlugin, GpuMemoryUsage};
+pub use profiler::{GpuProfiler, GpuProfilerPlugin};
+pub use readback::GpuReadback;
+pub use render_graph::{
+    GraphPass, PassContext, RenderGraphPlugin, RenderGraphResource, VulkanoRenderGraph,
+};
+pub use renderer::{
+    AttachmentOps, OffscreenRenderer, OffscreenTarget, PresentOverlay, WindowAttachments,
+    WindowRendering,
+};
+#[cfg(feature = "sprite")]
+pub use sprite::{
+    SpriteAnchor, SpritePlugin, SpriteRenderer, SpriteTexture, SpriteTextures, TextureAtlasLayout,
+    VulkanoCamera2d, VulkanoSprite, VulkanoTextureAtlas,
+};
+pub use transient_images::{TransientExtent, TransientImageInfo, TransientImagePool};
+pub use uploader::GpuUploader;
+pub use vulkano_renderers::{EffectivePresentMode, PerSwapchainFormat, VulkanoRenderers};
 
-#[derive(Resource, Deref, DerefMut, Default)]
+#[derive(Resource, Deref, DerefMut)]
 pub struct BevyVulkanoContext(VulkanoContext);
 
+impl Default for BevyVulkanoContext {
+    fn default() -> Self {
+        Self::new(VulkanoConfig::default())
+    }
+}
+
+impl BevyVulkanoContext {
+    /// Creates the context from `config`. Insert it before adding [`VulkanoPlugin`] to use your own
+    /// config.
+    pub fn new(config: VulkanoConfig) -> Self {
+        Self(VulkanoContext::new(config))
+    }
+
+    /// Whether the `dynamic_rendering` feature is enabled, needed by
+    /// [`renderer::VulkanoWindowRenderer::begin_rendering`].
+    pub fn has_dynamic_rendering(&self) -> bool {
+        self.device().enabled_features().dynamic_rendering
+    }
+}
+
+/// Asks for the `dynamic_rendering` feature in `config`, with `khr_dynamic_rendering` for devices
+/// before Vulkan 1.3, and only lets devices that support it be picked.
+pub fn request_dynamic_rendering(config: &mut VulkanoConfig) {
+    config.device_features.dynamic_rendering = true;
+    config.device_extensions.khr_dynamic_rendering = true;
+
+    let device_filter_fn = config.device_filter_fn.clone();
+    config.device_filter_fn = Arc::new(move |physical_device: &PhysicalDevice| {
+        // The extension depends on others that are core in 1.2.
+        physical_device.api_version() >= Version::V1_2
+            && physical_device.supported_features().dynamic_rendering
+            && device_filter_fn(physical_device)
+    });
+}
+
+/// Asks for the `present_id` and `present_wait` features in `config`, with their extensions, and
+/// only lets devices that support them be picked. Needed by [`FramePacing::present_wait`].
+pub fn request_present_wait(config: &mut VulkanoConfig) {
+    config.device_features.present_id = true;
+    config.device_features.present_wait = true;
+    config.device_extensions.khr_present_id = true;
+    config.device_extensions.khr_present_wait = true;
+
+    let device_filter_fn = config.device_filter_fn.clone();
+    config.device_filter_fn = Arc::new(move |physical_device: &PhysicalDevice| {
+        let supported_features = physical_device.supported_features();
+        supported_features.present_id
+            && supported_features.present_wait
+            && device_filter_fn(physical_device)
+    });
+}
+
+/// Sets up the vulkano context, window renderers and the systems around them.
+///
+/// Frames are paced by the [`FramePacing`] resource, insert it before adding the plugin to start
+/// with your own. If it asks for [`FramePacing::present_wait`] in low latency mode, the device
+/// features it needs are requested, see [`request_present_wait`].
+///
+/// Insert a [`BevyVulkanoContext`] before adding the plugin to use your own config, for example
+/// with [`request_dynamic_rendering`]. The plugin then leaves the device features to you.
+#[derive(Default)]
 pub struct VulkanoPlugin;
 
 impl Plugin for VulkanoPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<BevyVulkanoContext>()
+        let frame_pacing = *app
+            .world_mut()
+            .get_resource_or_insert_with(FramePacing::default);
+
+        if !app.world().contains_resource::<BevyVulkanoContext>() {
+            let mut config = VulkanoConfig::default();
+            if frame_pacing.low_latency && frame_pacing.present_wait {
+                request_present_wait(&mut config);
+            }
+            app.insert_resource(BevyVulkanoContext::new(config));
+        }
+
+        let enabled_features = app
+            .world()
+            .resource::<BevyVulkanoContext>()
+            .device()
+            .enabled_features();
+        if frame_pacing.present_wait
+            && !(enabled_features.present_id && enabled_features.present_wait)
+        {
+            warn!(
+                "FramePacing::present_wait needs the present_id and present_wait device features, \
+                 waiting on fences instead."
+            );
+        }
+
+        app.init_resource::<TransientImagePool>()
             .init_non_send_resource::<EntityHashMap<VulkanoWindowRendererWithoutWindow>>()
+            .init_non_send_resource::<EntityHashMap<OffscreenRenderer>>()
+            .init_non_send_resource::<GpuUploader>()
+            .init_non_send_resource::<GpuReadback>()
+            .add_event::<ReadbackFailed>()
+            .init_non_send_resource::<ComputeTasks>()
             // Systems in startup can access a renderer immediately with this, I hope.
             .add_systems(PreStartup, create_renderer)
+            // Uploads and compute tasks queued last frame (or in startup) are submitted before anything
+            // renders.
+            .add_systems(
+                PreUpdate,
+                (
+                    (flush_uploads, submit_compute_tasks).chain(),
+                    poll_readbacks,
+                    // Offscreen targets inserted in startup are ready for `Update`.
+                    update_offscreen_targets,
+                ),
+            )
             .add_systems(
                 PostUpdate,
                 (
                     create_renderer,
-                    (update_present_mode, resize).after(create_renderer),
+                    reconcile_renderers.after(create_renderer),
                     destroy_renderer,
+                    update_offscreen_targets,
+                ),
+            )
+            .add_systems(
+                Last,
+                (
+                    free_unused_transient_images,
+                    pace_frames,
+                    update_effective_present_mode,
                 ),
             );
     }
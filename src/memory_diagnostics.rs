@@ -0,0 +1,329 @@
+use std::sync::Arc;
+
+use bevy::{
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    ecs::entity::EntityHashMap,
+    prelude::*,
+};
+use vulkano::{
+    ash::vk, device::physical::PhysicalDevice, memory::MemoryHeapFlags, DeviceSize, Version,
+    VulkanObject,
+};
+
+use crate::{
+    renderer::{OffscreenRenderer, VulkanoWindowRendererWithoutWindow},
+    BevyVulkanoContext, GpuUploader, TransientImagePool,
+};
+
+const MIB: f64 = 1024.0 * 1024.0;
+
+/// Reports gpu memory usage as diagnostics, and keeps the latest numbers in the [`GpuMemoryUsage`]
+/// resource. Add it after [`crate::VulkanoPlugin`].
+///
+/// Heap usage and budget come from `VK_EXT_memory_budget`, which is used when the device supports
+/// it. Without it, only the heap sizes and the allocations made through the crate are known.
+pub struct GpuMemoryDiagnosticsPlugin {
+    /// A warning is logged when a heap's usage goes above this fraction of its budget.
+    pub warn_threshold: f64,
+}
+
+impl Default for GpuMemoryDiagnosticsPlugin {
+    fn default() -> Self {
+        Self {
+            warn_threshold: 0.9,
+        }
+    }
+}
+
+impl Plugin for GpuMemoryDiagnosticsPlugin {
+    fn build(&self, app: &mut App) {
+        let context = app.world().resource::<BevyVulkanoContext>();
+        let physical_device = context.device().physical_device().clone();
+        let heap_count = physical_device.memory_properties().memory_heaps.len();
+        let budget_query = MemoryBudgetQuery::new(&physical_device);
+        if budget_query.is_none() {
+            info!(
+                "VK_EXT_memory_budget is unavailable, gpu heap usage and budget won't be \
+                 reported. Heap sizes and the crate's own allocations still are."
+            );
+        }
+
+        for heap in 0..heap_count {
+            app.register_diagnostic(
+                Diagnostic::new(GpuMemoryUsage::heap_usage_path(heap)).with_suffix("MiB"),
+            )
+            .register_diagnostic(
+                Diagnostic::new(GpuMemoryUsage::heap_budget_path(heap)).with_suffix("MiB"),
+            );
+        }
+
+        app.insert_resource(GpuMemoryUsage::default())
+            .insert_resource(MemoryDiagnosticsState {
+                physical_device,
+                budget_query,
+                warn_threshold: self.warn_threshold,
+                over_threshold: vec![false; heap_count],
+            })
+            .register_diagnostic(
+                Diagnostic::new(GpuMemoryUsage::RENDERERS_MEMORY).with_suffix("MiB"),
+            )
+            .register_diagnostic(Diagnostic::new(GpuMemoryUsage::RENDERERS_IMAGE_COUNT))
+            .register_diagnostic(Diagnostic::new(GpuMemoryUsage::ALLOCATED_IMAGES))
+            .register_diagnostic(
+                Diagnostic::new(GpuMemoryUsage::ALLOCATED_IMAGES_MEMORY).with_suffix("MiB"),
+            )
+            .register_diagnostic(Diagnostic::new(GpuMemoryUsage::UPLOADER_ALLOCATIONS))
+            .add_systems(Last, update_gpu_memory_usage);
+    }
+}
+
+/// A memory heap of the device.
+#[derive(Clone, Debug)]
+pub struct HeapUsage {
+    /// The total size of the heap.
+    pub size: DeviceSize,
+    /// Whether the heap is device local.
+    pub device_local: bool,
+    /// How much this process can allocate from the heap before it may run into trouble, if known.
+    pub budget: Option<DeviceSize>,
+    /// How much of the heap this process uses, if known.
+    pub usage: Option<DeviceSize>,
+}
+
+/// The memory held by a window renderer, estimated from the extents and formats of its images.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RendererMemoryUsage {
+    pub swapchain_images: usize,
+    pub swapchain_bytes: DeviceSize,
+    /// The depth/stencil and multisampled color attachments.
+    pub attachments: usize,
+    pub attachment_bytes: DeviceSize,
+    pub additional_image_views: usize,
+    pub additional_image_view_bytes: DeviceSize,
+}
+
+impl RendererMemoryUsage {
+    /// All the bytes held by the renderer.
+    #[inline]
+    pub fn total_bytes(&self) -> DeviceSize {
+        self.swapchain_bytes + self.attachment_bytes + self.additional_image_view_bytes
+    }
+
+    /// All the images held by the renderer.
+    #[inline]
+    pub fn image_count(&self) -> usize {
+        self.swapchain_images + self.attachments + self.additional_image_views
+    }
+}
+
+/// The allocations made through the crate, estimated from the sizes of the buffers and the
+/// extents and formats of the images.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllocationCounts {
+    /// Buffers made by the [`GpuUploader`] since it was created, staging buffers included.
+    pub uploader_buffers: u64,
+    pub uploader_bytes: DeviceSize,
+    /// Images held by the [`TransientImagePool`].
+    pub transient_images: usize,
+    pub transient_image_bytes: DeviceSize,
+    /// Images made by the [`TransientImagePool`] since it was created, freed ones included.
+    pub transient_images_created: u64,
+    /// The depth/stencil and multisampled color attachments of all window renderers.
+    pub attachments: usize,
+    pub attachment_bytes: DeviceSize,
+    /// The images of all offscreen targets.
+    pub offscreen_images: usize,
+    pub offscreen_image_bytes: DeviceSize,
+}
+
+impl AllocationCounts {
+    /// The images currently held by the transient pool, the attachments and offscreen targets.
+    #[inline]
+    pub fn live_images(&self) -> usize {
+        self.transient_images + self.attachments + self.offscreen_images
+    }
+
+    /// The bytes of [`Self::live_images`].
+    #[inline]
+    pub fn live_image_bytes(&self) -> DeviceSize {
+        self.transient_image_bytes + self.attachment_bytes + self.offscreen_image_bytes
+    }
+}
+
+/// The latest gpu memory usage, updated every frame in [`Last`].
+#[derive(Resource, Default, Debug)]
+pub struct GpuMemoryUsage {
+    pub heaps: Vec<HeapUsage>,
+    /// The memory of each window renderer, keyed by window entity.
+    pub renderers: EntityHashMap<RendererMemoryUsage>,
+    pub allocations: AllocationCounts,
+}
+
+impl GpuMemoryUsage {
+    pub const ALLOCATED_IMAGES: DiagnosticPath =
+        DiagnosticPath::const_new("gpu_memory/allocated_images");
+    pub const ALLOCATED_IMAGES_MEMORY: DiagnosticPath =
+        DiagnosticPath::const_new("gpu_memory/allocated_images_memory");
+    pub const RENDERERS_IMAGE_COUNT: DiagnosticPath =
+        DiagnosticPath::const_new("gpu_memory/renderers_image_count");
+    pub const RENDERERS_MEMORY: DiagnosticPath = DiagnosticPath::const_new("gpu_memory/renderers");
+    pub const UPLOADER_ALLOCATIONS: DiagnosticPath =
+        DiagnosticPath::const_new("gpu_memory/uploader_allocations");
+
+    pub fn heap_usage_path(heap: usize) -> DiagnosticPath {
+        DiagnosticPath::new(format!("gpu_memory/heap_{heap}/usage"))
+    }
+
+    pub fn heap_budget_path(heap: usize) -> DiagnosticPath {
+        DiagnosticPath::new(format!("gpu_memory/heap_{heap}/budget"))
+    }
+}
+
+#[derive(Resource)]
+struct MemoryDiagnosticsState {
+    physical_device: Arc<PhysicalDevice>,
+    budget_query: Option<MemoryBudgetQuery>,
+    warn_threshold: f64,
+    /// So we only warn once each time a heap goes over the threshold.
+    over_threshold: Vec<bool>,
+}
+
+/// Vulkano doesn't expose `VK_EXT_memory_budget`, so it's queried through the instance functions
+/// vulkano loaded.
+struct MemoryBudgetQuery;
+
+impl MemoryBudgetQuery {
+    fn new(physical_device: &PhysicalDevice) -> Option<Self> {
+        (physical_device.supported_extensions().ext_memory_budget
+            && physical_device.instance().api_version() >= Version::V1_1)
+            .then_some(Self)
+    }
+
+    /// Returns the budget and usage of each heap.
+    fn query(
+        &self,
+        physical_device: &PhysicalDevice,
+    ) -> (
+        [DeviceSize; vk::MAX_MEMORY_HEAPS],
+        [DeviceSize; vk::MAX_MEMORY_HEAPS],
+    ) {
+        let mut budget = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::default();
+        let mut properties = vk::PhysicalDeviceMemoryProperties2::default().push_next(&mut budget);
+
+        // Safety: the instance is Vulkan 1.1 and the device supports `VK_EXT_memory_budget`,
+        // checked in `new`.
+        unsafe {
+            let fns = physical_device.instance().fns();
+            (fns.v1_1.get_physical_device_memory_properties2)(
+                physical_device.handle(),
+                &mut properties,
+            );
+        }
+
+        (budget.heap_budget, budget.heap_usage)
+    }
+}
+
+/// Gathers heap, renderer and allocation memory usage, and publishes it.
+fn update_gpu_memory_usage(
+    mut state: ResMut<MemoryDiagnosticsState>,
+    mut usage: ResMut<GpuMemoryUsage>,
+    mut diagnostics: Diagnostics,
+    renderers: NonSend<EntityHashMap<VulkanoWindowRendererWithoutWindow>>,
+    offscreen_targets: NonSend<EntityHashMap<OffscreenRenderer>>,
+    uploader: NonSend<GpuUploader>,
+    transient_images: Res<TransientImagePool>,
+) {
+    let budget = state
+        .budget_query
+        .as_ref()
+        .map(|query| query.query(&state.physical_device));
+
+    usage.heaps = state
+        .physical_device
+        .memory_properties()
+        .memory_heaps
+        .iter()
+        .enumerate()
+        .map(|(i, heap)| HeapUsage {
+            size: heap.size,
+            device_local: heap.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL),
+            budget: budget.map(|(budget, _)| budget[i]),
+            usage: budget.map(|(_, usage)| usage[i]),
+        })
+        .collect();
+
+    usage.renderers = renderers
+        .iter()
+        .map(|(entity, renderer)| (*entity, renderer.memory_usage()))
+        .collect();
+
+    usage.allocations = AllocationCounts {
+        uploader_buffers: uploader.allocation_count(),
+        uploader_bytes: uploader.allocated_bytes(),
+        transient_images: transient_images.len(),
+        transient_image_bytes: transient_images.memory_bytes(),
+        transient_images_created: transient_images.allocation_count(),
+        attachments: usage.renderers.values().map(|r| r.attachments).sum(),
+        attachment_bytes: usage.renderers.values().map(|r| r.attachment_bytes).sum(),
+        offscreen_images: offscreen_targets
+            .values()
+            .map(OffscreenRenderer::image_count)
+            .sum(),
+        offscreen_image_bytes: offscreen_targets
+            .values()
+            .map(OffscreenRenderer::memory_bytes)
+            .sum(),
+    };
+
+    let warn_threshold = state.warn_threshold;
+    for (i, heap) in usage.heaps.iter().enumerate() {
+        let (Some(heap_budget), Some(heap_usage)) = (heap.budget, heap.usage) else {
+            continue;
+        };
+
+        diagnostics.add_measurement(&GpuMemoryUsage::heap_usage_path(i), || {
+            heap_usage as f64 / MIB
+        });
+        diagnostics.add_measurement(&GpuMemoryUsage::heap_budget_path(i), || {
+            heap_budget as f64 / MIB
+        });
+
+        let over_threshold = heap_usage as f64 > heap_budget as f64 * warn_threshold;
+        if over_threshold && !state.over_threshold[i] {
+            warn!(
+                "Gpu memory heap {i} is using {:.1} of its {:.1} MiB budget.",
+                heap_usage as f64 / MIB,
+                heap_budget as f64 / MIB,
+            );
+        }
+        state.over_threshold[i] = over_threshold;
+    }
+
+    diagnostics.add_measurement(&GpuMemoryUsage::RENDERERS_MEMORY, || {
+        usage
+            .renderers
+            .values()
+            .map(RendererMemoryUsage::total_bytes)
+            .sum::<DeviceSize>() as f64
+            / MIB
+    });
+    diagnostics.add_measurement(&GpuMemoryUsage::RENDERERS_IMAGE_COUNT, || {
+        usage
+            .renderers
+            .values()
+            .map(RendererMemoryUsage::image_count)
+            .sum::<usize>() as f64
+    });
+
+    let allocations = usage.allocations;
+    diagnostics.add_measurement(&GpuMemoryUsage::ALLOCATED_IMAGES, || {
+        allocations.live_images() as f64
+    });
+    diagnostics.add_measurement(&GpuMemoryUsage::ALLOCATED_IMAGES_MEMORY, || {
+        allocations.live_image_bytes() as f64 / MIB
+    });
+    diagnostics.add_measurement(&GpuMemoryUsage::UPLOADER_ALLOCATIONS, || {
+        allocations.uploader_buffers as f64
+    });
+}
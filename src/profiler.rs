@@ -0,0 +1,388 @@
+use std::{collections::VecDeque, fmt::Write, sync::Arc};
+
+use bevy::{
+    diagnostic::{
+        Diagnostic, DiagnosticMeasurement, DiagnosticPath, DiagnosticsStore, RegisterDiagnostic,
+    },
+    prelude::*,
+    utils::{HashMap, Instant},
+};
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    device::Device,
+    query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
+    sync::PipelineStage,
+};
+
+use crate::BevyVulkanoContext;
+
+/// How many frames a slot of queries is kept before it is reused. Results are read when the slot
+/// comes around again, so they are never waited on.
+const FRAMES_IN_FLIGHT: usize = 3;
+
+/// Adds a [`GpuProfiler`] and publishes its results as diagnostics. Add it after
+/// [`crate::VulkanoPlugin`] and [`bevy::diagnostic::DiagnosticsPlugin`], and add
+/// [`bevy::diagnostic::LogDiagnosticsPlugin`] to see the results in the console.
+pub struct GpuProfilerPlugin {
+    /// The most scopes that can be recorded in one frame.
+    pub max_scopes_per_frame: u32,
+    /// Keep resolved scopes, so they can be exported with [`GpuProfiler::chrome_trace_json`].
+    pub record_trace: bool,
+    /// The most scopes kept for the trace. The oldest are dropped past it.
+    pub max_trace_events: usize,
+}
+
+impl Default for GpuProfilerPlugin {
+    fn default() -> Self {
+        Self {
+            max_scopes_per_frame: 64,
+            record_trace: false,
+            max_trace_events: 100_000,
+        }
+    }
+}
+
+impl Plugin for GpuProfilerPlugin {
+    fn build(&self, app: &mut App) {
+        let context = app.world().resource::<BevyVulkanoContext>();
+        let profiler = GpuProfiler::new(
+            context.device().clone(),
+            context.graphics_queue().queue_family_index(),
+            self.max_scopes_per_frame,
+            self.record_trace,
+            self.max_trace_events,
+        );
+
+        app.insert_resource(profiler)
+            .register_diagnostic(Diagnostic::new(GpuProfiler::FRAME_TIME).with_suffix("ms"))
+            .add_systems(First, resolve_gpu_profiler);
+    }
+}
+
+/// Wraps a region of a command buffer in [`GpuProfiler::begin_scope`] and
+/// [`GpuProfiler::end_scope`].
+pub struct GpuScope {
+    index: usize,
+}
+
+struct Scope {
+    name: &'static str,
+    start_query: u32,
+    end_query: Option<u32>,
+}
+
+struct FrameQueries {
+    query_pool: Arc<QueryPool>,
+    scopes: Vec<Scope>,
+    next_query: u32,
+}
+
+/// A resolved scope, for exporting.
+struct TraceEvent {
+    name: &'static str,
+    start_us: f64,
+    duration_us: f64,
+}
+
+/// Measures how long regions of your command buffers take on the gpu, using timestamp queries.
+///
+/// Each scope is published as the diagnostic `gpu/<name>` in milliseconds, without leading,
+/// trailing or repeated slashes in the name, and the time between the first and last timestamp of
+/// a frame as [`GpuProfiler::FRAME_TIME`]. Results arrive a few frames late, as they are read
+/// without stalling.
+///
+/// Scopes are only supported on the graphics queue. The first scope of a frame must begin outside
+/// of a render pass, as that is where the queries get reset.
+#[derive(Resource)]
+pub struct GpuProfiler {
+    frames: Vec<FrameQueries>,
+    current_frame: usize,
+    queries_per_frame: u32,
+    /// Nanoseconds per timestamp tick.
+    timestamp_period: f64,
+    enabled: bool,
+    record_trace: bool,
+    max_trace_events: usize,
+    trace: VecDeque<TraceEvent>,
+    /// The timestamp all trace events are relative to.
+    trace_origin: Option<u64>,
+    scope_paths: HashMap<&'static str, DiagnosticPath>,
+}
+
+impl GpuProfiler {
+    pub const FRAME_TIME: DiagnosticPath = DiagnosticPath::const_new("gpu/frame_time");
+
+    fn new(
+        device: Arc<Device>,
+        queue_family_index: u32,
+        max_scopes_per_frame: u32,
+        record_trace: bool,
+        max_trace_events: usize,
+    ) -> Self {
+        let physical_device = device.physical_device();
+        let enabled = physical_device.queue_family_properties()[queue_family_index as usize]
+            .timestamp_valid_bits
+            .is_some();
+        if !enabled {
+            warn!("The graphics queue doesn't support timestamps, gpu profiling is disabled.");
+        }
+
+        let queries_per_frame = max_scopes_per_frame * 2;
+        let frames = (0..FRAMES_IN_FLIGHT)
+            .map(|_| FrameQueries {
+                query_pool: QueryPool::new(device.clone(), QueryPoolCreateInfo {
+                    query_count: queries_per_frame,
+                    ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+                })
+                .unwrap(),
+                scopes: Vec::new(),
+                next_query: 0,
+            })
+            .collect();
+
+        Self {
+            frames,
+            current_frame: 0,
+            queries_per_frame,
+            timestamp_period: physical_device.properties().timestamp_period as f64,
+            enabled,
+            record_trace,
+            max_trace_events,
+            trace: VecDeque::new(),
+            trace_origin: None,
+            scope_paths: HashMap::default(),
+        }
+    }
+
+    /// Writes the starting timestamp of a scope into `builder`. Returns `None` if profiling is
+    /// disabled or this frame ran out of queries.
+    pub fn begin_scope(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        name: &'static str,
+    ) -> Option<GpuScope> {
+        if !self.enabled {
+            return None;
+        }
+
+        let queries_per_frame = self.queries_per_frame;
+        let frame = &mut self.frames[self.current_frame];
+        if frame.next_query + 2 > queries_per_frame {
+            warn!("Ran out of gpu profiler queries, scope {name} is skipped.");
+            return None;
+        }
+
+        unsafe {
+            // Queries must be reset before they are written.
+            if frame.next_query == 0 {
+                builder
+                    .reset_query_pool(frame.query_pool.clone(), 0..queries_per_frame)
+                    .unwrap();
+            }
+
+            builder
+                .write_timestamp(
+                    frame.query_pool.clone(),
+                    frame.next_query,
+                    PipelineStage::TopOfPipe,
+                )
+                .unwrap();
+        }
+
+        frame.scopes.push(Scope {
+            name,
+            start_query: frame.next_query,
+            end_query: None,
+        });
+        frame.next_query += 2;
+
+        Some(GpuScope {
+            index: frame.scopes.len() - 1,
+        })
+    }
+
+    /// Writes the ending timestamp of a scope into `builder`.
+    pub fn end_scope(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        scope: GpuScope,
+    ) {
+        let frame = &mut self.frames[self.current_frame];
+        let scope = &mut frame.scopes[scope.index];
+        let end_query = scope.start_query + 1;
+
+        unsafe {
+            builder
+                .write_timestamp(
+                    frame.query_pool.clone(),
+                    end_query,
+                    PipelineStage::BottomOfPipe,
+                )
+                .unwrap();
+        }
+
+        scope.end_query = Some(end_query);
+    }
+
+    /// Whether the graphics queue supports timestamps.
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the scopes recorded so far, up to [`GpuProfilerPlugin::max_trace_events`], in the
+    /// [Chrome trace event format](https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU),
+    /// which can be opened in `chrome://tracing` or [Perfetto](https://ui.perfetto.dev).
+    /// Only has content if [`GpuProfilerPlugin::record_trace`] is set.
+    pub fn chrome_trace_json(&self) -> String {
+        let mut json = String::from("{\"traceEvents\":[");
+        for (i, event) in self.trace.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let name = event.name.replace('\\', "\\\\").replace('"', "\\\"");
+            write!(
+                json,
+                r#"{{"name":"{name}","ph":"X","ts":{:.3},"dur":{:.3},"pid":0,"tid":0}}"#,
+                event.start_us, event.duration_us
+            )
+            .unwrap();
+        }
+        json.push_str("]}");
+        json
+    }
+
+    /// Drops the scopes recorded so far, for example after writing them out.
+    pub fn clear_trace(&mut self) {
+        self.trace.clear();
+        self.trace_origin = None;
+    }
+
+    /// Writes [`GpuProfiler::chrome_trace_json`] to a file.
+    pub fn write_chrome_trace(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.chrome_trace_json())
+    }
+
+    /// Moves on to the next frame's queries, reading the results of the frame that used them
+    /// before. Returns the scope durations in milliseconds, and the frame time if anything was
+    /// recorded.
+    fn next_frame(&mut self) -> (Vec<(&'static str, f64)>, Option<f64>) {
+        self.current_frame = (self.current_frame + 1) % FRAMES_IN_FLIGHT;
+        let frame = &mut self.frames[self.current_frame];
+
+        let scopes = std::mem::take(&mut frame.scopes);
+        let query_count = frame.next_query;
+        frame.next_query = 0;
+        if query_count == 0 {
+            return (Vec::new(), None);
+        }
+        let query_pool = frame.query_pool.clone();
+
+        let timestamp_period = self.timestamp_period;
+        let to_ms = |ticks: u64| ticks as f64 * timestamp_period / 1_000_000.0;
+        let mut durations = Vec::with_capacity(scopes.len());
+        let mut frame_start = u64::MAX;
+        let mut frame_end = 0;
+        let mut dropped = 0;
+
+        for scope in scopes {
+            // Scopes that were never ended are ignored. Their end query is never written, so
+            // reading it would never be ready.
+            let Some(end_query) = scope.end_query else {
+                continue;
+            };
+            let mut timestamps = [0u64; 2];
+            // Not waiting, and not allowing partial results. This frame was submitted a few frames
+            // ago, if it still isn't done, we drop the results rather than stalling.
+            match query_pool.get_results(
+                scope.start_query..end_query + 1,
+                &mut timestamps,
+                QueryResultFlags::empty(),
+            ) {
+                Ok(true) => {}
+                Ok(false) => {
+                    dropped += 1;
+                    continue;
+                }
+                Err(e) => {
+                    error!("Failed to read gpu profiler results: {e}");
+                    return (Vec::new(), None);
+                }
+            }
+            let [start, end] = timestamps;
+            frame_start = frame_start.min(start);
+            frame_end = frame_end.max(end);
+
+            let duration_ms = to_ms(end.saturating_sub(start));
+            durations.push((scope.name, duration_ms));
+
+            if self.record_trace && self.max_trace_events > 0 {
+                if self.trace.len() == self.max_trace_events {
+                    self.trace.pop_front();
+                }
+                let origin = *self.trace_origin.get_or_insert(start);
+                self.trace.push_back(TraceEvent {
+                    name: scope.name,
+                    start_us: to_ms(start.saturating_sub(origin)) * 1000.0,
+                    duration_us: duration_ms * 1000.0,
+                });
+            }
+        }
+
+        if dropped > 0 {
+            debug!("{dropped} gpu profiler results weren't ready and were dropped.");
+        }
+
+        let frame_time = (frame_end >= frame_start).then(|| to_ms(frame_end - frame_start));
+        (durations, frame_time)
+    }
+
+    fn scope_path(&mut self, name: &'static str) -> &DiagnosticPath {
+        self.scope_paths.entry(name).or_insert_with(|| {
+            // Diagnostic paths can't have empty components, so stray slashes are dropped.
+            let components = name
+                .split('/')
+                .filter(|component| !component.is_empty())
+                .collect::<Vec<_>>();
+            let name = if components.is_empty() {
+                "unnamed".to_string()
+            } else {
+                components.join("/")
+            };
+            DiagnosticPath::new(format!("gpu/{name}"))
+        })
+    }
+}
+
+/// Reads finished results and publishes them as diagnostics.
+pub fn resolve_gpu_profiler(
+    mut profiler: ResMut<GpuProfiler>,
+    mut diagnostics: ResMut<DiagnosticsStore>,
+) {
+    let (durations, frame_time) = profiler.next_frame();
+    let time = Instant::now();
+
+    for (name, duration_ms) in durations {
+        let path = profiler.scope_path(name);
+        if diagnostics.get(path).is_none() {
+            diagnostics.add(Diagnostic::new(path.clone()).with_suffix("ms"));
+        }
+
+        diagnostics
+            .get_mut(path)
+            .unwrap()
+            .add_measurement(DiagnosticMeasurement {
+                time,
+                value: duration_ms,
+            });
+    }
+
+    if let Some(frame_time) = frame_time {
+        if let Some(diagnostic) = diagnostics.get_mut(&GpuProfiler::FRAME_TIME) {
+            diagnostic.add_measurement(DiagnosticMeasurement {
+                time,
+                value: frame_time,
+            });
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::{ops::Deref, sync::Arc};
+
+use bevy::prelude::*;
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        CopyBufferInfo,
+    },
+    device::Queue,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    sync::{future::FenceSignalFuture, GpuFuture, Sharing},
+    Validated, VulkanError,
+};
+
+use crate::BevyVulkanoContext;
+
+/// Identifies a readback. It is part of the [`BufferReadback`] event, so you can tell which request
+/// the data belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ReadbackHandle(u64);
+
+/// Sent when a readback requested with [`GpuReadback::read_buffer`] has arrived.
+/// You must add this event yourself for each `T` you read back, using
+/// `app.add_event::<BufferReadback<T>>()`.
+#[derive(Event)]
+pub struct BufferReadback<T: BufferContents + Clone> {
+    pub handle: ReadbackHandle,
+    pub data: Vec<T>,
+}
+
+/// Sent instead of the data when a readback fails, to be submitted or on the gpu.
+#[derive(Event, Debug)]
+pub struct ReadbackFailed {
+    pub handle: ReadbackHandle,
+    pub error: Validated<VulkanError>,
+}
+
+struct PendingReadback {
+    handle: ReadbackHandle,
+    /// The fence of the copy, or why it couldn't be submitted.
+    fence: Result<Arc<FenceSignalFuture<Box<dyn GpuFuture>>>, Validated<VulkanError>>,
+    deliver: Box<dyn FnOnce(&mut Commands)>,
+}
+
+/// Copies buffers back from the gpu without stalling the frame.
+/// Each request records a copy into a host visible buffer on the compute queue, after the future
+/// you give it. The compute queue is only used if it can access the source: when it's in the
+/// graphics queue family, or the source was created with [`Sharing::Concurrent`] including its
+/// family. Otherwise the copy is recorded on the graphics queue, which must then own the source,
+/// as we don't do queue family ownership transfers.
+///
+/// Every frame in [`PreUpdate`] the fences of pending readbacks are polled, and the data of those
+/// that finished is delivered, so systems in [`Update`] can see it. Those that failed are sent as
+/// [`ReadbackFailed`] events instead.
+pub struct GpuReadback {
+    graphics_queue: Arc<Queue>,
+    compute_queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    pending: Vec<PendingReadback>,
+    next_handle: u64,
+}
+
+impl FromWorld for GpuReadback {
+    fn from_world(world: &mut World) -> Self {
+        let context = world.resource::<BevyVulkanoContext>();
+
+        Self {
+            graphics_queue: context.graphics_queue().clone(),
+            compute_queue: context.compute_queue().clone(),
+            memory_allocator: context.memory_allocator().clone(),
+            command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
+                context.device().clone(),
+                Default::default(),
+            )),
+            pending: Vec::new(),
+            next_handle: 0,
+        }
+    }
+}
+
+impl GpuReadback {
+    /// Reads `source` back once `after` has finished, delivering it as a [`BufferReadback<T>`]
+    /// event. `source` must have been created with [`BufferUsage::TRANSFER_SRC`].
+    ///
+    /// Returns the handle of the readback, and a future you can keep chaining your work onto.
+    /// If you have nothing to wait on, pass `sync::now(device).boxed()`.
+    pub fn read_buffer<T>(
+        &mut self,
+        source: Subbuffer<[T]>,
+        after: Box<dyn GpuFuture>,
+    ) -> (ReadbackHandle, Box<dyn GpuFuture>)
+    where
+        T: BufferContents + Clone,
+    {
+        let handle = ReadbackHandle(self.next_handle);
+        let future = self.submit(handle, source, after, move |data, commands| {
+            commands.add(move |world: &mut World| {
+                world.send_event(BufferReadback {
+                    handle,
+                    data,
+                });
+            });
+        });
+
+        (handle, future)
+    }
+
+    /// Like [`GpuReadback::read_buffer`], but the data is written into the resource `R` using
+    /// `write`. Nothing happens if the resource doesn't exist anymore.
+    pub fn read_buffer_into_resource<T, R>(
+        &mut self,
+        source: Subbuffer<[T]>,
+        after: Box<dyn GpuFuture>,
+        write: fn(&mut R, Vec<T>),
+    ) -> (ReadbackHandle, Box<dyn GpuFuture>)
+    where
+        T: BufferContents + Clone,
+        R: Resource,
+    {
+        let handle = ReadbackHandle(self.next_handle);
+        let future = self.submit(handle, source, after, move |data, commands| {
+            commands.add(move |world: &mut World| {
+                if let Some(mut resource) = world.get_resource_mut::<R>() {
+                    write(&mut resource, data);
+                }
+            });
+        });
+
+        (handle, future)
+    }
+
+    /// Like [`GpuReadback::read_buffer`], but the data is written into the component `C` of
+    /// `entity` using `write`. Nothing happens if the entity or component doesn't exist anymore.
+    pub fn read_buffer_into_component<T, C>(
+        &mut self,
+        entity: Entity,
+        source: Subbuffer<[T]>,
+        after: Box<dyn GpuFuture>,
+        write: fn(&mut C, Vec<T>),
+    ) -> (ReadbackHandle, Box<dyn GpuFuture>)
+    where
+        T: BufferContents + Clone,
+        C: Component,
+    {
+        let handle = ReadbackHandle(self.next_handle);
+        let future = self.submit(handle, source, after, move |data, commands| {
+            commands.add(move |world: &mut World| {
+                if let Some(mut component) = world.get_mut::<C>(entity) {
+                    write(&mut component, data);
+                }
+            });
+        });
+
+        (handle, future)
+    }
+
+    /// How many readbacks are still waiting on the gpu.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn submit<T>(
+        &mut self,
+        handle: ReadbackHandle,
+        source: Subbuffer<[T]>,
+        after: Box<dyn GpuFuture>,
+        deliver: impl FnOnce(Vec<T>, &mut Commands) + 'static,
+    ) -> Box<dyn GpuFuture>
+    where
+        T: BufferContents + Clone,
+    {
+        self.next_handle += 1;
+
+        let destination = Buffer::new_slice::<T>(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                    | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+                ..Default::default()
+            },
+            source.len(),
+        )
+        .unwrap();
+
+        let queue = self.queue_for(source.buffer().sharing());
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.clone(),
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        builder
+            .copy_buffer(CopyBufferInfo::buffers(source, destination.clone()))
+            .unwrap();
+        let command_buffer = builder.build().unwrap();
+
+        // A failed submission is kept pending, so that it's reported when polling.
+        let fence = after
+            .then_execute(queue.clone(), command_buffer)
+            .unwrap()
+            .boxed()
+            .then_signal_fence_and_flush()
+            .map(Arc::new);
+        let future = match &fence {
+            Ok(fence) => fence.clone().boxed(),
+            Err(_) => vulkano::sync::now(queue.device().clone()).boxed(),
+        };
+
+        self.pending.push(PendingReadback {
+            handle,
+            fence,
+            deliver: Box::new(move |commands| match destination.read() {
+                Ok(data) => deliver(data.to_vec(), commands),
+                Err(e) => error!("Failed to read back buffer: {e}"),
+            }),
+        });
+
+        future
+    }
+
+    /// The queue to copy a source with `sharing` on, see [`GpuReadback`].
+    fn queue_for<I: Deref<Target = [u32]>>(&self, sharing: &Sharing<I>) -> Arc<Queue> {
+        let compute_family = self.compute_queue.queue_family_index();
+        let usable_from_compute_queue = compute_family == self.graphics_queue.queue_family_index()
+            || match sharing {
+                Sharing::Exclusive => false,
+                Sharing::Concurrent(queue_family_indices) => {
+                    queue_family_indices.contains(&compute_family)
+                }
+            };
+
+        if usable_from_compute_queue {
+            self.compute_queue.clone()
+        } else {
+            self.graphics_queue.clone()
+        }
+    }
+
+    /// Delivers every readback whose fence has been signaled, without waiting on the rest.
+    /// Readbacks that failed to be submitted, or whose fence can't be checked, such as after the
+    /// device is lost, are sent as [`ReadbackFailed`].
+    fn poll(&mut self, commands: &mut Commands) {
+        let mut index = 0;
+        while index < self.pending.len() {
+            let signaled = match &self.pending[index].fence {
+                Ok(fence) => fence.is_signaled().map_err(Validated::Error),
+                Err(e) => Err(e.clone()),
+            };
+            let result = match signaled {
+                Ok(false) => {
+                    index += 1;
+                    continue;
+                }
+                // The fence is signaled, so this doesn't block. It lets vulkano know the copy has
+                // finished, which is needed before the buffer can be read.
+                Ok(true) => self.pending[index].fence.as_ref().unwrap().wait(None),
+                Err(e) => Err(e),
+            };
+
+            let readback = self.pending.remove(index);
+            match result {
+                Ok(()) => (readback.deliver)(commands),
+                Err(error) => {
+                    error!("Readback {:?} failed: {error}", readback.handle);
+                    let handle = readback.handle;
+                    commands.add(move |world: &mut World| {
+                        world.send_event(ReadbackFailed {
+                            handle,
+                            error,
+                        });
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Polls pending readbacks and delivers the finished ones.
+pub fn poll_readbacks(mut readback: NonSendMut<GpuReadback>, mut commands: Commands) {
+    readback.poll(&mut commands);
+}
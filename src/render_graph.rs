@@ -0,0 +1,523 @@
+use std::{sync::Arc, time::Duration};
+
+use bevy::{prelude::*, utils::HashMap};
+use vulkano::{
+    buffer::Subbuffer,
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        PrimaryAutoCommandBuffer,
+    },
+    format::Format,
+    image::view::ImageView,
+    sync::GpuFuture,
+    VulkanError,
+};
+
+use crate::{
+    renderer::VulkanoWindowRenderer, uploader::UploadHandle,
+    vulkano_renderers::reconcile_renderers, BevyVulkanoContext, ComputeTasks, GpuUploader,
+    VulkanoRenderers,
+};
+
+type RecordFn =
+    Box<dyn FnMut(&PassContext, &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>)>;
+
+/// Adds a [`VulkanoRenderGraph`], and renders it into every window in [`PostUpdate`]. Add it after
+/// [`crate::VulkanoPlugin`].
+pub struct RenderGraphPlugin;
+
+impl Plugin for RenderGraphPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_non_send_resource::<VulkanoRenderGraph>()
+            .add_systems(PostUpdate, execute_render_graph.after(reconcile_renderers));
+    }
+}
+
+/// Something a pass reads or writes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RenderGraphResource {
+    /// The swapchain image of the window being rendered. Passes writing it are what the graph
+    /// renders, every other pass only runs if they depend on it.
+    SwapchainImage,
+    /// An additional image view of the window being rendered, by key, see
+    /// [`crate::renderer::VulkanoWindowRenderer::add_additional_image_view`].
+    AdditionalImageView(usize),
+    /// An image added with [`VulkanoRenderGraph::add_image`].
+    Image(&'static str),
+    /// A buffer added with [`VulkanoRenderGraph::add_buffer`].
+    Buffer(&'static str),
+}
+
+/// A step of the render graph, recording commands into the frame's command buffer. Declare
+/// everything it reads and writes, this is what orders the passes.
+pub struct GraphPass {
+    name: &'static str,
+    reads: Vec<RenderGraphResource>,
+    writes: Vec<RenderGraphResource>,
+    window: Option<Entity>,
+    uploads: Vec<UploadHandle>,
+    keep: bool,
+    record: RecordFn,
+}
+
+impl GraphPass {
+    /// Creates a pass that records its commands with `record`. Passes are identified by `name`.
+    pub fn new(
+        name: &'static str,
+        record: impl FnMut(&PassContext, &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>)
+            + 'static,
+    ) -> Self {
+        Self {
+            name,
+            reads: Vec::new(),
+            writes: Vec::new(),
+            window: None,
+            uploads: Vec::new(),
+            keep: false,
+            record: Box::new(record),
+        }
+    }
+
+    /// Declares that the pass reads `resource`. It runs after every pass writing it.
+    pub fn with_read(mut self, resource: RenderGraphResource) -> Self {
+        self.reads.push(resource);
+        self
+    }
+
+    /// Declares that the pass writes `resource`. Passes writing the same resource run in the order
+    /// they were added. A pass that also reads what it writes, such as an overlay drawn over the
+    /// swapchain image, only depends on the writers added before it.
+    pub fn with_write(mut self, resource: RenderGraphResource) -> Self {
+        self.writes.push(resource);
+        self
+    }
+
+    /// Only runs the pass when rendering `window`. By default, it runs for every window.
+    pub fn with_window(mut self, window: Entity) -> Self {
+        self.window = Some(window);
+        self
+    }
+
+    /// Waits for an upload of [`GpuUploader`] before the frame the pass is in.
+    pub fn with_upload(mut self, upload: UploadHandle) -> Self {
+        self.uploads.push(upload);
+        self
+    }
+
+    /// Never culls the pass, even if nothing rendered reads what it writes. For passes whose
+    /// results are used elsewhere, such as read back to the cpu.
+    pub fn with_keep(mut self) -> Self {
+        self.keep = true;
+        self
+    }
+
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn runs_for(&self, window: Entity) -> bool {
+        self.window
+            .map_or(true, |pass_window| pass_window == window)
+    }
+}
+
+/// What a pass can use while recording.
+pub struct PassContext<'a> {
+    /// The window being rendered.
+    pub window: Entity,
+    /// The index of the swapchain image being rendered.
+    pub image_index: u32,
+    /// Whether the swapchain was recreated this frame, so anything made from its images, such as
+    /// framebuffers, must be made again.
+    pub swapchain_recreated: bool,
+    swapchain_image: Arc<ImageView>,
+    additional_image_views: &'a HashMap<usize, Arc<ImageView>>,
+    images: &'a HashMap<&'static str, Arc<ImageView>>,
+    buffers: &'a HashMap<&'static str, Subbuffer<[u8]>>,
+}
+
+impl PassContext<'_> {
+    #[inline]
+    pub fn swapchain_image(&self) -> &Arc<ImageView> {
+        &self.swapchain_image
+    }
+
+    #[inline]
+    pub fn swapchain_format(&self) -> Format {
+        self.swapchain_image.format()
+    }
+
+    #[inline]
+    pub fn swapchain_image_size(&self) -> [u32; 2] {
+        let [width, height, _] = self.swapchain_image.image().extent();
+        [width, height]
+    }
+
+    #[inline]
+    pub fn additional_image_view(&self, key: usize) -> Option<&Arc<ImageView>> {
+        self.additional_image_views.get(&key)
+    }
+
+    #[inline]
+    pub fn image(&self, name: &str) -> Option<&Arc<ImageView>> {
+        self.images.get(name)
+    }
+
+    #[inline]
+    pub fn buffer(&self, name: &str) -> Option<&Subbuffer<[u8]>> {
+        self.buffers.get(name)
+    }
+}
+
+/// Passes that render every window, ordered by what they read and write, instead of futures
+/// chained by hand between [`crate::renderer::VulkanoWindowRenderer::acquire`] and
+/// [`crate::renderer::VulkanoWindowRenderer::present`].
+///
+/// Each frame, in [`PostUpdate`], the passes of each window that has any are recorded in order
+/// into one command buffer, which is executed between acquiring and presenting its swapchain
+/// image. Passes that don't lead to the swapchain image are culled. Vulkano inserts the pipeline
+/// barriers and image layout transitions between the passes as they are recorded.
+///
+/// Don't render a window yourself if the graph renders it too.
+pub struct VulkanoRenderGraph {
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    passes: Vec<GraphPass>,
+    images: HashMap<&'static str, Arc<ImageView>>,
+    buffers: HashMap<&'static str, Subbuffer<[u8]>>,
+    /// The order to run the passes in, by index, without culled passes. `None` when the passes
+    /// changed since it was compiled.
+    order: Option<Vec<usize>>,
+}
+
+impl FromWorld for VulkanoRenderGraph {
+    fn from_world(world: &mut World) -> Self {
+        let context = world.resource::<BevyVulkanoContext>();
+
+        Self {
+            command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
+                context.device().clone(),
+                Default::default(),
+            )),
+            passes: Vec::new(),
+            images: HashMap::default(),
+            buffers: HashMap::default(),
+            order: None,
+        }
+    }
+}
+
+impl VulkanoRenderGraph {
+    /// Adds a pass, replacing the pass with the same name if there is one.
+    pub fn add_pass(&mut self, pass: GraphPass) {
+        match self.passes.iter_mut().find(|other| other.name == pass.name) {
+            Some(other) => *other = pass,
+            None => self.passes.push(pass),
+        }
+        self.order = None;
+    }
+
+    /// Removes the pass named `name`. Returns whether there was one.
+    pub fn remove_pass(&mut self, name: &str) -> bool {
+        let len = self.passes.len();
+        self.passes.retain(|pass| pass.name != name);
+        self.order = None;
+
+        self.passes.len() != len
+    }
+
+    /// Makes `view` available to passes as [`RenderGraphResource::Image`].
+    pub fn add_image(&mut self, name: &'static str, view: Arc<ImageView>) {
+        self.images.insert(name, view);
+    }
+
+    /// Makes `buffer` available to passes as [`RenderGraphResource::Buffer`].
+    pub fn add_buffer(&mut self, name: &'static str, buffer: Subbuffer<[u8]>) {
+        self.buffers.insert(name, buffer);
+    }
+
+    pub fn remove_image(&mut self, name: &str) -> Option<Arc<ImageView>> {
+        self.images.remove(name)
+    }
+
+    pub fn remove_buffer(&mut self, name: &str) -> Option<Subbuffer<[u8]>> {
+        self.buffers.remove(name)
+    }
+
+    /// The names of the passes that will run, in order. Fails with the names of the passes that
+    /// depend on each other in a cycle.
+    pub fn execution_order(&mut self) -> Result<Vec<&'static str>, Vec<&'static str>> {
+        let order = self.compile()?;
+        Ok(order.iter().map(|&i| self.passes[i].name).collect())
+    }
+
+    fn compile(&mut self) -> Result<&[usize], Vec<&'static str>> {
+        if self.order.is_none() {
+            self.order = Some(compile(&self.passes)?);
+        }
+
+        Ok(self.order.as_deref().unwrap())
+    }
+
+    /// Records the passes for the window of `renderer` into one command buffer.
+    fn record(
+        &mut self,
+        renderer: &VulkanoWindowRenderer,
+        window: Entity,
+        swapchain_recreated: bool,
+    ) -> Arc<PrimaryAutoCommandBuffer> {
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.clone(),
+            renderer.graphics_queue().queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        let context = PassContext {
+            window,
+            image_index: renderer.image_index(),
+            swapchain_recreated,
+            swapchain_image: renderer.swapchain_image_view(),
+            additional_image_views: renderer.additional_image_views(),
+            images: &self.images,
+            buffers: &self.buffers,
+        };
+        for &i in self.order.iter().flatten() {
+            let pass = &mut self.passes[i];
+            if pass.runs_for(window) {
+                (pass.record)(&context, &mut builder);
+            }
+        }
+
+        builder.build().unwrap()
+    }
+
+    /// Whether a pass writes the swapchain image of `window`, so the graph renders it.
+    fn renders(&self, window: Entity) -> bool {
+        self.order.as_deref().is_some_and(|order| {
+            order.iter().any(|&i| {
+                let pass = &self.passes[i];
+                pass.runs_for(window) && pass.writes.contains(&RenderGraphResource::SwapchainImage)
+            })
+        })
+    }
+
+    fn uploads(&self, window: Entity) -> Vec<UploadHandle> {
+        self.order
+            .iter()
+            .flatten()
+            .map(|&i| &self.passes[i])
+            .filter(|pass| pass.runs_for(window))
+            .flat_map(|pass| pass.uploads.iter().copied())
+            .collect()
+    }
+}
+
+/// Orders the passes so that each runs after the passes it depends on, keeping the order they were
+/// added in otherwise, and leaves out the passes nothing rendered depends on.
+fn compile(passes: &[GraphPass]) -> Result<Vec<usize>, Vec<&'static str>> {
+    let mut writers = HashMap::<RenderGraphResource, Vec<usize>>::default();
+    for (i, pass) in passes.iter().enumerate() {
+        for &resource in &pass.writes {
+            writers.entry(resource).or_default().push(i);
+        }
+    }
+
+    let mut dependencies = vec![Vec::new(); passes.len()];
+    for (i, pass) in passes.iter().enumerate() {
+        // Writers of the same resource run in the order they were added.
+        for resource in &pass.writes {
+            let resource_writers = &writers[resource];
+            let position = resource_writers.iter().position(|&j| j == i).unwrap();
+            if position > 0 {
+                dependencies[i].push(resource_writers[position - 1]);
+            }
+        }
+        // Readers see the resource once every pass has written it.
+        for resource in pass.reads.iter().filter(|&r| !pass.writes.contains(r)) {
+            dependencies[i].extend(writers.get(resource).into_iter().flatten());
+        }
+    }
+
+    let mut kept = vec![false; passes.len()];
+    let mut stack = passes
+        .iter()
+        .enumerate()
+        .filter(|(_, pass)| pass.keep || pass.writes.contains(&RenderGraphResource::SwapchainImage))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    while let Some(i) = stack.pop() {
+        if !kept[i] {
+            kept[i] = true;
+            stack.extend(&dependencies[i]);
+        }
+    }
+
+    let mut done = vec![false; passes.len()];
+    let mut order = Vec::new();
+    let kept_count = kept.iter().filter(|&&kept| kept).count();
+    while order.len() < kept_count {
+        let Some(next) = (0..passes.len())
+            .find(|&i| kept[i] && !done[i] && dependencies[i].iter().all(|&j| done[j]))
+        else {
+            return Err((0..passes.len())
+                .filter(|&i| kept[i] && !done[i])
+                .map(|i| passes[i].name)
+                .collect());
+        };
+        done[next] = true;
+        order.push(next);
+    }
+
+    Ok(order)
+}
+
+/// Renders the graph into every window that has a pass writing its swapchain image.
+pub(crate) fn execute_render_graph(
+    context: Res<BevyVulkanoContext>,
+    mut graph: NonSendMut<VulkanoRenderGraph>,
+    mut renderers: VulkanoRenderers,
+    mut uploader: NonSendMut<GpuUploader>,
+    compute_tasks: NonSend<ComputeTasks>,
+) {
+    if graph.passes.is_empty() {
+        return;
+    }
+    if let Err(cycle) = graph.compile() {
+        error!("Render graph passes depend on each other in a cycle: {cycle:?}");
+        return;
+    }
+    let graph = &mut *graph;
+
+    for (window, mut renderer) in renderers.iter_mut() {
+        if !graph.renders(window) {
+            continue;
+        }
+
+        // Before acquiring, so a failed upload skips the frame without holding an image.
+        let uploads = match graph
+            .uploads(window)
+            .into_iter()
+            .map(|upload| uploader.future(upload))
+            .collect::<Result<Vec<_>, _>>()
+        {
+            Ok(uploads) => uploads,
+            Err(e) => {
+                error!("Failed to submit the uploads of the render graph: {e}");
+                continue;
+            }
+        };
+
+        let mut swapchain_recreated = false;
+        let before = match renderer.acquire(Some(Duration::from_millis(1000)), |_| {
+            swapchain_recreated = true
+        }) {
+            Ok(before) => before,
+            Err(VulkanError::OutOfDate | VulkanError::FullScreenExclusiveModeLost) => continue,
+            Err(e) => {
+                warn!("Skipping a frame of the render graph, failed to acquire an image: {e}");
+                continue;
+            }
+        };
+        let before = uploads
+            .into_iter()
+            .flatten()
+            .fold(before, |before, upload| before.join(upload).boxed());
+        let before = compute_tasks.join(before);
+
+        let command_buffer = graph.record(&renderer, window, swapchain_recreated);
+
+        let after = before
+            .then_execute(context.graphics_queue().clone(), command_buffer)
+            .unwrap()
+            .boxed();
+        renderer.present(after, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pass(name: &'static str) -> GraphPass {
+        GraphPass::new(name, |_, _| {})
+    }
+
+    fn names(passes: &[GraphPass], order: &[usize]) -> Vec<&'static str> {
+        order.iter().map(|&i| passes[i].name).collect()
+    }
+
+    #[test]
+    fn orders_readers_after_writers() {
+        let passes = [
+            pass("composite")
+                .with_read(RenderGraphResource::Image("lighting"))
+                .with_write(RenderGraphResource::SwapchainImage),
+            pass("lighting")
+                .with_read(RenderGraphResource::Image("gbuffer"))
+                .with_write(RenderGraphResource::Image("lighting")),
+            pass("gbuffer").with_write(RenderGraphResource::Image("gbuffer")),
+        ];
+
+        let order = compile(&passes).unwrap();
+        assert_eq!(names(&passes, &order), ["gbuffer", "lighting", "composite"]);
+    }
+
+    #[test]
+    fn keeps_the_order_writers_were_added_in() {
+        let passes = [
+            pass("scene").with_write(RenderGraphResource::SwapchainImage),
+            pass("gizmos")
+                .with_read(RenderGraphResource::SwapchainImage)
+                .with_write(RenderGraphResource::SwapchainImage),
+            pass("ui")
+                .with_read(RenderGraphResource::SwapchainImage)
+                .with_write(RenderGraphResource::SwapchainImage),
+        ];
+
+        let order = compile(&passes).unwrap();
+        assert_eq!(names(&passes, &order), ["scene", "gizmos", "ui"]);
+    }
+
+    #[test]
+    fn culls_passes_nothing_rendered_depends_on() {
+        let passes = [
+            pass("unused").with_write(RenderGraphResource::Image("unused")),
+            pass("shadows").with_write(RenderGraphResource::Image("shadows")),
+            pass("readback")
+                .with_write(RenderGraphResource::Buffer("readback"))
+                .with_keep(),
+            pass("scene")
+                .with_read(RenderGraphResource::Image("shadows"))
+                .with_write(RenderGraphResource::SwapchainImage),
+        ];
+
+        let order = compile(&passes).unwrap();
+        assert_eq!(names(&passes, &order), ["shadows", "readback", "scene"]);
+    }
+
+    #[test]
+    fn reports_cycles() {
+        let passes = [
+            pass("a")
+                .with_read(RenderGraphResource::Image("b"))
+                .with_write(RenderGraphResource::Image("a")),
+            pass("b")
+                .with_read(RenderGraphResource::Image("a"))
+                .with_write(RenderGraphResource::Image("b")),
+            pass("scene")
+                .with_read(RenderGraphResource::Image("a"))
+                .with_write(RenderGraphResource::SwapchainImage),
+        ];
+
+        let cycle = compile(&passes).unwrap_err();
+        assert!(cycle.contains(&"a") && cycle.contains(&"b"));
+    }
+
+    #[test]
+    fn empty_without_swapchain_writers() {
+        let passes = [pass("offscreen").with_write(RenderGraphResource::Image("offscreen"))];
+
+        assert!(compile(&passes).unwrap().is_empty());
+    }
+}
@@ -1,19 +1,50 @@
 // A heavily tweaked VulkanoWindowRenderer. To find the changes, you can ctrl f tweak.
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    num::NonZeroU64,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use bevy::utils::HashMap;
+use bevy::{
+    log::{error, warn},
+    prelude::Component,
+    utils::HashMap,
+};
 use vulkano::{
-    device::{Device, Queue},
-    format::Format,
-    image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage},
-    memory::allocator::{AllocationCreateInfo, StandardMemoryAllocator},
-    swapchain::{self, PresentMode, Surface, Swapchain, SwapchainCreateInfo, SwapchainPresentInfo},
-    sync::{self, GpuFuture},
-    Validated, VulkanError,
+    command_buffer::{
+        AutoCommandBufferBuilder, RenderingAttachmentInfo, RenderingAttachmentResolveInfo,
+        RenderingInfo, SubpassContents,
+    },
+    device::{physical::PhysicalDevice, Device, Queue},
+    format::{ClearValue, Format, FormatFeatures},
+    image::{
+        view::ImageView, Image, ImageAspects, ImageCreateInfo, ImageFormatInfo, ImageType,
+        ImageUsage, SampleCount, SampleCounts,
+    },
+    memory::{
+        allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+        MemoryPropertyFlags,
+    },
+    pipeline::graphics::subpass::PipelineRenderingCreateInfo,
+    render_pass::{
+        AttachmentLoadOp, AttachmentStoreOp, Framebuffer, FramebufferCreateInfo, RenderPass,
+    },
+    swapchain::{
+        self, CompositeAlpha, CompositeAlphas, FullScreenExclusive, PresentFuture, PresentMode,
+        Surface, Swapchain, SwapchainCreateInfo, SwapchainPresentInfo, Win32Monitor,
+    },
+    sync::{self, future::FenceSignalFuture, GpuFuture},
+    DeviceSize, Validated, ValidationError, VulkanError,
 };
 use vulkano_util::context::VulkanoContext;
-use winit::window::Window;
+use winit::{
+    dpi::PhysicalSize,
+    monitor::VideoModeHandle,
+    window::{Fullscreen, Window},
+};
+
+use crate::{memory_diagnostics::RendererMemoryUsage, transient_images::create_transient_image};
 
 // There is no way to get an arc, or a window wrapper, or anything from bevy winit. Greedy plugin. At best we can borrow from it.
 pub struct VulkanoWindowRendererWithoutWindow {
@@ -27,48 +58,765 @@ pub struct VulkanoWindowRendererWithoutWindow {
     additional_image_views: HashMap<usize, Arc<ImageView>>,
     recreate_swapchain: bool,
     previous_frame_end: Option<Box<dyn GpuFuture>>,
+    /// The fence of the last present, also held by `previous_frame_end`, so it can be waited on.
+    previous_frame_fence: Option<Arc<FenceSignalFuture<PresentFuture<Box<dyn GpuFuture>>>>>,
+    /// Whether `VK_KHR_present_id` and `VK_KHR_present_wait` are enabled on the device.
+    uses_present_id: bool,
+    /// The id of the last present, if it was presented to the current swapchain.
+    last_present_id: Option<NonZeroU64>,
+    present_count: u64,
     image_index: u32,
+    /// The present modes asked for, in order of preference.
+    present_modes: Vec<PresentMode>,
+    /// The present mode in use, the first of `present_modes` that the surface supports.
     present_mode: PresentMode,
+    supported_present_modes: Vec<PresentMode>,
+    /// The composite alpha modes asked for, in order of preference.
+    composite_alphas: Vec<CompositeAlpha>,
+    /// The composite alpha mode in use, the first of `composite_alphas` that the surface supports.
+    composite_alpha: CompositeAlpha,
+    supported_composite_alpha: CompositeAlphas,
+    /// Whether exclusive full-screen access is wanted, see
+    /// [`VulkanoWindowRenderer::set_full_screen_exclusive`].
+    full_screen_exclusive: bool,
+    /// Whether the swapchain currently holds exclusive full-screen access.
+    has_full_screen_exclusive: bool,
+    /// The depth/stencil and multisampled color attachments asked for.
+    attachments: WindowAttachments,
+    /// Whether the depth/stencil attachment is kept after rendering, see
+    /// [`VulkanoWindowRenderer::set_depth_stored`].
+    depth_stored: bool,
+    /// The sample count in use, the closest the device supports to the one asked for.
+    samples: SampleCount,
+    depth_stencil_view: Option<Arc<ImageView>>,
+    msaa_color_view: Option<Arc<ImageView>>,
+    /// Framebuffers made by [`VulkanoWindowRenderer::framebuffer`], and the frame they were last
+    /// used in.
+    framebuffers: Vec<(Arc<Framebuffer>, u64)>,
+    /// How many images have been acquired.
+    frame_count: u64,
+    frame_stats: FrameStats,
+    last_present: Option<Instant>,
+    /// Recorded over the next presented image, see [`VulkanoWindowRenderer::set_present_overlay`].
+    present_overlay: Option<PresentOverlay>,
+}
+
+/// Commands recorded over a window's swapchain image right before it's presented. Given the
+/// renderer and the future of the frame's rendering, returns the future to present after.
+pub type PresentOverlay =
+    Box<dyn FnOnce(&mut VulkanoWindowRenderer, Box<dyn GpuFuture>) -> Box<dyn GpuFuture>>;
+
+/// Attachments a window renderer makes for you, and recreates with its swapchain. Insert it on a
+/// window entity, or set it with [`VulkanoWindowRenderer::set_attachments`].
+///
+/// Their contents don't outlive a frame: they are made with [`ImageUsage::TRANSIENT_ATTACHMENT`], so
+/// use `DontCare` as their store op. The exception is a depth/stencil attachment kept for an overlay
+/// with [`VulkanoWindowRenderer::set_depth_stored`], which has to be stored with `Store`.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowAttachments {
+    /// Make a depth attachment, in the first supported of `D32_SFLOAT`, `X8_D24_UNORM_PACK32` and
+    /// `D16_UNORM`.
+    pub depth: bool,
+    /// Give the depth attachment a stencil aspect, in the first supported of
+    /// `D24_UNORM_S8_UINT`, `D32_SFLOAT_S8_UINT` and `D16_UNORM_S8_UINT`.
+    pub stencil: bool,
+    /// The sample count of the color and depth attachments. With more than one, a multisampled
+    /// color attachment is made, to be resolved into the swapchain image. If the device doesn't
+    /// support it, the closest lower count is used.
+    pub samples: SampleCount,
+}
+
+impl Default for WindowAttachments {
+    fn default() -> Self {
+        Self {
+            depth: false,
+            stencil: false,
+            samples: SampleCount::Sample1,
+        }
+    }
+}
+
+impl WindowAttachments {
+    /// A depth attachment, without multisampling.
+    pub fn depth() -> Self {
+        Self {
+            depth: true,
+            ..Default::default()
+        }
+    }
+
+    /// A depth attachment and a multisampled color attachment with `samples`.
+    pub fn depth_msaa(samples: SampleCount) -> Self {
+        Self {
+            depth: true,
+            samples,
+            ..Default::default()
+        }
+    }
+}
+
+/// How an attachment is loaded at the start of [`VulkanoWindowRenderer::begin_rendering`], and
+/// stored at its end.
+#[derive(Clone, Copy, Debug)]
+pub struct AttachmentOps {
+    pub load_op: AttachmentLoadOp,
+    pub store_op: AttachmentStoreOp,
+    /// The value to clear to, when `load_op` is `Clear`.
+    pub clear_value: Option<ClearValue>,
+}
+
+impl AttachmentOps {
+    /// Clear to `clear_value`, and keep what is rendered.
+    pub fn clear(clear_value: impl Into<ClearValue>) -> Self {
+        Self {
+            load_op: AttachmentLoadOp::Clear,
+            store_op: AttachmentStoreOp::Store,
+            clear_value: Some(clear_value.into()),
+        }
+    }
+
+    /// Keep the previous contents, and what is rendered.
+    pub fn load() -> Self {
+        Self {
+            load_op: AttachmentLoadOp::Load,
+            store_op: AttachmentStoreOp::Store,
+            clear_value: None,
+        }
+    }
+
+    /// Clear to `clear_value`, and throw away what is rendered once the rendering ends. Use it for
+    /// the attachments of [`WindowAttachments`], unless the depth/stencil attachment is stored, see
+    /// [`VulkanoWindowRenderer::set_depth_stored`].
+    pub fn clear_and_discard(clear_value: impl Into<ClearValue>) -> Self {
+        Self {
+            store_op: AttachmentStoreOp::DontCare,
+            ..Self::clear(clear_value)
+        }
+    }
+}
+
+/// The attachments of [`VulkanoWindowRenderer::begin_rendering`], besides the window's color
+/// image.
+#[derive(Clone, Debug)]
+pub struct WindowRendering {
+    color: AttachmentOps,
+    depth_stencil: Option<(Arc<ImageView>, AttachmentOps)>,
+    color_attachments: Vec<(Arc<ImageView>, AttachmentOps)>,
+}
+
+impl WindowRendering {
+    /// Render into the window's color image, with `color` as its ops.
+    pub fn new(color: AttachmentOps) -> Self {
+        Self {
+            color,
+            depth_stencil: None,
+            color_attachments: Vec::new(),
+        }
+    }
+
+    /// Add a depth and/or stencil attachment, such as
+    /// [`VulkanoWindowRenderer::depth_stencil_image_view`]. Its ops are used for both aspects.
+    pub fn with_depth_stencil(mut self, image_view: Arc<ImageView>, ops: AttachmentOps) -> Self {
+        self.depth_stencil = Some((image_view, ops));
+        self
+    }
+
+    /// Add a color attachment after the window's color image, such as
+    /// [`VulkanoWindowRenderer::get_additional_image_view`]. They are in the order they are added.
+    pub fn with_color_attachment(mut self, image_view: Arc<ImageView>, ops: AttachmentOps) -> Self {
+        self.color_attachments.push((image_view, ops));
+        self
+    }
+}
+
+/// An image to render into instead of a window, such as a security camera monitor or a portal.
+/// Insert it on any entity, and its images are made (and remade when it changes) in an
+/// [`OffscreenRenderer`], see [`crate::VulkanoRenderers::get_offscreen_target`]. Targets the
+/// device can't make images for, because of their extent, format or usage, are skipped with a
+/// warning.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct OffscreenTarget {
+    pub extent: [u32; 2],
+    pub format: Format,
+    /// Usage on top of the attachment and sampled usage every target has.
+    pub usage: ImageUsage,
+    /// With more than one, a multisampled image is rendered into and resolved into the sampled
+    /// image. Only color formats can be multisampled. If the device doesn't support it, the
+    /// closest lower count is used.
+    pub samples: SampleCount,
+}
+
+impl OffscreenTarget {
+    /// A target without multisampling.
+    pub fn new(extent: [u32; 2], format: Format) -> Self {
+        Self {
+            extent,
+            format,
+            usage: ImageUsage::empty(),
+            samples: SampleCount::Sample1,
+        }
+    }
+
+    pub fn with_usage(mut self, usage: ImageUsage) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn with_samples(mut self, samples: SampleCount) -> Self {
+        self.samples = samples;
+        self
+    }
+}
+
+/// The images of an [`OffscreenTarget`].
+pub struct OffscreenRenderer {
+    target: OffscreenTarget,
+    samples: SampleCount,
+    render_target: Arc<ImageView>,
+    /// The single sampled image, the same as `render_target` without multisampling.
+    sampled: Arc<ImageView>,
+}
+
+impl OffscreenRenderer {
+    /// Makes the images of `target`. Fails if the device can't make them, because of the extent,
+    /// or the format not supporting the usage.
+    pub(crate) fn new(
+        memory_allocator: &Arc<StandardMemoryAllocator>,
+        physical_device: &PhysicalDevice,
+        target: OffscreenTarget,
+    ) -> Result<Self, Box<ValidationError>> {
+        let is_depth_stencil = target
+            .format
+            .aspects()
+            .intersects(ImageAspects::DEPTH | ImageAspects::STENCIL);
+        let attachment_usage = if is_depth_stencil {
+            ImageUsage::DEPTH_STENCIL_ATTACHMENT
+        } else {
+            ImageUsage::COLOR_ATTACHMENT
+        };
+        let usage = target.usage | attachment_usage | ImageUsage::SAMPLED;
+        validate_offscreen_target(physical_device, &target, usage)?;
+
+        let samples = if is_depth_stencil {
+            if target.samples != SampleCount::Sample1 {
+                warn!("Offscreen targets of depth/stencil formats can't be multisampled.");
+            }
+            SampleCount::Sample1
+        } else {
+            choose_sample_count(
+                target.samples,
+                physical_device.properties().framebuffer_color_sample_counts,
+            )
+        };
+
+        let sampled = ImageView::new_default(
+            Image::new(
+                memory_allocator.clone(),
+                ImageCreateInfo {
+                    image_type: ImageType::Dim2d,
+                    format: target.format,
+                    extent: [target.extent[0], target.extent[1], 1],
+                    usage,
+                    ..Default::default()
+                },
+                AllocationCreateInfo::default(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let render_target = if samples == SampleCount::Sample1 {
+            sampled.clone()
+        } else {
+            create_transient_attachment(
+                memory_allocator,
+                target.format,
+                target.extent,
+                samples,
+                attachment_usage,
+            )
+        };
+
+        Ok(Self {
+            target,
+            samples,
+            render_target,
+            sampled,
+        })
+    }
+
+    /// The target the images were made for.
+    #[inline]
+    pub fn target(&self) -> OffscreenTarget {
+        self.target
+    }
+
+    /// The sample count of [`Self::render_target`]. This can be lower than the one asked for, if
+    /// the device doesn't support it.
+    #[inline]
+    pub fn samples(&self) -> SampleCount {
+        self.samples
+    }
+
+    #[inline]
+    pub fn extent(&self) -> [u32; 2] {
+        self.target.extent
+    }
+
+    #[inline]
+    pub fn format(&self) -> Format {
+        self.target.format
+    }
+
+    /// The image to render into. When multisampled, resolve it into
+    /// [`Self::resolve_target`], its contents don't outlive the render pass.
+    #[inline]
+    pub fn render_target(&self) -> Arc<ImageView> {
+        self.render_target.clone()
+    }
+
+    /// The image to resolve [`Self::render_target`] into, if it is multisampled.
+    #[inline]
+    pub fn resolve_target(&self) -> Option<Arc<ImageView>> {
+        (self.samples != SampleCount::Sample1).then(|| self.sampled.clone())
+    }
+
+    /// The image to sample from in later passes, once rendered (and resolved).
+    #[inline]
+    pub fn sampled_image_view(&self) -> Arc<ImageView> {
+        self.sampled.clone()
+    }
+
+    /// How many images the target holds, two when multisampled.
+    #[inline]
+    pub fn image_count(&self) -> usize {
+        if self.samples == SampleCount::Sample1 {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Estimates the memory held by the images, from their extents, formats and sample counts.
+    pub fn memory_bytes(&self) -> DeviceSize {
+        let mut bytes = image_bytes(&self.sampled);
+        if self.samples != SampleCount::Sample1 {
+            bytes += image_bytes(&self.render_target);
+        }
+        bytes
+    }
+}
+
+/// Timing and swapchain statistics of a window renderer, kept up to date by
+/// [`VulkanoWindowRenderer::acquire`] and [`VulkanoWindowRenderer::present`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    /// How long the last acquire blocked waiting for a swapchain image.
+    pub acquire_wait: Duration,
+    /// Time between the last two presents.
+    pub present_interval: Duration,
+    /// How many times the swapchain has been recreated.
+    pub swapchain_recreations: u32,
+    /// How many times acquiring or presenting found the swapchain out of date.
+    pub out_of_date: u32,
+    /// How many times the swapchain was suboptimal for the surface.
+    pub suboptimal: u32,
+    /// How many frames were started, but never made it to the screen.
+    pub skipped_frames: u32,
 }
 
 impl VulkanoWindowRendererWithoutWindow {
     /// Creates a new [`VulkanoWindowRendererWithoutWindow`] which is used to orchestrate your rendering with
     /// Vulkano. Pass [`WindowDescriptor`] and optionally a function modifying the
     /// [`SwapchainCreateInfo`] parameters.
+    ///
+    /// `present_modes` are tried in order, the first one the surface supports is used. If none are
+    /// supported, [`PresentMode::Fifo`] is used, as it always is. `composite_alphas` work the same
+    /// way, see [`composite_alpha_fallbacks`].
     pub fn new(
         vulkano_context: &VulkanoContext,
         // tweak
         window: &Window,
         // tweak
-        present_mode: PresentMode,
+        present_modes: &[PresentMode],
+        // tweak
+        composite_alphas: &[CompositeAlpha],
         swapchain_create_info_modify: fn(&mut SwapchainCreateInfo),
     ) -> Self {
         // tweak
         //let window = Arc::new(window);
 
         // Create swap chain & frame(s) to which we'll render
-        let (swap_chain, final_views) = VulkanoWindowRenderer::create_swapchain(
-            vulkano_context.device().clone(),
-            window,
-            present_mode,
-            swapchain_create_info_modify,
-        );
+        let (swap_chain, final_views, supported_present_modes, supported_composite_alpha) =
+            VulkanoWindowRenderer::create_swapchain(
+                vulkano_context.device().clone(),
+                window,
+                present_modes,
+                composite_alphas,
+                swapchain_create_info_modify,
+            );
 
         let previous_frame_end = Some(sync::now(vulkano_context.device().clone()).boxed());
+        let enabled_features = vulkano_context.device().enabled_features();
 
         Self {
             graphics_queue: vulkano_context.graphics_queue().clone(),
             compute_queue: vulkano_context.compute_queue().clone(),
+            present_mode: swap_chain.present_mode(),
+            composite_alpha: swap_chain.composite_alpha(),
             swapchain: swap_chain,
             final_views,
             memory_allocator: vulkano_context.memory_allocator().clone(),
             additional_image_views: HashMap::default(),
             recreate_swapchain: false,
             previous_frame_end,
+            previous_frame_fence: None,
+            uses_present_id: enabled_features.present_id && enabled_features.present_wait,
+            last_present_id: None,
+            present_count: 0,
             image_index: 0,
-            present_mode,
+            present_modes: present_modes.to_vec(),
+            supported_present_modes,
+            composite_alphas: composite_alphas.to_vec(),
+            supported_composite_alpha,
+            full_screen_exclusive: false,
+            has_full_screen_exclusive: false,
+            attachments: WindowAttachments::default(),
+            depth_stored: false,
+            samples: SampleCount::Sample1,
+            depth_stencil_view: None,
+            msaa_color_view: None,
+            framebuffers: Vec::new(),
+            frame_count: 0,
+            frame_stats: FrameStats::default(),
+            last_present: None,
+            present_overlay: None,
         }
     }
+
+    /// Returns the timing and swapchain statistics of this renderer.
+    #[inline]
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
+    /// The present mode in use.
+    #[inline]
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+
+    /// The composite alpha mode in use.
+    #[inline]
+    pub fn composite_alpha(&self) -> CompositeAlpha {
+        self.composite_alpha
+    }
+
+    /// Whether presents are tagged with ids that [`Self::wait_for_previous_frame`] can wait on.
+    /// This needs the `present_id` and `present_wait` features enabled on the device.
+    #[inline]
+    pub fn uses_present_id(&self) -> bool {
+        self.uses_present_id
+    }
+
+    /// Blocks until the last presented frame has finished rendering. With `present_wait`, and
+    /// [`Self::uses_present_id`], waits until it has been shown instead.
+    pub fn wait_for_previous_frame(&self, present_wait: bool) {
+        if present_wait {
+            if let Some(present_id) = self.last_present_id {
+                // A minimized window may never show the frame, so don't wait forever.
+                match self
+                    .swapchain
+                    .wait_for_present(present_id, Some(Duration::from_millis(100)))
+                    .map_err(Validated::unwrap)
+                {
+                    Ok(_) | Err(VulkanError::Timeout) | Err(VulkanError::OutOfDate) => {}
+                    Err(e) => error!("Failed to wait for present: {e}"),
+                }
+                return;
+            }
+        }
+
+        if let Some(fence) = &self.previous_frame_fence {
+            fence.wait(None).unwrap_or_else(|e| error!("{e}"));
+        }
+    }
+
+    /// Estimates the memory held by the swapchain images, attachments and additional image views,
+    /// from their extents, formats and sample counts.
+    pub fn memory_usage(&self) -> RendererMemoryUsage {
+        let attachments = self
+            .depth_stencil_view
+            .iter()
+            .chain(&self.msaa_color_view)
+            .collect::<Vec<_>>();
+
+        RendererMemoryUsage {
+            swapchain_images: self.final_views.len(),
+            swapchain_bytes: self.final_views.iter().map(|view| image_bytes(view)).sum(),
+            attachments: attachments.len(),
+            attachment_bytes: attachments.iter().map(|view| image_bytes(view)).sum(),
+            additional_image_views: self.additional_image_views.len(),
+            additional_image_view_bytes: self
+                .additional_image_views
+                .values()
+                .map(|view| image_bytes(view))
+                .sum(),
+        }
+    }
+}
+
+/// The present modes to try, in order, when `present_mode` is asked for. [`PresentMode::Fifo`] is
+/// last, as it is the only mode that is always supported.
+///
+/// - `Mailbox` falls back to `Immediate`, then `Fifo`.
+/// - `Immediate` falls back to `Mailbox`, then `Fifo`.
+/// - `FifoRelaxed`, `SharedDemandRefresh` and `SharedContinuousRefresh` fall back to `Fifo`.
+pub fn present_mode_fallbacks(present_mode: PresentMode) -> &'static [PresentMode] {
+    match present_mode {
+        PresentMode::Mailbox => &[
+            PresentMode::Mailbox,
+            PresentMode::Immediate,
+            PresentMode::Fifo,
+        ],
+        PresentMode::Immediate => &[
+            PresentMode::Immediate,
+            PresentMode::Mailbox,
+            PresentMode::Fifo,
+        ],
+        PresentMode::FifoRelaxed => &[PresentMode::FifoRelaxed, PresentMode::Fifo],
+        PresentMode::SharedDemandRefresh => &[PresentMode::SharedDemandRefresh, PresentMode::Fifo],
+        PresentMode::SharedContinuousRefresh => {
+            &[PresentMode::SharedContinuousRefresh, PresentMode::Fifo]
+        }
+        _ => &[PresentMode::Fifo],
+    }
+}
+
+/// The composite alpha modes to try, in order, when `composite_alpha` is asked for. The
+/// transparent modes fall back to each other, and to `Inherit`, which leaves it to the platform.
+///
+/// - `Opaque` falls back to `Inherit`.
+/// - `PreMultiplied` falls back to `PostMultiplied`, then `Inherit`.
+/// - `PostMultiplied` falls back to `PreMultiplied`, then `Inherit`.
+/// - `Inherit` falls back to `PreMultiplied`, then `PostMultiplied`.
+pub fn composite_alpha_fallbacks(composite_alpha: CompositeAlpha) -> &'static [CompositeAlpha] {
+    match composite_alpha {
+        CompositeAlpha::PreMultiplied => &[
+            CompositeAlpha::PreMultiplied,
+            CompositeAlpha::PostMultiplied,
+            CompositeAlpha::Inherit,
+        ],
+        CompositeAlpha::PostMultiplied => &[
+            CompositeAlpha::PostMultiplied,
+            CompositeAlpha::PreMultiplied,
+            CompositeAlpha::Inherit,
+        ],
+        CompositeAlpha::Inherit => &[
+            CompositeAlpha::Inherit,
+            CompositeAlpha::PreMultiplied,
+            CompositeAlpha::PostMultiplied,
+        ],
+        _ => &[CompositeAlpha::Opaque, CompositeAlpha::Inherit],
+    }
+}
+
+/// Picks the first of `composite_alphas` that is supported, or any supported mode. The surface
+/// always supports at least one.
+fn choose_composite_alpha(
+    composite_alphas: &[CompositeAlpha],
+    supported: CompositeAlphas,
+) -> CompositeAlpha {
+    composite_alphas
+        .iter()
+        .copied()
+        .find(|&composite_alpha| supported.contains_enum(composite_alpha))
+        .or_else(|| supported.into_iter().next())
+        .unwrap_or(CompositeAlpha::Opaque)
+}
+
+/// The video mode of `size` with the highest refresh rate, then bit depth. `None` if `video_modes`
+/// has none of that size.
+pub fn best_video_mode(
+    video_modes: impl Iterator<Item = VideoModeHandle>,
+    size: PhysicalSize<u32>,
+) -> Option<VideoModeHandle> {
+    best_of_size(video_modes, size, |video_mode| {
+        (
+            video_mode.size(),
+            video_mode.refresh_rate_millihertz(),
+            video_mode.bit_depth(),
+        )
+    })
+}
+
+/// [`best_video_mode`] over anything with a size, refresh rate and bit depth, as winit's video
+/// modes can only come from a monitor.
+fn best_of_size<T>(
+    video_modes: impl Iterator<Item = T>,
+    size: PhysicalSize<u32>,
+    properties: impl Fn(&T) -> (PhysicalSize<u32>, u32, u16),
+) -> Option<T> {
+    video_modes
+        .filter(|video_mode| properties(video_mode).0 == size)
+        .max_by_key(|video_mode| {
+            let (_, refresh_rate_millihertz, bit_depth) = properties(video_mode);
+            (refresh_rate_millihertz, bit_depth)
+        })
+}
+
+/// Picks the first of `present_modes` that is supported, or [`PresentMode::Fifo`].
+fn choose_present_mode(present_modes: &[PresentMode], supported: &[PresentMode]) -> PresentMode {
+    present_modes
+        .iter()
+        .copied()
+        .find(|present_mode| supported.contains(present_mode))
+        .unwrap_or(PresentMode::Fifo)
+}
+
+pub(crate) fn image_bytes(view: &ImageView) -> DeviceSize {
+    let image = view.image();
+    let [width, height, depth] = image.extent();
+    width as DeviceSize
+        * height as DeviceSize
+        * depth as DeviceSize
+        * image.array_layers() as DeviceSize
+        * image.format().block_size()
+        * image.samples() as DeviceSize
+}
+
+/// The first depth/stencil format listed in [`WindowAttachments`] that the device can attach.
+fn choose_depth_stencil_format(physical_device: &PhysicalDevice, stencil: bool) -> Option<Format> {
+    let formats: &[Format] = if stencil {
+        &[
+            Format::D24_UNORM_S8_UINT,
+            Format::D32_SFLOAT_S8_UINT,
+            Format::D16_UNORM_S8_UINT,
+        ]
+    } else {
+        &[
+            Format::D32_SFLOAT,
+            Format::X8_D24_UNORM_PACK32,
+            Format::D16_UNORM,
+        ]
+    };
+
+    formats.iter().copied().find(|&format| {
+        physical_device
+            .format_properties(format)
+            .is_ok_and(|properties| {
+                properties
+                    .optimal_tiling_features
+                    .intersects(FormatFeatures::DEPTH_STENCIL_ATTACHMENT)
+            })
+    })
+}
+
+/// Checks that the device can make an image of `target` with `usage`, which `Image::new` would
+/// otherwise panic on, and render to it.
+fn validate_offscreen_target(
+    physical_device: &PhysicalDevice,
+    target: &OffscreenTarget,
+    usage: ImageUsage,
+) -> Result<(), Box<ValidationError>> {
+    if target.extent.contains(&0) {
+        return Err(Box::new(ValidationError {
+            context: "target.extent".into(),
+            problem: "is zero in some dimension".into(),
+            ..Default::default()
+        }));
+    }
+
+    let format_properties = physical_device
+        .image_format_properties(ImageFormatInfo {
+            format: target.format,
+            image_type: ImageType::Dim2d,
+            usage,
+            ..Default::default()
+        })
+        .map_err(|e| match e {
+            Validated::ValidationError(e) => e,
+            Validated::Error(e) => Box::new(ValidationError {
+                context: "target.format".into(),
+                problem: format!("couldn't be queried: {e}").into(),
+                ..Default::default()
+            }),
+        })?;
+    let Some(format_properties) = format_properties else {
+        return Err(Box::new(ValidationError {
+            context: "target.format".into(),
+            problem: format!("{:?} doesn't support the {usage:?} usage", target.format).into(),
+            ..Default::default()
+        }));
+    };
+
+    // Framebuffers have limits of their own on top of the image's.
+    let properties = physical_device.properties();
+    let max_extent = [
+        format_properties.max_extent[0].min(properties.max_framebuffer_width),
+        format_properties.max_extent[1].min(properties.max_framebuffer_height),
+    ];
+    if target.extent[0] > max_extent[0] || target.extent[1] > max_extent[1] {
+        return Err(Box::new(ValidationError {
+            context: "target.extent".into(),
+            problem: format!(
+                "{:?} is larger than the device allows, {max_extent:?}",
+                target.extent
+            )
+            .into(),
+            ..Default::default()
+        }));
+    }
+
+    Ok(())
+}
+
+/// The highest of `supported` that isn't above `samples`.
+fn choose_sample_count(samples: SampleCount, supported: SampleCounts) -> SampleCount {
+    [
+        SampleCount::Sample64,
+        SampleCount::Sample32,
+        SampleCount::Sample16,
+        SampleCount::Sample8,
+        SampleCount::Sample4,
+        SampleCount::Sample2,
+    ]
+    .into_iter()
+    .find(|&count| count as u32 <= samples as u32 && supported.contains_enum(count))
+    .unwrap_or(SampleCount::Sample1)
+}
+
+/// Creates an attachment whose contents don't outlive a frame, in lazily allocated memory if the
+/// device has any.
+fn create_transient_attachment(
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    format: Format,
+    extent: [u32; 2],
+    samples: SampleCount,
+    usage: ImageUsage,
+) -> Arc<ImageView> {
+    ImageView::new_default(
+        Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format,
+                extent: [extent[0], extent[1], 1],
+                usage: usage | ImageUsage::TRANSIENT_ATTACHMENT,
+                samples,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter {
+                    preferred_flags: MemoryPropertyFlags::DEVICE_LOCAL
+                        | MemoryPropertyFlags::LAZILY_ALLOCATED,
+                    ..MemoryTypeFilter::PREFER_DEVICE
+                },
+                ..Default::default()
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap()
 }
 
 /// A window renderer struct holding the winit window surface and functionality for organizing your
@@ -99,13 +847,23 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
 
     /// Creates the swapchain and its images based on [`WindowDescriptor`]. The swapchain creation
     /// can be modified with the `swapchain_create_info_modify` function passed as an input.
+    /// Also returns the present modes and composite alpha modes supported by the surface.
+    ///
+    /// If the preferred composite alpha mode isn't `Opaque`, a format with an alpha channel is
+    /// picked, when the surface has one.
     fn create_swapchain(
         device: Arc<Device>,
         window: &Window,
         // tweak
-        present_mode: PresentMode,
+        present_modes: &[PresentMode],
+        composite_alphas: &[CompositeAlpha],
         swapchain_create_info_modify: fn(&mut SwapchainCreateInfo),
-    ) -> (Arc<Swapchain>, Vec<Arc<ImageView>>) {
+    ) -> (
+        Arc<Swapchain>,
+        Vec<Arc<ImageView>>,
+        Vec<PresentMode>,
+        CompositeAlphas,
+    ) {
         // tweak
         // I think this is safe?
         let surface =
@@ -114,27 +872,40 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
             .physical_device()
             .surface_capabilities(&surface, Default::default())
             .unwrap();
-        let image_format = device
+        let surface_formats = device
             .physical_device()
             .surface_formats(&surface, Default::default())
-            .unwrap()[0]
-            .0;
+            .unwrap();
+        // tweak
+        let wants_alpha = composite_alphas
+            .first()
+            .is_some_and(|&composite_alpha| composite_alpha != CompositeAlpha::Opaque);
+        let image_format = surface_formats
+            .iter()
+            .map(|&(format, _)| format)
+            .find(|format| !wants_alpha || format.components()[3] != 0)
+            .unwrap_or(surface_formats[0].0);
+        let supported_present_modes = device
+            .physical_device()
+            .surface_present_modes(&surface, Default::default())
+            .unwrap()
+            .into_iter()
+            .collect::<Vec<_>>();
         let (swapchain, images) = Swapchain::new(device, surface, {
             let mut create_info = SwapchainCreateInfo {
                 min_image_count: surface_capabilities.min_image_count.max(2),
                 image_format,
                 image_extent: window.inner_size().into(),
                 image_usage: ImageUsage::COLOR_ATTACHMENT,
-                composite_alpha: surface_capabilities
-                    .supported_composite_alpha
-                    .into_iter()
-                    .next()
-                    .unwrap(),
+                composite_alpha: choose_composite_alpha(
+                    composite_alphas,
+                    surface_capabilities.supported_composite_alpha,
+                ),
                 ..Default::default()
             };
 
             // tweak
-            create_info.present_mode = present_mode;
+            create_info.present_mode = choose_present_mode(present_modes, &supported_present_modes);
             swapchain_create_info_modify(&mut create_info);
             create_info
         })
@@ -144,16 +915,374 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
             .map(|image| ImageView::new_default(image).unwrap())
             .collect::<Vec<_>>();
 
-        (swapchain, images)
+        (
+            swapchain,
+            images,
+            supported_present_modes,
+            surface_capabilities.supported_composite_alpha,
+        )
     }
 
-    /// Set window renderer present mode. This triggers a swapchain recreation.
+    /// Set window renderer present mode, falling back as described in [`present_mode_fallbacks`]
+    /// if it isn't supported. This triggers a swapchain recreation if the present mode in use
+    /// changes.
     #[inline]
     pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        self.set_present_modes(present_mode_fallbacks(present_mode));
+    }
+
+    /// Set the present modes to try, in order of preference. The first supported one is used, or
+    /// [`PresentMode::Fifo`] if none are. This triggers a swapchain recreation if the present mode
+    /// in use changes.
+    /// Returns whether `present_modes` differ from the ones set before.
+    pub fn set_present_modes(&mut self, present_modes: &[PresentMode]) -> bool {
+        if self.without_window.present_modes == present_modes {
+            return false;
+        }
+        self.without_window.present_modes = present_modes.to_vec();
+
+        let present_mode =
+            choose_present_mode(present_modes, &self.without_window.supported_present_modes);
         if self.without_window.present_mode != present_mode {
             self.without_window.present_mode = present_mode;
             self.without_window.recreate_swapchain = true;
         }
+
+        true
+    }
+
+    /// The present mode in use. This can differ from the one asked for, if the surface doesn't
+    /// support it.
+    #[inline]
+    pub fn present_mode(&self) -> PresentMode {
+        self.without_window.present_mode
+    }
+
+    /// The present modes supported by the surface.
+    #[inline]
+    pub fn supported_present_modes(&self) -> &[PresentMode] {
+        &self.without_window.supported_present_modes
+    }
+
+    /// Set the composite alpha modes to try, in order of preference. The first supported one is
+    /// used, or any supported mode if none are. This triggers a swapchain recreation if the mode
+    /// in use changes. The swapchain format is picked when the renderer is created, so it only has
+    /// an alpha channel if the renderer was created with a transparent mode first.
+    /// Returns whether `composite_alphas` differ from the ones set before.
+    pub fn set_composite_alphas(&mut self, composite_alphas: &[CompositeAlpha]) -> bool {
+        if self.without_window.composite_alphas == composite_alphas {
+            return false;
+        }
+        self.without_window.composite_alphas = composite_alphas.to_vec();
+
+        let composite_alpha = choose_composite_alpha(
+            composite_alphas,
+            self.without_window.supported_composite_alpha,
+        );
+        if self.without_window.composite_alpha != composite_alpha {
+            self.without_window.composite_alpha = composite_alpha;
+            self.without_window.recreate_swapchain = true;
+        }
+
+        true
+    }
+
+    /// The composite alpha mode in use. This can differ from the one asked for, if the surface
+    /// doesn't support it.
+    #[inline]
+    pub fn composite_alpha(&self) -> CompositeAlpha {
+        self.without_window.composite_alpha
+    }
+
+    /// The composite alpha modes supported by the surface.
+    #[inline]
+    pub fn supported_composite_alpha(&self) -> CompositeAlphas {
+        self.without_window.supported_composite_alpha
+    }
+
+    /// Ask for exclusive full-screen access, with `VK_EXT_full_screen_exclusive`, while the window
+    /// is in exclusive fullscreen. This lets the driver skip the compositor. It is only available
+    /// on Windows, with the `ext_full_screen_exclusive` device extension enabled, otherwise this
+    /// does nothing. This triggers a swapchain recreation if it changes.
+    pub fn set_full_screen_exclusive(&mut self, full_screen_exclusive: bool) {
+        let full_screen_exclusive = full_screen_exclusive
+            && cfg!(target_os = "windows")
+            && self
+                .without_window
+                .graphics_queue
+                .device()
+                .enabled_extensions()
+                .ext_full_screen_exclusive;
+        if self.without_window.full_screen_exclusive != full_screen_exclusive {
+            self.without_window.full_screen_exclusive = full_screen_exclusive;
+            self.without_window.recreate_swapchain = true;
+        }
+    }
+
+    /// Whether the swapchain holds exclusive full-screen access.
+    #[inline]
+    pub fn has_full_screen_exclusive(&self) -> bool {
+        self.without_window.has_full_screen_exclusive
+    }
+
+    /// Whether the window is fullscreen, either borderless or exclusive.
+    #[inline]
+    pub fn is_fullscreen(&self) -> bool {
+        self.window().fullscreen().is_some()
+    }
+
+    /// The refresh rate the window is shown at, in millihertz. In exclusive fullscreen, this is the
+    /// refresh rate of the video mode in use, otherwise the one of the window's monitor. `None` if
+    /// it can't be known.
+    pub fn refresh_rate_millihertz(&self) -> Option<u32> {
+        match self.window().fullscreen() {
+            Some(Fullscreen::Exclusive(video_mode)) => Some(video_mode.refresh_rate_millihertz()),
+            _ => self.window().current_monitor()?.refresh_rate_millihertz(),
+        }
+    }
+
+    /// In exclusive fullscreen, switches the window to [`best_video_mode`] of the size it is shown
+    /// at, if it uses another one. Called when the swapchain is recreated, so it follows the video
+    /// mode bevy picks when [`bevy::window::Window::mode`] changes.
+    fn use_best_video_mode(&self) {
+        let Some(Fullscreen::Exclusive(video_mode)) = self.window().fullscreen() else {
+            return;
+        };
+        let Some(best) = best_video_mode(video_mode.monitor().video_modes(), video_mode.size())
+        else {
+            return;
+        };
+        if best != video_mode {
+            self.window()
+                .set_fullscreen(Some(Fullscreen::Exclusive(best)));
+        }
+    }
+
+    /// Set the attachments made for you, and recreated with the swapchain. This triggers a swapchain
+    /// recreation if they change.
+    pub fn set_attachments(&mut self, attachments: WindowAttachments) {
+        if self.without_window.attachments != attachments {
+            self.without_window.attachments = attachments;
+            self.without_window.recreate_swapchain = true;
+        }
+    }
+
+    /// The attachments asked for.
+    #[inline]
+    pub fn attachments(&self) -> WindowAttachments {
+        self.without_window.attachments
+    }
+
+    /// Keep the contents of the depth/stencil attachment after rendering, so that an overlay drawn
+    /// before presenting can load them, such as depth-tested gizmos. The attachment is then made
+    /// without [`ImageUsage::TRANSIENT_ATTACHMENT`], and your last render pass of the frame has to
+    /// store it with `Store`. This triggers a swapchain recreation if it changes.
+    pub fn set_depth_stored(&mut self, depth_stored: bool) {
+        if self.without_window.depth_stored != depth_stored {
+            self.without_window.depth_stored = depth_stored;
+            self.without_window.recreate_swapchain = true;
+        }
+    }
+
+    /// Whether the depth/stencil attachment is kept after rendering.
+    #[inline]
+    pub fn is_depth_stored(&self) -> bool {
+        self.without_window.depth_stored
+    }
+
+    /// The sample count of the attachments. This can be lower than the one asked for, if the device
+    /// doesn't support it.
+    #[inline]
+    pub fn samples(&self) -> SampleCount {
+        self.without_window.samples
+    }
+
+    /// The depth/stencil attachment, if one was asked for and a format is supported.
+    #[inline]
+    pub fn depth_stencil_image_view(&self) -> Option<Arc<ImageView>> {
+        self.without_window.depth_stencil_view.clone()
+    }
+
+    #[inline]
+    pub fn depth_stencil_format(&self) -> Option<Format> {
+        self.without_window
+            .depth_stencil_view
+            .as_ref()
+            .map(|view| view.format())
+    }
+
+    /// The multisampled color attachment, if the sample count is above one. Resolve it into
+    /// [`Self::swapchain_image_view`].
+    #[inline]
+    pub fn msaa_color_image_view(&self) -> Option<Arc<ImageView>> {
+        self.without_window.msaa_color_view.clone()
+    }
+
+    /// The image to render color into: the multisampled color attachment if there is one, otherwise
+    /// the swapchain image.
+    #[inline]
+    pub fn color_image_view(&self) -> Arc<ImageView> {
+        self.msaa_color_image_view()
+            .unwrap_or_else(|| self.swapchain_image_view())
+    }
+
+    /// Returns a framebuffer of `render_pass` for the current swapchain image, made the first time
+    /// it is asked for and cached after that. The swapchain image is the first attachment, followed
+    /// by `extra_attachments`, such as [`Self::depth_stencil_image_view`]. When the swapchain image
+    /// is elsewhere in the render pass, such as after [`Self::msaa_color_image_view`] as its
+    /// resolve attachment, use [`Self::framebuffer_with_attachments`].
+    ///
+    /// The cache is cleared when the swapchain is recreated. Framebuffers that aren't asked for in
+    /// a while, because the render pass or extra attachments changed, are dropped.
+    pub fn framebuffer(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+        extra_attachments: &[Arc<ImageView>],
+    ) -> Result<Arc<Framebuffer>, Validated<VulkanError>> {
+        let attachments = std::iter::once(self.swapchain_image_view())
+            .chain(extra_attachments.iter().cloned())
+            .collect::<Vec<_>>();
+        self.framebuffer_with_attachments(render_pass, &attachments)
+    }
+
+    /// Returns a framebuffer of `render_pass` with `attachments`, in the order of the render pass,
+    /// cached like [`Self::framebuffer`]. Put [`Self::swapchain_image_view`] where the render pass
+    /// has the swapchain image.
+    pub fn framebuffer_with_attachments(
+        &mut self,
+        render_pass: &Arc<RenderPass>,
+        attachments: &[Arc<ImageView>],
+    ) -> Result<Arc<Framebuffer>, Validated<VulkanError>> {
+        let frame = self.without_window.frame_count;
+        // Every swapchain image has its own framebuffer, each used once every few frames.
+        let max_unused_frames = 2 * self.without_window.final_views.len() as u64;
+        let framebuffers = &mut self.without_window.framebuffers;
+        framebuffers.retain(|(_, last_used_frame)| last_used_frame + max_unused_frames >= frame);
+
+        let cached = framebuffers.iter_mut().find(|(framebuffer, _)| {
+            Arc::ptr_eq(framebuffer.render_pass(), render_pass)
+                && framebuffer.attachments().len() == attachments.len()
+                && framebuffer
+                    .attachments()
+                    .iter()
+                    .zip(attachments)
+                    .all(|(a, b)| Arc::ptr_eq(a, b))
+        });
+        if let Some((framebuffer, last_used_frame)) = cached {
+            *last_used_frame = frame;
+            return Ok(framebuffer.clone());
+        }
+
+        let framebuffer = Framebuffer::new(render_pass.clone(), FramebufferCreateInfo {
+            attachments: attachments.to_vec(),
+            ..Default::default()
+        })?;
+        framebuffers.push((framebuffer.clone(), frame));
+
+        Ok(framebuffer)
+    }
+
+    /// Begins dynamic rendering on the current swapchain image, without a render pass or
+    /// framebuffer. Color is rendered into [`Self::color_image_view`], and resolved into the
+    /// swapchain image if it is multisampled. End it with `builder.end_rendering()`.
+    ///
+    /// The command buffer transitions the images into attachment layouts and back, the swapchain
+    /// image ends up ready to present. This needs the `dynamic_rendering` device feature, see
+    /// [`crate::request_dynamic_rendering`] and
+    /// [`crate::BevyVulkanoContext::has_dynamic_rendering`]. Make pipelines for it with
+    /// [`Self::pipeline_rendering_create_info`].
+    pub fn begin_rendering<L>(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        rendering: &WindowRendering,
+    ) -> Result<(), Box<ValidationError>> {
+        let attachment =
+            |image_view: Arc<ImageView>, ops: &AttachmentOps| RenderingAttachmentInfo {
+                load_op: ops.load_op,
+                store_op: ops.store_op,
+                clear_value: ops.clear_value,
+                ..RenderingAttachmentInfo::image_view(image_view)
+            };
+
+        let mut color = attachment(self.color_image_view(), &rendering.color);
+        if self.without_window.msaa_color_view.is_some() {
+            // Only the resolved image is kept.
+            color.store_op = AttachmentStoreOp::DontCare;
+            color.resolve_info = Some(RenderingAttachmentResolveInfo::image_view(
+                self.swapchain_image_view(),
+            ));
+        }
+        let color_attachments = std::iter::once(Some(color))
+            .chain(
+                rendering
+                    .color_attachments
+                    .iter()
+                    .map(|(image_view, ops)| Some(attachment(image_view.clone(), ops))),
+            )
+            .collect();
+
+        let (depth_attachment, stencil_attachment) = match &rendering.depth_stencil {
+            Some((image_view, ops)) => {
+                let aspects = image_view.format().aspects();
+                (
+                    aspects
+                        .intersects(ImageAspects::DEPTH)
+                        .then(|| attachment(image_view.clone(), ops)),
+                    aspects
+                        .intersects(ImageAspects::STENCIL)
+                        .then(|| attachment(image_view.clone(), ops)),
+                )
+            }
+            None => (None, None),
+        };
+
+        builder.begin_rendering(RenderingInfo {
+            render_area_extent: self.swapchain_image_size(),
+            layer_count: 1,
+            color_attachments,
+            depth_attachment,
+            stencil_attachment,
+            contents: SubpassContents::Inline,
+            ..Default::default()
+        })?;
+
+        Ok(())
+    }
+
+    /// The attachment formats of `rendering`, for the `subpass` of a pipeline that renders in
+    /// [`Self::begin_rendering`]. Its multisample state should use [`Self::samples`].
+    pub fn pipeline_rendering_create_info(
+        &self,
+        rendering: &WindowRendering,
+    ) -> PipelineRenderingCreateInfo {
+        let depth_stencil_format = rendering
+            .depth_stencil
+            .as_ref()
+            .map(|(image_view, _)| image_view.format());
+
+        PipelineRenderingCreateInfo {
+            color_attachment_formats: std::iter::once(Some(self.swapchain_format()))
+                .chain(
+                    rendering
+                        .color_attachments
+                        .iter()
+                        .map(|(image_view, _)| Some(image_view.format())),
+                )
+                .collect(),
+            depth_attachment_format: depth_stencil_format
+                .filter(|format| format.aspects().intersects(ImageAspects::DEPTH)),
+            stencil_attachment_format: depth_stencil_format
+                .filter(|format| format.aspects().intersects(ImageAspects::STENCIL)),
+            ..Default::default()
+        }
+    }
+
+    /// Whether the window can be see-through: the composite alpha mode isn't `Opaque`, and the
+    /// swapchain format has an alpha channel.
+    #[inline]
+    pub fn is_transparent(&self) -> bool {
+        self.composite_alpha() != CompositeAlpha::Opaque
+            && self.swapchain_format().components()[3] != 0
     }
 
     /// Return swapchain image format.
@@ -162,6 +1291,12 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
         self.without_window.final_views[self.without_window.image_index as usize].format()
     }
 
+    /// Returns the timing and swapchain statistics of this renderer.
+    #[inline]
+    pub fn frame_stats(&self) -> FrameStats {
+        self.without_window.frame_stats
+    }
+
     /// Returns the index of last swapchain image that is the next render target.
     #[inline]
     pub fn image_index(&self) -> u32 {
@@ -195,8 +1330,40 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
     /// Size of the physical window.
     #[inline]
     pub fn window_size(&self) -> [f32; 2] {
-        let size = self.window().inner_size();
-        [size.width as f32, size.height as f32]
+        let [width, height] = self.physical_size();
+        [width as f32, height as f32]
+    }
+
+    /// Size of the window in physical pixels. This is what the swapchain images are sized to, so
+    /// use it for pixel-perfect rendering.
+    #[inline]
+    pub fn physical_size(&self) -> [u32; 2] {
+        self.window().inner_size().into()
+    }
+
+    /// Size of the window in logical pixels, the physical size divided by the scale factor. This
+    /// is the size bevy reports in [`bevy::window::Window::width`] and
+    /// [`bevy::window::Window::height`], and the one to lay out UI with.
+    #[inline]
+    pub fn logical_size(&self) -> [f32; 2] {
+        self.window()
+            .inner_size()
+            .to_logical::<f32>(self.window().scale_factor())
+            .into()
+    }
+
+    /// How many physical pixels there are per logical pixel. Changes when the window moves to a
+    /// monitor with a different DPI.
+    #[inline]
+    pub fn scale_factor(&self) -> f32 {
+        self.window().scale_factor() as f32
+    }
+
+    /// Whether the swapchain images no longer match the physical size of the window, and the
+    /// swapchain needs to be recreated.
+    #[inline]
+    pub fn is_swapchain_outdated(&self) -> bool {
+        self.physical_size() != self.swapchain_image_size()
     }
 
     /// Size of the final swapchain image (surface).
@@ -213,15 +1380,10 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
         self.without_window.final_views[self.without_window.image_index as usize].clone()
     }
 
-    /// Return scale factor accounted window size.
+    /// Return scale factor accounted window size. The same as [`Self::logical_size`].
     #[inline]
     pub fn resolution(&self) -> [f32; 2] {
-        let size = self.window().inner_size();
-        let scale_factor = self.window().scale_factor();
-        [
-            (size.width as f64 / scale_factor) as f32,
-            (size.height as f64 / scale_factor) as f32,
-        ]
+        self.logical_size()
     }
 
     #[inline]
@@ -271,6 +1433,23 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
             .insert(key, image);
     }
 
+    /// Like [`Self::add_additional_image_view`], but like the images of
+    /// [`crate::TransientImagePool`], the image is made transient if `usage` only has attachment
+    /// usages, in lazily allocated memory if the device has any. Its contents can't outlive a
+    /// render pass, so use it for attachments that are cleared or don't care on load, and not
+    /// stored.
+    #[inline]
+    pub fn add_transient_image_view(&mut self, key: usize, format: Format, usage: ImageUsage) {
+        let view = create_transient_image(
+            &self.without_window.memory_allocator,
+            format,
+            self.swapchain_image_size(),
+            SampleCount::Sample1,
+            usage,
+        );
+        self.without_window.additional_image_views.insert(key, view);
+    }
+
     /// Get additional image view by key.
     #[inline]
     pub fn get_additional_image_view(&mut self, key: usize) -> Arc<ImageView> {
@@ -281,6 +1460,12 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
             .clone()
     }
 
+    /// Every additional image view, by key.
+    #[inline]
+    pub fn additional_image_views(&self) -> &HashMap<usize, Arc<ImageView>> {
+        &self.without_window.additional_image_views
+    }
+
     /// Remove additional image by key.
     #[inline]
     pub fn remove_additional_image_view(&mut self, key: usize) {
@@ -293,6 +1478,11 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
     /// after which the swapchain image has been acquired and previous frame ended.
     /// Execute your command buffers after calling this function and
     /// finish rendering by calling [`VulkanoWindowRenderer::present`].
+    ///
+    /// On error, skip the frame without presenting. [`VulkanError::OutOfDate`] and
+    /// [`VulkanError::FullScreenExclusiveModeLost`] are expected, the swapchain is recreated on the
+    /// next call. [`VulkanError::Timeout`] and [`VulkanError::NotReady`] mean no image was free in
+    /// time.
     #[inline]
     pub fn acquire(
         &mut self,
@@ -307,22 +1497,40 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
         }
 
         // Acquire next image in the swapchain
-        let (image_index, suboptimal, acquire_future) =
-            match swapchain::acquire_next_image(self.without_window.swapchain.clone(), timeout)
-                .map_err(Validated::unwrap)
-            {
-                Ok(r) => r,
-                Err(VulkanError::OutOfDate) => {
-                    self.without_window.recreate_swapchain = true;
-                    return Err(VulkanError::OutOfDate);
-                }
-                Err(e) => panic!("failed to acquire next image: {e}"),
-            };
+        let acquire_start = Instant::now();
+        let acquired =
+            swapchain::acquire_next_image(self.without_window.swapchain.clone(), timeout)
+                .map_err(Validated::unwrap);
+        self.without_window.frame_stats.acquire_wait = acquire_start.elapsed();
+
+        let (image_index, suboptimal, acquire_future) = match acquired {
+            Ok(r) => r,
+            Err(VulkanError::OutOfDate) => {
+                self.without_window.recreate_swapchain = true;
+                self.without_window.frame_stats.out_of_date += 1;
+                self.without_window.frame_stats.skipped_frames += 1;
+                return Err(VulkanError::OutOfDate);
+            }
+            // Another application took the screen, or the window left fullscreen.
+            Err(VulkanError::FullScreenExclusiveModeLost) => {
+                self.without_window.recreate_swapchain = true;
+                self.without_window.has_full_screen_exclusive = false;
+                self.without_window.frame_stats.skipped_frames += 1;
+                return Err(VulkanError::FullScreenExclusiveModeLost);
+            }
+            // A timeout, or the image not being ready yet without a timeout, only skips this frame.
+            Err(e) => {
+                self.without_window.frame_stats.skipped_frames += 1;
+                return Err(e);
+            }
+        };
         if suboptimal {
             self.without_window.recreate_swapchain = true;
+            self.without_window.frame_stats.suboptimal += 1;
         }
         // Update our image index
         self.without_window.image_index = image_index;
+        self.without_window.frame_count += 1;
 
         let future = self
             .without_window
@@ -334,23 +1542,48 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
         Ok(future.boxed())
     }
 
+    /// Sets commands to record over the swapchain image the next time it's presented, after
+    /// everything rendered that frame. It's used once, so set it again every frame. `None` removes
+    /// the one set before.
+    #[inline]
+    pub fn set_present_overlay(&mut self, overlay: Option<PresentOverlay>) {
+        self.without_window.present_overlay = overlay;
+    }
+
     /// Finishes rendering by presenting the swapchain. Pass your last future as an input to this
     /// function.
     ///
     /// Depending on your implementation, you may want to wait on your future. For example, a
     /// compute shader dispatch using an image that's being later drawn should probably be waited
     /// on.
+    ///
+    /// The overlay set with [`Self::set_present_overlay`], if any, is recorded first.
     #[inline]
     pub fn present(&mut self, after_future: Box<dyn GpuFuture>, wait_future: bool) {
+        let after_future = match self.without_window.present_overlay.take() {
+            Some(overlay) => overlay(self, after_future),
+            None => after_future,
+        };
+
+        let mut present_info = SwapchainPresentInfo::swapchain_image_index(
+            self.without_window.swapchain.clone(),
+            self.without_window.image_index,
+        );
+        if self.without_window.uses_present_id {
+            self.without_window.present_count += 1;
+            present_info.present_id = NonZeroU64::new(self.without_window.present_count);
+        }
+        let present_id = present_info.present_id;
+
         let future = after_future
-            .then_swapchain_present(
-                self.without_window.graphics_queue.clone(),
-                SwapchainPresentInfo::swapchain_image_index(
-                    self.without_window.swapchain.clone(),
-                    self.without_window.image_index,
-                ),
-            )
+            .then_swapchain_present(self.without_window.graphics_queue.clone(), present_info)
             .then_signal_fence_and_flush();
+
+        let now = Instant::now();
+        if let Some(last_present) = self.without_window.last_present.replace(now) {
+            self.without_window.frame_stats.present_interval = now - last_present;
+        }
+
         match future.map_err(Validated::unwrap) {
             Ok(mut future) => {
                 if wait_future {
@@ -360,21 +1593,133 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
                     future.cleanup_finished();
                 }
 
+                let future = Arc::new(future);
+                self.without_window.previous_frame_fence = Some(future.clone());
+                self.without_window.last_present_id = present_id;
                 self.without_window.previous_frame_end = Some(future.boxed());
             }
             Err(VulkanError::OutOfDate) => {
                 self.without_window.recreate_swapchain = true;
+                self.without_window.previous_frame_fence = None;
+                self.without_window.last_present_id = None;
+                self.without_window.frame_stats.out_of_date += 1;
+                self.without_window.frame_stats.skipped_frames += 1;
+                self.without_window.previous_frame_end =
+                    Some(sync::now(self.without_window.graphics_queue.device().clone()).boxed());
+            }
+            // Another application took the screen, or the window left fullscreen.
+            Err(VulkanError::FullScreenExclusiveModeLost) => {
+                self.without_window.recreate_swapchain = true;
+                self.without_window.has_full_screen_exclusive = false;
+                self.without_window.previous_frame_fence = None;
+                self.without_window.last_present_id = None;
+                self.without_window.frame_stats.skipped_frames += 1;
                 self.without_window.previous_frame_end =
                     Some(sync::now(self.without_window.graphics_queue.device().clone()).boxed());
             }
             Err(e) => {
                 println!("failed to flush future: {e}");
+                self.without_window.frame_stats.skipped_frames += 1;
+                self.without_window.previous_frame_fence = None;
+                self.without_window.last_present_id = None;
                 self.without_window.previous_frame_end =
                     Some(sync::now(self.without_window.graphics_queue.device().clone()).boxed());
             }
         }
     }
 
+    /// How the swapchain should be created for exclusive full-screen access. The monitor is only
+    /// known on Windows, which is the only platform with the extension.
+    fn full_screen_exclusive_info(&self) -> (FullScreenExclusive, Option<Win32Monitor>) {
+        if !self.without_window.full_screen_exclusive {
+            return (FullScreenExclusive::Default, None);
+        }
+
+        #[cfg(target_os = "windows")]
+        if let Some(monitor) = self.window().current_monitor() {
+            use winit::platform::windows::MonitorHandleExtWindows;
+
+            // Safe, as the monitor handle comes from winit and outlives the swapchain creation.
+            let win32_monitor =
+                unsafe { Win32Monitor::new(monitor.hmonitor() as *const std::ffi::c_void) };
+            return (
+                FullScreenExclusive::ApplicationControlled,
+                Some(win32_monitor),
+            );
+        }
+
+        (FullScreenExclusive::Default, None)
+    }
+
+    /// Creates the attachments asked for with [`Self::set_attachments`], the size of the swapchain.
+    fn create_attachments(&mut self) {
+        let extent = self.swapchain_image_size();
+        let swapchain_format = self.swapchain_format();
+        let without_window = &mut *self.without_window;
+        let attachments = without_window.attachments;
+        let physical_device = without_window.graphics_queue.device().physical_device();
+        let properties = physical_device.properties();
+
+        let depth_stencil_format = attachments
+            .depth
+            .then(|| choose_depth_stencil_format(physical_device, attachments.stencil))
+            .flatten();
+        if attachments.depth && depth_stencil_format.is_none() {
+            warn!("No depth/stencil format is supported for the window's depth attachment.");
+        }
+
+        let mut supported_samples = properties.framebuffer_color_sample_counts;
+        if depth_stencil_format.is_some() {
+            supported_samples &= properties.framebuffer_depth_sample_counts;
+        }
+        if depth_stencil_format
+            .is_some_and(|format| format.aspects().intersects(ImageAspects::STENCIL))
+        {
+            supported_samples &= properties.framebuffer_stencil_sample_counts;
+        }
+        without_window.samples = choose_sample_count(attachments.samples, supported_samples);
+
+        without_window.depth_stencil_view = depth_stencil_format.map(|format| {
+            if without_window.depth_stored {
+                // Stored depth outlives the render pass, so it can't be transient.
+                ImageView::new_default(
+                    Image::new(
+                        without_window.memory_allocator.clone(),
+                        ImageCreateInfo {
+                            image_type: ImageType::Dim2d,
+                            format,
+                            extent: [extent[0], extent[1], 1],
+                            usage: ImageUsage::DEPTH_STENCIL_ATTACHMENT,
+                            samples: without_window.samples,
+                            ..Default::default()
+                        },
+                        AllocationCreateInfo::default(),
+                    )
+                    .unwrap(),
+                )
+                .unwrap()
+            } else {
+                create_transient_attachment(
+                    &without_window.memory_allocator,
+                    format,
+                    extent,
+                    without_window.samples,
+                    ImageUsage::DEPTH_STENCIL_ATTACHMENT,
+                )
+            }
+        });
+        without_window.msaa_color_view =
+            (without_window.samples != SampleCount::Sample1).then(|| {
+                create_transient_attachment(
+                    &without_window.memory_allocator,
+                    swapchain_format,
+                    extent,
+                    without_window.samples,
+                    ImageUsage::COLOR_ATTACHMENT,
+                )
+            });
+    }
+
     /// Recreates swapchain images and image views which follow the window size.
     fn recreate_swapchain_and_views(&mut self) {
         let image_extent: [u32; 2] = self.window().inner_size().into();
@@ -383,6 +1728,39 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
             return;
         }
 
+        self.use_best_video_mode();
+
+        if self.without_window.has_full_screen_exclusive {
+            self.without_window.has_full_screen_exclusive = false;
+            if let Err(e) = self
+                .without_window
+                .swapchain
+                .release_full_screen_exclusive_mode()
+            {
+                warn!("Failed to release exclusive full-screen access: {e}");
+            }
+        }
+
+        // The surface may support other present modes now, e.g. on another monitor.
+        match self
+            .without_window
+            .graphics_queue
+            .device()
+            .physical_device()
+            .surface_present_modes(self.without_window.swapchain.surface(), Default::default())
+        {
+            Ok(supported_present_modes) => {
+                self.without_window.supported_present_modes =
+                    supported_present_modes.into_iter().collect();
+                self.without_window.present_mode = choose_present_mode(
+                    &self.without_window.present_modes,
+                    &self.without_window.supported_present_modes,
+                );
+            }
+            Err(e) => warn!("Failed to query the supported present modes: {e}"),
+        }
+
+        let (full_screen_exclusive, win32_monitor) = self.full_screen_exclusive_info();
         let (new_swapchain, new_images) = self
             .without_window
             .swapchain
@@ -390,11 +1768,27 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
                 image_extent,
                 // Use present mode from current state
                 present_mode: self.without_window.present_mode,
+                composite_alpha: self.without_window.composite_alpha,
+                full_screen_exclusive,
+                win32_monitor,
                 ..self.without_window.swapchain.create_info()
             })
             .expect("failed to recreate swapchain");
 
         self.without_window.swapchain = new_swapchain;
+        if full_screen_exclusive == FullScreenExclusive::ApplicationControlled {
+            match self
+                .without_window
+                .swapchain
+                .acquire_full_screen_exclusive_mode()
+            {
+                Ok(()) => self.without_window.has_full_screen_exclusive = true,
+                // Fails while the window isn't fullscreen yet, the next recreation tries again.
+                Err(e) => warn!("Failed to acquire exclusive full-screen access: {e}"),
+            }
+        }
+        // Present ids belong to the old swapchain.
+        self.without_window.last_present_id = None;
         let new_images = new_images
             .into_iter()
             .map(|image| ImageView::new_default(image).unwrap())
@@ -411,8 +1805,248 @@ impl<'a, 'b> VulkanoWindowRenderer<'a, 'b> {
             let format = self.get_additional_image_view(i).format();
             let usage = self.get_additional_image_view(i).usage();
             self.remove_additional_image_view(i);
-            self.add_additional_image_view(i, format, usage);
+            if usage.intersects(ImageUsage::TRANSIENT_ATTACHMENT) {
+                self.add_transient_image_view(i, format, usage - ImageUsage::TRANSIENT_ATTACHMENT);
+            } else {
+                self.add_additional_image_view(i, format, usage);
+            }
         }
+        self.create_attachments();
+        // Framebuffers hold the old swapchain images and attachments.
+        self.without_window.framebuffers.clear();
         self.without_window.recreate_swapchain = false;
+        self.without_window.frame_stats.swapchain_recreations += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn present_mode_fallbacks_end_with_fifo() {
+        let table: [(PresentMode, &[PresentMode]); 6] = [
+            (PresentMode::Fifo, &[PresentMode::Fifo]),
+            (PresentMode::Mailbox, &[
+                PresentMode::Mailbox,
+                PresentMode::Immediate,
+                PresentMode::Fifo,
+            ]),
+            (PresentMode::Immediate, &[
+                PresentMode::Immediate,
+                PresentMode::Mailbox,
+                PresentMode::Fifo,
+            ]),
+            (PresentMode::FifoRelaxed, &[
+                PresentMode::FifoRelaxed,
+                PresentMode::Fifo,
+            ]),
+            (PresentMode::SharedDemandRefresh, &[
+                PresentMode::SharedDemandRefresh,
+                PresentMode::Fifo,
+            ]),
+            (PresentMode::SharedContinuousRefresh, &[
+                PresentMode::SharedContinuousRefresh,
+                PresentMode::Fifo,
+            ]),
+        ];
+        for (present_mode, fallbacks) in table {
+            assert_eq!(
+                present_mode_fallbacks(present_mode),
+                fallbacks,
+                "{present_mode:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn present_mode_is_first_supported_fallback() {
+        let table: [(PresentMode, &[PresentMode], PresentMode); 6] = [
+            (
+                PresentMode::Mailbox,
+                &[PresentMode::Fifo, PresentMode::Mailbox],
+                PresentMode::Mailbox,
+            ),
+            (
+                PresentMode::Mailbox,
+                &[PresentMode::Fifo, PresentMode::Immediate],
+                PresentMode::Immediate,
+            ),
+            (
+                PresentMode::Mailbox,
+                &[PresentMode::Fifo],
+                PresentMode::Fifo,
+            ),
+            (
+                PresentMode::Immediate,
+                &[PresentMode::Fifo, PresentMode::Mailbox],
+                PresentMode::Mailbox,
+            ),
+            (
+                PresentMode::FifoRelaxed,
+                &[PresentMode::Fifo, PresentMode::Mailbox],
+                PresentMode::Fifo,
+            ),
+            // Fifo is always supported, but it's still picked if a surface claims otherwise.
+            (PresentMode::Immediate, &[], PresentMode::Fifo),
+        ];
+        for (present_mode, supported, expected) in table {
+            assert_eq!(
+                choose_present_mode(present_mode_fallbacks(present_mode), supported),
+                expected,
+                "{present_mode:?} with {supported:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn composite_alpha_fallbacks_by_mode() {
+        let table: [(CompositeAlpha, &[CompositeAlpha]); 4] = [
+            (CompositeAlpha::Opaque, &[
+                CompositeAlpha::Opaque,
+                CompositeAlpha::Inherit,
+            ]),
+            (CompositeAlpha::PreMultiplied, &[
+                CompositeAlpha::PreMultiplied,
+                CompositeAlpha::PostMultiplied,
+                CompositeAlpha::Inherit,
+            ]),
+            (CompositeAlpha::PostMultiplied, &[
+                CompositeAlpha::PostMultiplied,
+                CompositeAlpha::PreMultiplied,
+                CompositeAlpha::Inherit,
+            ]),
+            (CompositeAlpha::Inherit, &[
+                CompositeAlpha::Inherit,
+                CompositeAlpha::PreMultiplied,
+                CompositeAlpha::PostMultiplied,
+            ]),
+        ];
+        for (composite_alpha, fallbacks) in table {
+            assert_eq!(
+                composite_alpha_fallbacks(composite_alpha),
+                fallbacks,
+                "{composite_alpha:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn composite_alpha_is_first_supported_fallback() {
+        let table = [
+            (
+                CompositeAlpha::PreMultiplied,
+                CompositeAlphas::OPAQUE | CompositeAlphas::PRE_MULTIPLIED,
+                CompositeAlpha::PreMultiplied,
+            ),
+            (
+                CompositeAlpha::PreMultiplied,
+                CompositeAlphas::OPAQUE | CompositeAlphas::POST_MULTIPLIED,
+                CompositeAlpha::PostMultiplied,
+            ),
+            (
+                CompositeAlpha::PostMultiplied,
+                CompositeAlphas::OPAQUE | CompositeAlphas::INHERIT,
+                CompositeAlpha::Inherit,
+            ),
+            (
+                CompositeAlpha::Opaque,
+                CompositeAlphas::INHERIT | CompositeAlphas::PRE_MULTIPLIED,
+                CompositeAlpha::Inherit,
+            ),
+            // None of the fallbacks are supported, so any supported mode is used.
+            (
+                CompositeAlpha::Opaque,
+                CompositeAlphas::PRE_MULTIPLIED,
+                CompositeAlpha::PreMultiplied,
+            ),
+            (
+                CompositeAlpha::Inherit,
+                CompositeAlphas::OPAQUE,
+                CompositeAlpha::Opaque,
+            ),
+        ];
+        for (composite_alpha, supported, expected) in table {
+            assert_eq!(
+                choose_composite_alpha(composite_alpha_fallbacks(composite_alpha), supported),
+                expected,
+                "{composite_alpha:?} with {supported:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn best_video_mode_prefers_refresh_rate_then_bit_depth() {
+        let size = PhysicalSize::new(1920, 1080);
+        let other_size = PhysicalSize::new(1280, 720);
+        let table: [(
+            &[(PhysicalSize<u32>, u32, u16)],
+            Option<(PhysicalSize<u32>, u32, u16)>,
+        ); 4] = [
+            (
+                &[(size, 60_000, 32), (size, 144_000, 24), (size, 120_000, 32)],
+                Some((size, 144_000, 24)),
+            ),
+            (
+                &[(size, 60_000, 24), (size, 60_000, 32)],
+                Some((size, 60_000, 32)),
+            ),
+            // Other sizes are never picked, even with a higher refresh rate.
+            (
+                &[(other_size, 240_000, 32), (size, 60_000, 32)],
+                Some((size, 60_000, 32)),
+            ),
+            (&[(other_size, 60_000, 32)], None),
+        ];
+        for (video_modes, expected) in table {
+            assert_eq!(
+                best_of_size(video_modes.iter().copied(), size, |&video_mode| video_mode),
+                expected,
+                "{video_modes:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn sample_count_is_highest_supported_up_to_requested() {
+        let table = [
+            (
+                SampleCount::Sample4,
+                SampleCounts::SAMPLE_1 | SampleCounts::SAMPLE_2 | SampleCounts::SAMPLE_4,
+                SampleCount::Sample4,
+            ),
+            (
+                SampleCount::Sample8,
+                SampleCounts::SAMPLE_1 | SampleCounts::SAMPLE_2 | SampleCounts::SAMPLE_4,
+                SampleCount::Sample4,
+            ),
+            (
+                SampleCount::Sample4,
+                SampleCounts::SAMPLE_1 | SampleCounts::SAMPLE_2 | SampleCounts::SAMPLE_8,
+                SampleCount::Sample2,
+            ),
+            (
+                SampleCount::Sample2,
+                SampleCounts::SAMPLE_1 | SampleCounts::SAMPLE_4,
+                SampleCount::Sample1,
+            ),
+            (
+                SampleCount::Sample1,
+                SampleCounts::SAMPLE_1 | SampleCounts::SAMPLE_4,
+                SampleCount::Sample1,
+            ),
+            (
+                SampleCount::Sample64,
+                SampleCounts::SAMPLE_1 | SampleCounts::SAMPLE_64,
+                SampleCount::Sample64,
+            ),
+        ];
+        for (samples, supported, expected) in table {
+            assert_eq!(
+                choose_sample_count(samples, supported),
+                expected,
+                "{samples:?} with {supported:?}"
+            );
+        }
     }
 }
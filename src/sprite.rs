@@ -0,0 +1,1136 @@
+//! 2D sprites drawn in instanced batches, enabled with the `sprite` feature.
+//!
+//! Spawn a [`VulkanoCamera`] marked with [`VulkanoCamera2d`], usually from
+//! [`VulkanoCamera2d::bundle`], and entities with a [`VulkanoSprite`] and a [`Transform`].
+//! [`SpritePlugin`] renders them in [`PostUpdate`], once cameras are updated, into each camera's
+//! target and viewport. It acquires and presents the windows rendered into their swapchain images
+//! itself, so don't render them in your own systems. Other targets are rendered in a submission of
+//! their own, see [`SpriteRenderer::join`].
+//!
+//! Every frame, the sprites are extracted from the world into a single instance buffer, sorted by
+//! [`VulkanoSprite::z_order`] and texture. Each run of sprites sharing a texture is one instanced
+//! draw of a quad.
+
+use std::{ops::Range, sync::Arc, time::Duration};
+
+use bevy::{prelude::*, utils::HashMap};
+use vulkano::{
+    buffer::{
+        allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
+        BufferContents, BufferUsage, Subbuffer,
+    },
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, ClearAttachment,
+        ClearRect, CommandBufferUsage, RenderPassBeginInfo, SubpassBeginInfo, SubpassContents,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
+    },
+    device::{Device, DeviceOwned},
+    format::{Format, NumericFormat},
+    image::{
+        sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+        view::ImageView,
+        Image, ImageAspects, ImageCreateInfo, ImageType, ImageUsage,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter},
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendAttachmentState, ColorBlendState},
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
+            rasterization::RasterizationState,
+            vertex_input::{Vertex, VertexDefinition},
+            viewport::ViewportState,
+            GraphicsPipelineCreateInfo,
+        },
+        layout::PipelineDescriptorSetLayoutCreateInfo,
+        DynamicState, GraphicsPipeline, PipelineBindPoint, PipelineLayout,
+        PipelineShaderStageCreateInfo,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    shader::EntryPoint,
+    sync::{self, future::FenceSignalFuture, GpuFuture},
+    Validated, VulkanError,
+};
+
+use crate::{
+    camera::{update_cameras, viewport_and_scissor, CameraMatrices, CameraTarget, VulkanoCamera},
+    uploader::{UploadFuture, UploadHandle},
+    vulkano_renderers::PerSwapchainFormat,
+    BevyVulkanoContext, CameraPlugin, GpuUploader, VulkanoRenderers,
+};
+
+/// Renders [`VulkanoSprite`]s through the cameras marked with [`VulkanoCamera2d`]. Add it after
+/// [`crate::VulkanoPlugin`]. Adds [`CameraPlugin`] if it isn't added yet.
+pub struct SpritePlugin;
+
+impl Plugin for SpritePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<CameraPlugin>() {
+            app.add_plugins(CameraPlugin);
+        }
+        app.init_resource::<SpriteTextures>()
+            .init_non_send_resource::<SpriteRenderer>()
+            .add_systems(PostUpdate, render_sprites.after(update_cameras));
+    }
+}
+
+/// A texture added to [`SpriteTextures`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SpriteTexture(u64);
+
+struct TextureEntry {
+    view: Arc<ImageView>,
+    filter: Filter,
+    upload: Option<UploadHandle>,
+}
+
+/// The textures sprites can use.
+#[derive(Resource, Default)]
+pub struct SpriteTextures {
+    textures: HashMap<SpriteTexture, TextureEntry>,
+    next_texture: u64,
+}
+
+impl SpriteTextures {
+    /// Makes `view` available to sprites, sampled with `filter`, `Nearest` for pixel art. If the
+    /// image is being uploaded, pass its `upload` so frames wait for it.
+    pub fn add(
+        &mut self,
+        view: Arc<ImageView>,
+        filter: Filter,
+        upload: Option<UploadHandle>,
+    ) -> SpriteTexture {
+        let texture = SpriteTexture(self.next_texture);
+        self.next_texture += 1;
+        self.textures.insert(texture, TextureEntry {
+            view,
+            filter,
+            upload,
+        });
+        texture
+    }
+
+    /// Sprites using a removed texture aren't drawn.
+    pub fn remove(&mut self, texture: SpriteTexture) {
+        self.textures.remove(&texture);
+    }
+
+    #[inline]
+    pub fn get(&self, texture: SpriteTexture) -> Option<&Arc<ImageView>> {
+        self.textures.get(&texture).map(|entry| &entry.view)
+    }
+
+    /// The size of a texture in pixels.
+    pub fn size(&self, texture: SpriteTexture) -> Option<UVec2> {
+        let extent = self.get(texture)?.image().extent();
+        Some(UVec2::new(extent[0], extent[1]))
+    }
+}
+
+/// Which point of a sprite is at its translation.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SpriteAnchor {
+    #[default]
+    Center,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+    CenterLeft,
+    CenterRight,
+    TopLeft,
+    TopCenter,
+    TopRight,
+    /// From `(-0.5, -0.5)` at the bottom left to `(0.5, 0.5)` at the top right.
+    Custom(Vec2),
+}
+
+impl SpriteAnchor {
+    pub fn as_vec(self) -> Vec2 {
+        match self {
+            SpriteAnchor::Center => Vec2::ZERO,
+            SpriteAnchor::BottomLeft => Vec2::new(-0.5, -0.5),
+            SpriteAnchor::BottomCenter => Vec2::new(0.0, -0.5),
+            SpriteAnchor::BottomRight => Vec2::new(0.5, -0.5),
+            SpriteAnchor::CenterLeft => Vec2::new(-0.5, 0.0),
+            SpriteAnchor::CenterRight => Vec2::new(0.5, 0.0),
+            SpriteAnchor::TopLeft => Vec2::new(-0.5, 0.5),
+            SpriteAnchor::TopCenter => Vec2::new(0.0, 0.5),
+            SpriteAnchor::TopRight => Vec2::new(0.5, 0.5),
+            SpriteAnchor::Custom(anchor) => anchor,
+        }
+    }
+}
+
+/// A textured quad, placed by the entity's [`Transform`].
+#[derive(Component, Clone, Debug)]
+pub struct VulkanoSprite {
+    /// The texture to draw, or `None` for a plain quad of `color`.
+    pub texture: Option<SpriteTexture>,
+    /// The region of the texture to draw, in pixels, or `None` for all of it. Ignored if the
+    /// entity has a [`VulkanoTextureAtlas`].
+    pub rect: Option<Rect>,
+    /// The size in world units. Without it, the size of the region drawn in pixels, or one unit
+    /// without a texture.
+    pub custom_size: Option<Vec2>,
+    /// Multiplies the texture, in linear RGBA.
+    pub color: [f32; 4],
+    pub anchor: SpriteAnchor,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Sprites are drawn from the lowest to the highest, later ones on top.
+    pub z_order: f32,
+}
+
+impl Default for VulkanoSprite {
+    fn default() -> Self {
+        Self {
+            texture: None,
+            rect: None,
+            custom_size: None,
+            color: [1.0; 4],
+            anchor: SpriteAnchor::default(),
+            flip_x: false,
+            flip_y: false,
+            z_order: 0.0,
+        }
+    }
+}
+
+impl VulkanoSprite {
+    /// A sprite of all of `texture`.
+    pub fn new(texture: SpriteTexture) -> Self {
+        Self {
+            texture: Some(texture),
+            ..Default::default()
+        }
+    }
+
+    /// A plain quad of `color` and `size`.
+    pub fn from_color(color: [f32; 4], size: Vec2) -> Self {
+        Self {
+            color,
+            custom_size: Some(size),
+            ..Default::default()
+        }
+    }
+}
+
+/// Where the regions of a texture atlas are, in pixels.
+#[derive(Clone, Debug, Default)]
+pub struct TextureAtlasLayout {
+    pub rects: Vec<URect>,
+}
+
+impl TextureAtlasLayout {
+    /// A grid of `columns` by `rows` tiles, row by row from the top left. `padding` is between
+    /// tiles, `offset` before the first one.
+    pub fn from_grid(
+        tile_size: UVec2,
+        columns: u32,
+        rows: u32,
+        padding: Option<UVec2>,
+        offset: Option<UVec2>,
+    ) -> Self {
+        let padding = padding.unwrap_or_default();
+        let offset = offset.unwrap_or_default();
+        let rects = (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| UVec2::new(column, row)))
+            .map(|cell| {
+                let min = offset + cell * (tile_size + padding);
+                URect::from_corners(min, min + tile_size)
+            })
+            .collect();
+
+        Self {
+            rects,
+        }
+    }
+
+    /// Adds a region, returning its index.
+    pub fn add_texture(&mut self, rect: URect) -> usize {
+        self.rects.push(rect);
+        self.rects.len() - 1
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.rects.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+}
+
+/// Draws a region of a texture atlas instead of [`VulkanoSprite::rect`]. Change `index` to
+/// animate a sprite.
+#[derive(Component, Clone, Debug)]
+pub struct VulkanoTextureAtlas {
+    pub layout: Arc<TextureAtlasLayout>,
+    pub index: usize,
+}
+
+/// Marks a [`VulkanoCamera`] as seeing [`VulkanoSprite`]s, which are rendered into its target, in
+/// its viewport. Sprites lie in the `z = 0` plane, which has to be between the near and far planes
+/// of the projection to be seen. They are drawn in order rather than depth tested.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct VulkanoCamera2d {
+    /// The color to clear the camera's viewport to, in linear RGBA, or `None` to draw over what's
+    /// there.
+    pub clear_color: Option<[f32; 4]>,
+}
+
+impl Default for VulkanoCamera2d {
+    fn default() -> Self {
+        Self {
+            clear_color: Some([0.0, 0.0, 0.0, 1.0]),
+        }
+    }
+}
+
+impl VulkanoCamera2d {
+    /// A camera into the primary window in logical pixels, at `scale` world units per pixel, see
+    /// [`crate::CameraProjection::Pixels`]. It clears to black.
+    pub fn bundle(scale: f32) -> (VulkanoCamera, VulkanoCamera2d) {
+        (
+            VulkanoCamera::pixels(scale, -1000.0, 1000.0),
+            VulkanoCamera2d::default(),
+        )
+    }
+}
+
+#[repr(C)]
+#[derive(BufferContents, Vertex, Clone, Copy)]
+struct SpriteInstance {
+    /// The bottom left corner, in world space.
+    #[format(R32G32_SFLOAT)]
+    origin: [f32; 2],
+    /// The bottom edge, from left to right.
+    #[format(R32G32_SFLOAT)]
+    axis_x: [f32; 2],
+    /// The left edge, from bottom to top.
+    #[format(R32G32_SFLOAT)]
+    axis_y: [f32; 2],
+    /// The min and max texture coordinates.
+    #[format(R32G32B32A32_SFLOAT)]
+    uv_rect: [f32; 4],
+    #[format(R32G32B32A32_SFLOAT)]
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(BufferContents)]
+struct PushConstants {
+    view_proj: [[f32; 4]; 4],
+    output_in_linear_colorspace: u32,
+}
+
+/// A sprite extracted from the world, in world space.
+struct ExtractedSprite {
+    instance: SpriteInstance,
+    texture: Option<SpriteTexture>,
+    z_order: f32,
+    /// The corners' bounding box, for culling.
+    bounds: Rect,
+}
+
+/// What a camera sees of the sprites this frame.
+struct SpriteView {
+    order: isize,
+    view_proj: Mat4,
+    /// [`VulkanoCamera::viewport`], made into pixels of the framebuffer when drawn.
+    viewport: Rect,
+    clear_color: Option<[f32; 4]>,
+    batches: Range<usize>,
+}
+
+/// Where cameras render sprites into.
+enum SpriteTarget {
+    /// The swapchain image of a window, acquired and presented here.
+    Window(Entity),
+    /// Any other image view, rendered into in a submission of its own.
+    Image(Arc<ImageView>),
+}
+
+impl SpriteTarget {
+    fn is(&self, other: &SpriteTarget) -> bool {
+        match (self, other) {
+            (SpriteTarget::Window(a), SpriteTarget::Window(b)) => a == b,
+            (SpriteTarget::Image(a), SpriteTarget::Image(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Consecutive instances drawn with the same texture.
+struct SpriteBatch {
+    texture: Option<SpriteTexture>,
+    instances: Range<u32>,
+}
+
+/// The render passes used to draw onto a swapchain image of some format.
+struct SpriteRenderPasses {
+    load: Arc<RenderPass>,
+    clear: Arc<RenderPass>,
+}
+
+/// The pipelines and buffers drawing sprites, and what's kept between frames.
+pub struct SpriteRenderer {
+    device: Arc<Device>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    instance_allocator: SubbufferAllocator,
+    vs: EntryPoint,
+    fs: EntryPoint,
+    layout: Arc<PipelineLayout>,
+    render_passes: PerSwapchainFormat<SpriteRenderPasses>,
+    /// Pipelines by swapchain format, made for the `load` render pass. They are compatible with
+    /// `clear`.
+    pipelines: PerSwapchainFormat<Arc<GraphicsPipeline>>,
+    /// Descriptor sets of the textures, and the view they were made for.
+    descriptor_sets: HashMap<SpriteTexture, (Arc<ImageView>, Arc<DescriptorSet>)>,
+    /// Used by sprites without a texture.
+    white: Arc<DescriptorSet>,
+    white_upload: UploadHandle,
+    /// The last submission rendering into targets other than swapchain images.
+    last_submission: Option<Arc<FenceSignalFuture<Box<dyn GpuFuture>>>>,
+    /// Reused every frame, so they don't allocate.
+    extracted: Vec<ExtractedSprite>,
+    instances: Vec<SpriteInstance>,
+    batches: Vec<SpriteBatch>,
+}
+
+impl FromWorld for SpriteRenderer {
+    fn from_world(world: &mut World) -> Self {
+        let context = world.resource::<BevyVulkanoContext>();
+        let device = context.device().clone();
+        let memory_allocator = context.memory_allocator().clone();
+
+        let vs = vs::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let fs = fs::load(device.clone())
+            .unwrap()
+            .entry_point("main")
+            .unwrap();
+        let layout = PipelineLayout::new(
+            device.clone(),
+            PipelineDescriptorSetLayoutCreateInfo::from_stages(&[
+                PipelineShaderStageCreateInfo::new(vs.clone()),
+                PipelineShaderStageCreateInfo::new(fs.clone()),
+            ])
+            .into_pipeline_layout_create_info(device.clone())
+            .unwrap(),
+        )
+        .unwrap();
+        let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
+            device.clone(),
+            Default::default(),
+        ));
+
+        let white_image = Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format: Format::R8G8B8A8_UNORM,
+                extent: [1, 1, 1],
+                usage: ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo::default(),
+        )
+        .unwrap();
+        let white_upload = world
+            .non_send_resource_mut::<GpuUploader>()
+            .upload_image([[255u8; 4]], white_image.clone());
+        let white = texture_descriptor_set(
+            &descriptor_set_allocator,
+            &layout,
+            ImageView::new_default(white_image).unwrap(),
+            Filter::Nearest,
+        );
+
+        Self {
+            command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
+                device.clone(),
+                Default::default(),
+            )),
+            descriptor_set_allocator,
+            instance_allocator: SubbufferAllocator::new(
+                memory_allocator,
+                SubbufferAllocatorCreateInfo {
+                    buffer_usage: BufferUsage::VERTEX_BUFFER,
+                    memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                        | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                    ..Default::default()
+                },
+            ),
+            device,
+            vs,
+            fs,
+            layout,
+            render_passes: PerSwapchainFormat::default(),
+            pipelines: PerSwapchainFormat::default(),
+            descriptor_sets: HashMap::default(),
+            white,
+            white_upload,
+            last_submission: None,
+            extracted: Vec::new(),
+            instances: Vec::new(),
+            batches: Vec::new(),
+        }
+    }
+}
+
+impl SpriteRenderer {
+    /// Joins `future` with the last submission rendering sprites into offscreen targets, images
+    /// and additional image views, so that whatever comes after can safely use them. Windows
+    /// rendered here already wait for it.
+    pub fn join(&self, future: Box<dyn GpuFuture>) -> Box<dyn GpuFuture> {
+        match self.last_submission.clone() {
+            Some(sprite_future) => future.join(sprite_future).boxed(),
+            None => future,
+        }
+    }
+
+    /// The futures of the textures' uploads still in flight.
+    fn upload_futures(
+        &self,
+        textures: &SpriteTextures,
+        uploader: &mut GpuUploader,
+    ) -> Result<Vec<UploadFuture>, Validated<VulkanError>> {
+        let mut futures = Vec::new();
+        for upload in textures
+            .textures
+            .values()
+            .filter_map(|entry| entry.upload)
+            .chain([self.white_upload])
+        {
+            futures.extend(uploader.future(upload)?);
+        }
+
+        Ok(futures)
+    }
+
+    /// Turns the sprites into world space instances, sorted into the order they are drawn.
+    fn extract<'a>(
+        &mut self,
+        sprites: impl Iterator<
+            Item = (
+                &'a VulkanoSprite,
+                &'a GlobalTransform,
+                Option<&'a VulkanoTextureAtlas>,
+            ),
+        >,
+        textures: &SpriteTextures,
+    ) {
+        self.extracted.clear();
+
+        for (sprite, transform, atlas) in sprites {
+            let texture_size = match sprite.texture {
+                Some(texture) => match textures.size(texture) {
+                    Some(size) => Some(size.as_vec2()),
+                    // Removed, skip it.
+                    None => continue,
+                },
+                None => None,
+            };
+            let rect = match atlas {
+                Some(atlas) => match atlas.layout.rects.get(atlas.index) {
+                    Some(rect) => Some(rect.as_rect()),
+                    None => continue,
+                },
+                None => sprite.rect,
+            };
+            let size = sprite
+                .custom_size
+                .or_else(|| rect.map(|rect| rect.size()))
+                .or(texture_size)
+                .unwrap_or(Vec2::ONE);
+
+            let mut uv_rect = match (rect, texture_size) {
+                (Some(rect), Some(texture_size)) => {
+                    [rect.min / texture_size, rect.max / texture_size]
+                }
+                _ => [Vec2::ZERO, Vec2::ONE],
+            };
+            if sprite.flip_x {
+                std::mem::swap(&mut uv_rect[0].x, &mut uv_rect[1].x);
+            }
+            if sprite.flip_y {
+                std::mem::swap(&mut uv_rect[0].y, &mut uv_rect[1].y);
+            }
+
+            let affine = transform.affine();
+            let bottom_left = (Vec2::splat(-0.5) - sprite.anchor.as_vec()) * size;
+            let origin = affine.transform_point3(bottom_left.extend(0.0)).truncate();
+            let axis_x = affine.transform_vector3(Vec3::X * size.x).truncate();
+            let axis_y = affine.transform_vector3(Vec3::Y * size.y).truncate();
+            let bounds = [origin + axis_x, origin + axis_y, origin + axis_x + axis_y]
+                .into_iter()
+                .fold(Rect::from_corners(origin, origin), |bounds, corner| {
+                    bounds.union_point(corner)
+                });
+
+            self.extracted.push(ExtractedSprite {
+                instance: SpriteInstance {
+                    origin: origin.to_array(),
+                    axis_x: axis_x.to_array(),
+                    axis_y: axis_y.to_array(),
+                    uv_rect: [uv_rect[0].x, uv_rect[0].y, uv_rect[1].x, uv_rect[1].y],
+                    color: sprite.color,
+                },
+                texture: sprite.texture,
+                z_order: sprite.z_order,
+                bounds,
+            });
+        }
+
+        // Sprites at the same z order are grouped by texture, for fewer batches.
+        self.extracted.sort_by(|a, b| {
+            a.z_order
+                .total_cmp(&b.z_order)
+                .then_with(|| a.texture.cmp(&b.texture))
+        });
+    }
+
+    /// Appends the instances `view` sees to `self.instances`, and their batches to
+    /// `self.batches`. Returns the range of batches added.
+    fn batch(&mut self, view: Rect) -> Range<usize> {
+        let first_batch = self.batches.len();
+
+        for sprite in &self.extracted {
+            if sprite.bounds.intersect(view).is_empty() {
+                continue;
+            }
+            let index = self.instances.len() as u32;
+            self.instances.push(sprite.instance);
+
+            // Batches don't continue across cameras.
+            let batch = self.batches[first_batch..].last_mut();
+            match batch {
+                Some(batch) if batch.texture == sprite.texture => batch.instances.end = index + 1,
+                _ => self.batches.push(SpriteBatch {
+                    texture: sprite.texture,
+                    instances: index..index + 1,
+                }),
+            }
+        }
+
+        first_batch..self.batches.len()
+    }
+
+    /// Keeps descriptor sets of the textures in use up to date, and drops the others.
+    fn update_descriptor_sets(&mut self, textures: &SpriteTextures) {
+        self.descriptor_sets
+            .retain(|texture, (view, _)| match textures.textures.get(texture) {
+                Some(entry) => Arc::ptr_eq(&entry.view, view),
+                None => false,
+            });
+        for (texture, entry) in &textures.textures {
+            if !self.descriptor_sets.contains_key(texture) {
+                let descriptor_set = texture_descriptor_set(
+                    &self.descriptor_set_allocator,
+                    &self.layout,
+                    entry.view.clone(),
+                    entry.filter,
+                );
+                self.descriptor_sets
+                    .insert(*texture, (entry.view.clone(), descriptor_set));
+            }
+        }
+    }
+
+    fn render_passes(&mut self, format: Format) -> &SpriteRenderPasses {
+        let device = self.device.clone();
+        self.render_passes
+            .get_or_insert_with(format, |format| SpriteRenderPasses {
+                load: vulkano::single_pass_renderpass!(
+                    device.clone(),
+                    attachments: {
+                        color: {
+                            format: format,
+                            samples: 1,
+                            load_op: Load,
+                            store_op: Store,
+                        },
+                    },
+                    pass: {
+                        color: [color],
+                        depth_stencil: {},
+                    },
+                )
+                .unwrap(),
+                clear: vulkano::single_pass_renderpass!(
+                    device.clone(),
+                    attachments: {
+                        color: {
+                            format: format,
+                            samples: 1,
+                            load_op: Clear,
+                            store_op: Store,
+                        },
+                    },
+                    pass: {
+                        color: [color],
+                        depth_stencil: {},
+                    },
+                )
+                .unwrap(),
+            })
+    }
+
+    /// The render pass for `views`, clearing the target if the first one has a clear color.
+    fn render_pass(&mut self, format: Format, views: &[SpriteView]) -> Arc<RenderPass> {
+        let render_passes = self.render_passes(format);
+        match views.first().and_then(|view| view.clear_color) {
+            Some(_) => render_passes.clear.clone(),
+            None => render_passes.load.clone(),
+        }
+    }
+
+    fn pipeline(&mut self, format: Format) -> Arc<GraphicsPipeline> {
+        let subpass = Subpass::from(self.render_passes(format).load.clone(), 0).unwrap();
+        let (device, vs, fs, layout) = (&self.device, &self.vs, &self.fs, &self.layout);
+
+        self.pipelines
+            .get_or_insert_with(format, |_| {
+                let vertex_input_state = SpriteInstance::per_instance().definition(vs).unwrap();
+                let stages = [
+                    PipelineShaderStageCreateInfo::new(vs.clone()),
+                    PipelineShaderStageCreateInfo::new(fs.clone()),
+                ];
+
+                GraphicsPipeline::new(device.clone(), None, GraphicsPipelineCreateInfo {
+                    stages: stages.into_iter().collect(),
+                    vertex_input_state: Some(vertex_input_state),
+                    // Each instance is a quad of four vertices.
+                    input_assembly_state: Some(InputAssemblyState {
+                        topology: PrimitiveTopology::TriangleStrip,
+                        ..Default::default()
+                    }),
+                    viewport_state: Some(ViewportState::default()),
+                    rasterization_state: Some(RasterizationState::default()),
+                    multisample_state: Some(MultisampleState::default()),
+                    color_blend_state: Some(ColorBlendState::with_attachment_states(
+                        1,
+                        ColorBlendAttachmentState {
+                            blend: Some(AttachmentBlend::alpha()),
+                            ..Default::default()
+                        },
+                    )),
+                    dynamic_state: [DynamicState::Viewport, DynamicState::Scissor]
+                        .into_iter()
+                        .collect(),
+                    subpass: Some(subpass.into()),
+                    ..GraphicsPipelineCreateInfo::layout(layout.clone())
+                })
+                .unwrap()
+            })
+            .clone()
+    }
+
+    /// Records drawing `views` into `framebuffer`, made for [`Self::render_pass`], from the first
+    /// to the last. The first view's clear color clears the whole target, later ones' only their
+    /// viewport.
+    fn record<L>(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<L>,
+        framebuffer: Arc<Framebuffer>,
+        views: &[SpriteView],
+        instance_buffer: Option<&Subbuffer<[SpriteInstance]>>,
+    ) {
+        let format = framebuffer.attachments()[0].format();
+        let extent = framebuffer.extent();
+        let pipeline = self.pipeline(format);
+        let output_in_linear_colorspace =
+            format.numeric_format_color() == Some(NumericFormat::SRGB);
+        let clear_color = views.first().and_then(|view| view.clear_color);
+
+        builder
+            .begin_render_pass(
+                RenderPassBeginInfo {
+                    clear_values: vec![clear_color.map(Into::into)],
+                    ..RenderPassBeginInfo::framebuffer(framebuffer)
+                },
+                SubpassBeginInfo {
+                    contents: SubpassContents::Inline,
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+            .bind_pipeline_graphics(pipeline)
+            .unwrap();
+        if let Some(instance_buffer) = instance_buffer {
+            builder
+                .bind_vertex_buffers(0, instance_buffer.clone())
+                .unwrap();
+        }
+
+        for (index, view) in views.iter().enumerate() {
+            let Some((viewport, scissor)) = viewport_and_scissor(view.viewport, extent) else {
+                continue;
+            };
+            if let (Some(clear_color), true) = (view.clear_color, index > 0) {
+                builder
+                    .clear_attachments(
+                        [ClearAttachment::Color {
+                            color_attachment: 0,
+                            clear_value: clear_color.into(),
+                        }]
+                        .into_iter()
+                        .collect(),
+                        [ClearRect {
+                            offset: scissor.offset,
+                            extent: scissor.extent,
+                            array_layers: 0..1,
+                        }]
+                        .into_iter()
+                        .collect(),
+                    )
+                    .unwrap();
+            }
+            builder
+                .set_viewport(0, [viewport].into_iter().collect())
+                .unwrap()
+                .set_scissor(0, [scissor].into_iter().collect())
+                .unwrap()
+                .push_constants(self.layout.clone(), 0, PushConstants {
+                    view_proj: view.view_proj.to_cols_array_2d(),
+                    output_in_linear_colorspace: output_in_linear_colorspace as u32,
+                })
+                .unwrap();
+
+            for batch in &self.batches[view.batches.clone()] {
+                let descriptor_set = match batch.texture {
+                    Some(texture) => match self.descriptor_sets.get(&texture) {
+                        Some((_, descriptor_set)) => descriptor_set.clone(),
+                        None => continue,
+                    },
+                    None => self.white.clone(),
+                };
+                builder
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Graphics,
+                        self.layout.clone(),
+                        0,
+                        descriptor_set,
+                    )
+                    .unwrap();
+
+                unsafe {
+                    builder
+                        .draw(4, batch.instances.len() as u32, 0, batch.instances.start)
+                        .unwrap();
+                }
+            }
+        }
+
+        builder.end_render_pass(Default::default()).unwrap();
+    }
+}
+
+fn texture_descriptor_set(
+    descriptor_set_allocator: &Arc<StandardDescriptorSetAllocator>,
+    layout: &Arc<PipelineLayout>,
+    view: Arc<ImageView>,
+    filter: Filter,
+) -> Arc<DescriptorSet> {
+    let sampler = Sampler::new(layout.device().clone(), SamplerCreateInfo {
+        mag_filter: filter,
+        min_filter: filter,
+        address_mode: [SamplerAddressMode::ClampToEdge; 3],
+        ..Default::default()
+    })
+    .unwrap();
+
+    DescriptorSet::new(
+        descriptor_set_allocator.clone(),
+        layout.set_layouts()[0].clone(),
+        [WriteDescriptorSet::image_view_sampler(0, view, sampler)],
+        [],
+    )
+    .unwrap()
+}
+
+/// The world space bounding box of what `view_proj` sees, for culling.
+fn view_bounds(view_proj: Mat4) -> Rect {
+    let inverse = view_proj.inverse();
+    // The corners of Vulkan's clip space, depth from 0 to 1.
+    let corners = [0.0, 1.0].into_iter().flat_map(|z| {
+        [[-1.0, -1.0], [1.0, -1.0], [-1.0, 1.0], [1.0, 1.0]]
+            .map(|[x, y]| inverse.project_point3(Vec3::new(x, y, z)).truncate())
+    });
+    corners
+        .fold(None, |bounds: Option<Rect>, corner| {
+            Some(match bounds {
+                Some(bounds) => bounds.union_point(corner),
+                None => Rect::from_corners(corner, corner),
+            })
+        })
+        .unwrap()
+}
+
+/// Extracts the sprites, and renders them through every [`VulkanoCamera2d`] into its target.
+pub(crate) fn render_sprites(
+    context: Res<BevyVulkanoContext>,
+    mut sprite_renderer: NonSendMut<SpriteRenderer>,
+    textures: Res<SpriteTextures>,
+    sprites: Query<(
+        &VulkanoSprite,
+        &GlobalTransform,
+        Option<&VulkanoTextureAtlas>,
+    )>,
+    cameras: Query<(&VulkanoCamera, &CameraMatrices, &VulkanoCamera2d)>,
+    mut renderers: VulkanoRenderers,
+    mut uploader: NonSendMut<GpuUploader>,
+) {
+    let mut targets = Vec::<(SpriteTarget, Vec<SpriteView>)>::new();
+    for (camera, matrices, camera_2d) in &cameras {
+        let target = match &camera.target {
+            CameraTarget::PrimaryWindow | CameraTarget::Window(_) => match matrices.window {
+                Some(window) => SpriteTarget::Window(window),
+                None => continue,
+            },
+            CameraTarget::AdditionalImageView {
+                window,
+                key,
+            } => {
+                let Some(renderer) = renderers.get_renderer(*window) else {
+                    continue;
+                };
+                match renderer.additional_image_views().get(key) {
+                    Some(view) => SpriteTarget::Image(view.clone()),
+                    None => continue,
+                }
+            }
+            CameraTarget::Offscreen(entity) => match renderers.get_offscreen_target(*entity) {
+                // Sprites are drawn into the single sampled image, without multisampling.
+                Some(offscreen) if offscreen.format().aspects().intersects(ImageAspects::COLOR) => {
+                    SpriteTarget::Image(offscreen.sampled_image_view())
+                }
+                _ => continue,
+            },
+            CameraTarget::Image(view) => SpriteTarget::Image(view.clone()),
+        };
+
+        let view = SpriteView {
+            order: camera.order,
+            view_proj: matrices.view_proj,
+            viewport: camera.viewport,
+            clear_color: camera_2d.clear_color,
+            batches: 0..0,
+        };
+        match targets.iter_mut().find(|(other, _)| other.is(&target)) {
+            Some((_, views)) => views.push(view),
+            None => targets.push((target, vec![view])),
+        }
+    }
+    if targets.is_empty() {
+        return;
+    }
+
+    let sprite_renderer = &mut *sprite_renderer;
+    // Before acquiring, so a failed upload skips the frame without holding an image.
+    let uploads = match sprite_renderer.upload_futures(&textures, &mut uploader) {
+        Ok(uploads) => uploads,
+        Err(e) => {
+            error!("Failed to submit the sprite textures: {e}");
+            return;
+        }
+    };
+    let join_uploads = |before: Box<dyn GpuFuture>| {
+        uploads
+            .iter()
+            .fold(before, |before, upload| before.join(upload.clone()).boxed())
+    };
+    sprite_renderer.extract(sprites.iter(), &textures);
+    sprite_renderer.update_descriptor_sets(&textures);
+
+    // Lay out every camera's instances first, they all go in one buffer.
+    sprite_renderer.instances.clear();
+    sprite_renderer.batches.clear();
+    for (_, views) in &mut targets {
+        views.sort_by_key(|view| view.order);
+        for view in views {
+            view.batches = sprite_renderer.batch(view_bounds(view.view_proj));
+        }
+    }
+    let instance_buffer = (!sprite_renderer.instances.is_empty()).then(|| {
+        let instance_buffer = sprite_renderer
+            .instance_allocator
+            .allocate_slice(sprite_renderer.instances.len() as u64)
+            .unwrap();
+        instance_buffer
+            .write()
+            .unwrap()
+            .copy_from_slice(&sprite_renderer.instances);
+        instance_buffer
+    });
+
+    // Images first, so windows showing them this frame can wait for them.
+    let mut builder = AutoCommandBufferBuilder::primary(
+        sprite_renderer.command_buffer_allocator.clone(),
+        context.graphics_queue().queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+    let mut renders_images = false;
+    for (target, views) in &targets {
+        let SpriteTarget::Image(image_view) = target else {
+            continue;
+        };
+        let render_pass = sprite_renderer.render_pass(image_view.format(), views);
+        let framebuffer = match Framebuffer::new(render_pass, FramebufferCreateInfo {
+            attachments: vec![image_view.clone()],
+            ..Default::default()
+        }) {
+            Ok(framebuffer) => framebuffer,
+            Err(e) => {
+                error!("Failed to create the sprite framebuffer: {e}");
+                continue;
+            }
+        };
+        sprite_renderer.record(&mut builder, framebuffer, views, instance_buffer.as_ref());
+        renders_images = true;
+    }
+    if renders_images {
+        let device = context.device().clone();
+        // Chain onto the previous submission while it's in flight, so that vulkano knows about
+        // the resources it still uses.
+        let before = match sprite_renderer.last_submission.take() {
+            Some(previous) if !previous.is_signaled().unwrap_or(false) => previous.boxed(),
+            Some(previous) => {
+                previous.wait(None).unwrap_or_else(|e| error!("{e}"));
+                sync::now(device.clone()).boxed()
+            }
+            None => sync::now(device.clone()).boxed(),
+        };
+        let before = join_uploads(before);
+
+        match before
+            .then_execute(context.graphics_queue().clone(), builder.build().unwrap())
+            .unwrap()
+            .boxed()
+            .then_signal_fence_and_flush()
+        {
+            Ok(future) => sprite_renderer.last_submission = Some(Arc::new(future)),
+            Err(e) => error!("Failed to submit sprites: {e}"),
+        }
+    }
+
+    for (target, views) in &targets {
+        let SpriteTarget::Window(window) = target else {
+            continue;
+        };
+        let Some(mut renderer) = renderers.get_renderer(*window) else {
+            continue;
+        };
+
+        let before = match renderer.acquire(Some(Duration::from_millis(1000)), |_| {}) {
+            Ok(before) => before,
+            Err(VulkanError::OutOfDate | VulkanError::FullScreenExclusiveModeLost) => continue,
+            Err(e) => {
+                warn!("Skipping a frame of sprites, failed to acquire an image: {e}");
+                continue;
+            }
+        };
+        let before = join_uploads(before);
+        let before = sprite_renderer.join(before);
+
+        let render_pass = sprite_renderer.render_pass(renderer.swapchain_format(), views);
+        let framebuffer = match renderer.framebuffer(&render_pass, &[]) {
+            Ok(framebuffer) => framebuffer,
+            Err(e) => {
+                error!("Failed to create the sprite framebuffer: {e}");
+                renderer.present(before, false);
+                continue;
+            }
+        };
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            sprite_renderer.command_buffer_allocator.clone(),
+            context.graphics_queue().queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        sprite_renderer.record(&mut builder, framebuffer, views, instance_buffer.as_ref());
+
+        let after = before
+            .then_execute(context.graphics_queue().clone(), builder.build().unwrap())
+            .unwrap()
+            .boxed();
+        renderer.present(after, false);
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+#version 450
+layout(location = 0) in vec2 origin;
+layout(location = 1) in vec2 axis_x;
+layout(location = 2) in vec2 axis_y;
+layout(location = 3) in vec4 uv_rect;
+layout(location = 4) in vec4 color;
+
+layout(location = 0) out vec2 v_tex_coords;
+layout(location = 1) out vec4 v_color;
+
+layout(push_constant) uniform PushConstants {
+    mat4 view_proj;
+    uint output_in_linear_colorspace;
+} push_constants;
+
+void main() {
+    // The corners of the quad, as a triangle strip.
+    vec2 corner = vec2(gl_VertexIndex & 1, gl_VertexIndex >> 1);
+    vec2 position = origin + corner.x * axis_x + corner.y * axis_y;
+    // Sprites are ordered by drawing them in order, not by depth, as there is no depth test.
+    gl_Position = push_constants.view_proj * vec4(position, 0.0, 1.0);
+    // Texture coordinates grow downwards.
+    v_tex_coords = mix(uv_rect.xw, uv_rect.zy, corner);
+    v_color = color;
+}",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+#version 450
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 1) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D sprite_texture;
+
+layout(push_constant) uniform PushConstants {
+    mat4 view_proj;
+    uint output_in_linear_colorspace;
+} push_constants;
+
+vec3 srgb_from_linear(vec3 linear) {
+    bvec3 cutoff = lessThan(linear, vec3(0.0031308));
+    vec3 lower = linear * vec3(12.92);
+    vec3 higher = vec3(1.055) * pow(linear, vec3(1.0 / 2.4)) - vec3(0.055);
+    return mix(higher, lower, vec3(cutoff));
+}
+
+void main() {
+    vec4 color = texture(sprite_texture, v_tex_coords) * v_color;
+    // Without an sRGB target, the conversion has to be done here.
+    if (push_constants.output_in_linear_colorspace == 0) {
+        color.rgb = srgb_from_linear(color.rgb);
+    }
+    f_color = color;
+}",
+    }
+}
@@ -0,0 +1,274 @@
+use std::sync::Arc;
+
+use bevy::{prelude::*, utils::HashMap};
+use vulkano::{
+    device::DeviceOwned,
+    format::Format,
+    image::{view::ImageView, Image, ImageCreateInfo, ImageType, ImageUsage, SampleCount},
+    memory::{
+        allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+        MemoryPropertyFlags,
+    },
+    DeviceSize,
+};
+
+use crate::{renderer::image_bytes, BevyVulkanoContext};
+
+/// How many frames an image has to go unused before another window may take it. Frames of the same
+/// window are chained, so it can reuse its images every frame, but other windows' frames may still
+/// be in flight.
+const FRAMES_IN_FLIGHT: u64 = 3;
+
+/// How big a transient image is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransientExtent {
+    /// A fraction of the swapchain image size, `1.0` is the same size.
+    SwapchainRelative(f32),
+    /// A fixed size in pixels.
+    Absolute([u32; 2]),
+}
+
+impl TransientExtent {
+    fn resolve(self, swapchain_image_size: [u32; 2]) -> [u32; 2] {
+        match self {
+            TransientExtent::SwapchainRelative(scale) => {
+                swapchain_image_size.map(|side| ((side as f32 * scale).round() as u32).max(1))
+            }
+            TransientExtent::Absolute(extent) => extent,
+        }
+    }
+}
+
+/// What a transient image is made for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransientImageInfo {
+    pub format: Format,
+    pub usage: ImageUsage,
+    pub samples: SampleCount,
+    pub extent: TransientExtent,
+}
+
+impl TransientImageInfo {
+    /// An image without multisampling, the size of the swapchain.
+    pub fn swapchain_sized(format: Format, usage: ImageUsage) -> Self {
+        Self {
+            format,
+            usage,
+            samples: SampleCount::Sample1,
+            extent: TransientExtent::SwapchainRelative(1.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct ImageKey {
+    format: Format,
+    usage: ImageUsage,
+    samples: SampleCount,
+    extent: [u32; 2],
+}
+
+struct PooledImage {
+    view: Arc<ImageView>,
+    /// The window that used it last.
+    window: Entity,
+    last_used_frame: u64,
+}
+
+/// Depth buffers, G-buffers and other intermediate targets whose contents don't outlive a frame.
+///
+/// Images are made on demand, and handed out again whenever an image of the same format, usage,
+/// samples and extent is asked for and isn't in use: by the same window in a later frame, or by any
+/// window once it has been unused for a few frames. Images that aren't asked for in a few frames are
+/// freed, in [`Last`].
+///
+/// Images only used as attachments are made with [`ImageUsage::TRANSIENT_ATTACHMENT`], and in lazily
+/// allocated memory if the device has any. On tiled gpus they may then take no memory at all.
+/// [`crate::renderer::VulkanoWindowRenderer::add_transient_image_view`] makes images that resize
+/// with a window the same way.
+///
+/// Reuse is per image: images of different formats, usages or extents never share memory. Aliasing
+/// their memory between passes that don't overlap would need barriers between the aliased images,
+/// which vulkano doesn't track.
+#[derive(Resource)]
+pub struct TransientImagePool {
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    images: HashMap<ImageKey, Vec<PooledImage>>,
+    frame: u64,
+    allocation_count: u64,
+    /// How many frames an image can go unused before it is freed.
+    pub max_unused_frames: u64,
+}
+
+impl FromWorld for TransientImagePool {
+    fn from_world(world: &mut World) -> Self {
+        let context = world.resource::<BevyVulkanoContext>();
+
+        Self {
+            memory_allocator: context.memory_allocator().clone(),
+            images: HashMap::default(),
+            frame: 0,
+            allocation_count: 0,
+            max_unused_frames: FRAMES_IN_FLIGHT,
+        }
+    }
+}
+
+impl TransientImagePool {
+    /// Returns an image for `window` this frame, reusing one if it can. `swapchain_image_size` is
+    /// what [`TransientExtent::SwapchainRelative`] is relative to, usually
+    /// [`crate::renderer::VulkanoWindowRenderer::swapchain_image_size`].
+    ///
+    /// Asking again for the same info in the same frame returns a different image.
+    pub fn get(
+        &mut self,
+        window: Entity,
+        swapchain_image_size: [u32; 2],
+        info: TransientImageInfo,
+    ) -> Arc<ImageView> {
+        let key = ImageKey {
+            format: info.format,
+            usage: info.usage,
+            samples: info.samples,
+            extent: info.extent.resolve(swapchain_image_size),
+        };
+        let frame = self.frame;
+        let images = self.images.entry(key).or_default();
+
+        // Prefer the window's own images, they don't have to wait for other frames to finish.
+        let reusable = images
+            .iter()
+            .position(|image| image.window == window && image.last_used_frame < frame)
+            .or_else(|| {
+                images
+                    .iter()
+                    .position(|image| image.last_used_frame + FRAMES_IN_FLIGHT <= frame)
+            });
+        if let Some(i) = reusable {
+            let image = &mut images[i];
+            image.window = window;
+            image.last_used_frame = frame;
+            return image.view.clone();
+        }
+
+        let view = create_transient_image(
+            &self.memory_allocator,
+            key.format,
+            key.extent,
+            key.samples,
+            key.usage,
+        );
+        self.allocation_count += 1;
+        images.push(PooledImage {
+            view: view.clone(),
+            window,
+            last_used_frame: frame,
+        });
+
+        view
+    }
+
+    /// How many images the pool holds.
+    pub fn len(&self) -> usize {
+        self.images.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Estimates the memory held by the pool's images, from their extents, formats and sample
+    /// counts. Lazily allocated images are counted at their full size.
+    pub fn memory_bytes(&self) -> DeviceSize {
+        self.images
+            .values()
+            .flatten()
+            .map(|image| image_bytes(&image.view))
+            .sum()
+    }
+
+    /// How many images the pool has made since it was created, freed ones included.
+    #[inline]
+    pub fn allocation_count(&self) -> u64 {
+        self.allocation_count
+    }
+
+    /// Frees every image.
+    pub fn clear(&mut self) {
+        self.images.clear();
+    }
+}
+
+/// Makes a 2D image and its view. Images only used as attachments are made with
+/// [`ImageUsage::TRANSIENT_ATTACHMENT`], in lazily allocated memory if the device has any.
+pub(crate) fn create_transient_image(
+    memory_allocator: &Arc<StandardMemoryAllocator>,
+    format: Format,
+    extent: [u32; 2],
+    samples: SampleCount,
+    usage: ImageUsage,
+) -> Arc<ImageView> {
+    // Transient attachments can't be used for anything but attachments.
+    let attachment_usage = ImageUsage::COLOR_ATTACHMENT
+        | ImageUsage::DEPTH_STENCIL_ATTACHMENT
+        | ImageUsage::INPUT_ATTACHMENT;
+    let transient = attachment_usage.contains(usage);
+
+    let has_lazily_allocated_memory = memory_allocator
+        .device()
+        .physical_device()
+        .memory_properties()
+        .memory_types
+        .iter()
+        .any(|memory_type| {
+            memory_type
+                .property_flags
+                .intersects(MemoryPropertyFlags::LAZILY_ALLOCATED)
+        });
+    let memory_type_filter = if transient && has_lazily_allocated_memory {
+        MemoryTypeFilter {
+            preferred_flags: MemoryPropertyFlags::DEVICE_LOCAL
+                | MemoryPropertyFlags::LAZILY_ALLOCATED,
+            ..MemoryTypeFilter::PREFER_DEVICE
+        }
+    } else {
+        MemoryTypeFilter::PREFER_DEVICE
+    };
+
+    ImageView::new_default(
+        Image::new(
+            memory_allocator.clone(),
+            ImageCreateInfo {
+                image_type: ImageType::Dim2d,
+                format,
+                extent: [extent[0], extent[1], 1],
+                usage: if transient {
+                    usage | ImageUsage::TRANSIENT_ATTACHMENT
+                } else {
+                    usage
+                },
+                samples,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter,
+                ..Default::default()
+            },
+        )
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+/// Frees the images that haven't been used in a while, and starts the next frame.
+pub(crate) fn free_unused_transient_images(mut pool: ResMut<TransientImagePool>) {
+    let pool = &mut *pool;
+    let frame = pool.frame;
+    let max_unused_frames = pool.max_unused_frames;
+
+    for images in pool.images.values_mut() {
+        images.retain(|image| image.last_used_frame + max_unused_frames >= frame);
+    }
+    pool.images.retain(|_, images| !images.is_empty());
+    pool.frame += 1;
+}
@@ -0,0 +1,398 @@
+use std::{fmt, ops::Deref, sync::Arc};
+
+use bevy::prelude::*;
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        CopyBufferInfo, CopyBufferToImageInfo,
+    },
+    device::Queue,
+    image::Image,
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    sync::{self, future::FenceSignalFuture, GpuFuture, Sharing},
+    DeviceSize, Validated, VulkanError,
+};
+
+use crate::BevyVulkanoContext;
+
+/// The future of a submitted upload batch. It can be cloned and joined into as many frames as you
+/// like.
+pub type UploadFuture = Arc<FenceSignalFuture<Box<dyn GpuFuture>>>;
+
+/// Identifies the batch an upload was placed in. Pass it to [`GpuUploader::join`] before using the
+/// destination of the upload on the gpu.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UploadHandle {
+    batch: u64,
+}
+
+/// An upload that couldn't be submitted, returned by [`GpuUploader::join`]. It stays pending, and
+/// is retried on the next flush.
+pub struct UploadFailed {
+    /// The future that was to be joined with the upload, so the frame can still be presented or
+    /// waited on without it.
+    pub future: Box<dyn GpuFuture>,
+    pub error: Validated<VulkanError>,
+}
+
+impl fmt::Debug for UploadFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UploadFailed")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for UploadFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to submit the upload: {}", self.error)
+    }
+}
+
+enum PendingCopy {
+    Buffer(CopyBufferInfo),
+    Image(CopyBufferToImageInfo),
+}
+
+/// Collects buffer and image uploads from any system during the frame, and submits them as one
+/// transfer command buffer in [`PreUpdate`], before your render systems run.
+///
+/// The data is written into host visible staging buffers and copied on the gpu into (ideally)
+/// device local destinations, so this works without resizable bar.
+///
+/// If the device has a dedicated transfer queue, it is used, but only when every destination in
+/// the batch was created with [`Sharing::Concurrent`] including the transfer queue family.
+/// Otherwise, the batch is submitted on the graphics queue, as we don't do queue family ownership
+/// transfers.
+pub struct GpuUploader {
+    graphics_queue: Arc<Queue>,
+    transfer_queue: Option<Arc<Queue>>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    pending: Vec<PendingCopy>,
+    pending_needs_graphics_queue: bool,
+    /// The batch that pending copies will be submitted as.
+    current_batch: u64,
+    in_flight: Vec<(u64, UploadFuture)>,
+    allocation_count: u64,
+    allocated_bytes: DeviceSize,
+}
+
+impl FromWorld for GpuUploader {
+    fn from_world(world: &mut World) -> Self {
+        let context = world.resource::<BevyVulkanoContext>();
+
+        Self {
+            graphics_queue: context.graphics_queue().clone(),
+            transfer_queue: context.transfer_queue().cloned(),
+            memory_allocator: context.memory_allocator().clone(),
+            command_buffer_allocator: Arc::new(StandardCommandBufferAllocator::new(
+                context.device().clone(),
+                Default::default(),
+            )),
+            pending: Vec::new(),
+            pending_needs_graphics_queue: false,
+            current_batch: 0,
+            in_flight: Vec::new(),
+            allocation_count: 0,
+            allocated_bytes: 0,
+        }
+    }
+}
+
+impl GpuUploader {
+    /// Creates a device local buffer with `usage` and queues `data` to be uploaded into it.
+    pub fn create_buffer_from_iter<T, I>(
+        &mut self,
+        usage: BufferUsage,
+        data: I,
+    ) -> (Subbuffer<[T]>, UploadHandle)
+    where
+        T: BufferContents,
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let data = data.into_iter();
+        let buffer = Buffer::new_slice(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: usage | BufferUsage::TRANSFER_DST,
+                sharing: match self.concurrent_queue_family_indices() {
+                    Some(queue_family_indices) => {
+                        Sharing::Concurrent(queue_family_indices.into_iter().collect())
+                    }
+                    None => Sharing::Exclusive,
+                },
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE,
+                ..Default::default()
+            },
+            data.len() as u64,
+        )
+        .unwrap();
+        self.count_allocation(buffer.size());
+
+        let handle = self.upload_iter(data, buffer.clone());
+        (buffer, handle)
+    }
+
+    /// Queues `data` to be copied into `destination`, which must have been created with
+    /// [`BufferUsage::TRANSFER_DST`].
+    pub fn upload_iter<T, I>(&mut self, data: I, destination: Subbuffer<[T]>) -> UploadHandle
+    where
+        T: BufferContents,
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let staging = self.staging_buffer_from_iter(data);
+        self.queue_buffer_copy(staging, destination)
+    }
+
+    /// Queues `data` to be copied into `destination`, which must have been created with
+    /// [`BufferUsage::TRANSFER_DST`].
+    pub fn upload_data<T>(&mut self, data: T, destination: Subbuffer<T>) -> UploadHandle
+    where
+        T: BufferContents,
+    {
+        let staging = Buffer::from_data(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            Self::staging_allocation_create_info(),
+            data,
+        )
+        .unwrap();
+        self.count_allocation(staging.size());
+
+        self.queue_buffer_copy(staging, destination)
+    }
+
+    /// Queues `data` to be copied into the first mip level of `destination`, which must have been
+    /// created with [`vulkano::image::ImageUsage::TRANSFER_DST`].
+    pub fn upload_image<T, I>(&mut self, data: I, destination: Arc<Image>) -> UploadHandle
+    where
+        T: BufferContents,
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        self.check_sharing(destination.sharing());
+
+        let staging = self.staging_buffer_from_iter(data);
+        self.pending
+            .push(PendingCopy::Image(CopyBufferToImageInfo::buffer_image(
+                staging,
+                destination,
+            )));
+
+        self.current_handle()
+    }
+
+    /// Returns whether the upload has finished on the gpu.
+    pub fn is_complete(&self, handle: UploadHandle) -> bool {
+        if handle.batch == self.current_batch {
+            return false;
+        }
+
+        !self
+            .in_flight
+            .iter()
+            .any(|(batch, future)| *batch == handle.batch && !future.is_signaled().unwrap_or(false))
+    }
+
+    /// Returns the future of the upload, if it is still in flight.
+    /// If the upload hasn't been submitted yet, everything pending is submitted now. Fails if that
+    /// submission fails, the upload then stays pending.
+    pub fn future(
+        &mut self,
+        handle: UploadHandle,
+    ) -> Result<Option<UploadFuture>, Validated<VulkanError>> {
+        if handle.batch == self.current_batch {
+            self.flush()?;
+        }
+
+        Ok(self
+            .in_flight
+            .iter()
+            .find(|(batch, _)| *batch == handle.batch)
+            .map(|(_, future)| future.clone()))
+    }
+
+    /// Joins `future` with the upload, so that whatever comes after can safely use the
+    /// destination. Does nothing if the upload is already complete. Fails if the upload can't be
+    /// submitted, giving `future` back.
+    pub fn join(
+        &mut self,
+        handle: UploadHandle,
+        future: Box<dyn GpuFuture>,
+    ) -> Result<Box<dyn GpuFuture>, UploadFailed> {
+        match self.future(handle) {
+            Ok(Some(upload_future)) => Ok(future.join(upload_future).boxed()),
+            Ok(None) => Ok(future),
+            Err(error) => Err(UploadFailed {
+                future,
+                error,
+            }),
+        }
+    }
+
+    /// Submits every pending upload in one command buffer. This is called for you in
+    /// [`PreUpdate`], but you can call it yourself if you can't wait until then. If the submission
+    /// fails, the uploads stay pending, and their handles incomplete.
+    pub fn flush(&mut self) -> Result<(), Validated<VulkanError>> {
+        // Forget about batches that have finished.
+        self.in_flight
+            .retain(|(_, future)| !future.is_signaled().unwrap_or(false));
+
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let queue = match &self.transfer_queue {
+            Some(transfer_queue) if !self.pending_needs_graphics_queue => transfer_queue.clone(),
+            _ => self.graphics_queue.clone(),
+        };
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.clone(),
+            queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        // The copies stay pending until the batch is submitted, so a failed submission is retried
+        // on the next flush instead of being reported as complete.
+        for copy in &self.pending {
+            match copy {
+                PendingCopy::Buffer(copy) => builder.copy_buffer(copy.clone()).unwrap(),
+                PendingCopy::Image(copy) => builder.copy_buffer_to_image(copy.clone()).unwrap(),
+            };
+        }
+
+        let command_buffer = builder.build().unwrap();
+
+        match sync::now(queue.device().clone())
+            .then_execute(queue, command_buffer)
+            .unwrap()
+            .boxed()
+            .then_signal_fence_and_flush()
+        {
+            Ok(future) => self.in_flight.push((self.current_batch, Arc::new(future))),
+            Err(e) => return Err(e),
+        }
+
+        self.pending.clear();
+        self.pending_needs_graphics_queue = false;
+        self.current_batch += 1;
+
+        Ok(())
+    }
+
+    /// How many buffers the uploader has allocated since it was made, staging buffers included.
+    #[inline]
+    pub fn allocation_count(&self) -> u64 {
+        self.allocation_count
+    }
+
+    /// How many bytes the uploader has allocated since it was made, staging buffers included.
+    #[inline]
+    pub fn allocated_bytes(&self) -> DeviceSize {
+        self.allocated_bytes
+    }
+
+    fn current_handle(&self) -> UploadHandle {
+        UploadHandle {
+            batch: self.current_batch,
+        }
+    }
+
+    fn queue_buffer_copy<T: ?Sized>(
+        &mut self,
+        staging: Subbuffer<T>,
+        destination: Subbuffer<T>,
+    ) -> UploadHandle {
+        self.check_sharing(destination.buffer().sharing());
+
+        self.pending
+            .push(PendingCopy::Buffer(CopyBufferInfo::buffers(
+                staging,
+                destination,
+            )));
+
+        self.current_handle()
+    }
+
+    /// The queue families that buffers created by the uploader are shared between, so they can be
+    /// used by both the transfer and graphics queues.
+    fn concurrent_queue_family_indices(&self) -> Option<[u32; 2]> {
+        let transfer_queue = self.transfer_queue.as_ref()?;
+        let graphics_family = self.graphics_queue.queue_family_index();
+        let transfer_family = transfer_queue.queue_family_index();
+
+        (graphics_family != transfer_family).then_some([graphics_family, transfer_family])
+    }
+
+    /// Falls back to the graphics queue if the destination can't be used from the transfer
+    /// queue.
+    fn check_sharing<I: Deref<Target = [u32]>>(&mut self, sharing: &Sharing<I>) {
+        let Some(transfer_queue) = &self.transfer_queue else {
+            return;
+        };
+
+        let usable_from_transfer_queue = match sharing {
+            Sharing::Exclusive => false,
+            Sharing::Concurrent(queue_family_indices) => {
+                queue_family_indices.contains(&transfer_queue.queue_family_index())
+            }
+        };
+
+        if !usable_from_transfer_queue {
+            self.pending_needs_graphics_queue = true;
+        }
+    }
+
+    fn staging_buffer_from_iter<T, I>(&mut self, data: I) -> Subbuffer<[T]>
+    where
+        T: BufferContents,
+        I: IntoIterator<Item = T>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let staging = Buffer::from_iter(
+            self.memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            Self::staging_allocation_create_info(),
+            data,
+        )
+        .unwrap();
+        self.count_allocation(staging.size());
+
+        staging
+    }
+
+    fn count_allocation(&mut self, bytes: DeviceSize) {
+        self.allocation_count += 1;
+        self.allocated_bytes += bytes;
+    }
+
+    fn staging_allocation_create_info() -> AllocationCreateInfo {
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        }
+    }
+}
+
+/// Submits the uploads queued since the last frame.
+pub fn flush_uploads(mut uploader: NonSendMut<GpuUploader>) {
+    if let Err(e) = uploader.flush() {
+        error!("Failed to submit uploads, retrying on the next flush: {e}");
+    }
+}
@@ -1,22 +1,39 @@
 use bevy::{
     ecs::{entity::EntityHashMap, system::SystemParam},
     prelude::*,
-    window::{PresentMode, WindowClosing, WindowCreated, WindowResized},
+    utils::HashMap,
+    window::{
+        CompositeAlphaMode, PresentMode, PrimaryWindow, WindowBackendScaleFactorChanged,
+        WindowClosing, WindowCreated, WindowMode, WindowResized, WindowScaleFactorChanged,
+    },
     winit::WinitWindows,
 };
+use vulkano::{format::Format, swapchain::CompositeAlpha};
 
 use crate::{
-    renderer::{VulkanoWindowRenderer, VulkanoWindowRendererWithoutWindow},
+    renderer::{
+        composite_alpha_fallbacks, present_mode_fallbacks, OffscreenRenderer, OffscreenTarget,
+        VulkanoWindowRenderer, VulkanoWindowRendererWithoutWindow, WindowAttachments,
+    },
     BevyVulkanoContext,
 };
 
+/// The present mode a window's renderer actually uses, after negotiating with the surface. Inserted
+/// on the window entity, and updated when [`Window::present_mode`] changes or the swapchain is
+/// recreated, see [`update_effective_present_mode`].
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq, Deref)]
+pub struct EffectivePresentMode(pub vulkano::swapchain::PresentMode);
+
 #[derive(SystemParam)]
-pub struct VulkanoRenderers<'w> {
+pub struct VulkanoRenderers<'w, 's> {
     pub renderers: NonSendMut<'w, EntityHashMap<VulkanoWindowRendererWithoutWindow>>,
     pub windows: NonSend<'w, WinitWindows>,
+    /// The images of every [`OffscreenTarget`], by entity.
+    pub offscreen_targets: NonSendMut<'w, EntityHashMap<OffscreenRenderer>>,
+    primary_window: Query<'w, 's, Entity, With<PrimaryWindow>>,
 }
 
-impl<'w> VulkanoRenderers<'w> {
+impl<'w, 's> VulkanoRenderers<'w, 's> {
     pub fn get_renderer(&mut self, entity: Entity) -> Option<VulkanoWindowRenderer> {
         let window = self.windows.get_window(entity)?;
         let renderer = self.renderers.get_mut(&entity)?;
@@ -24,6 +41,31 @@ impl<'w> VulkanoRenderers<'w> {
         Some(VulkanoWindowRenderer::new(window, renderer))
     }
 
+    /// Returns the renderer of the window marked with [`PrimaryWindow`].
+    pub fn get_primary_renderer(&mut self) -> Option<VulkanoWindowRenderer> {
+        let entity = self.primary_window.get_single().ok()?;
+        self.get_renderer(entity)
+    }
+
+    /// Iterates over the renderers of every window, so you can render into all of them in the same
+    /// frame.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, VulkanoWindowRenderer<'_, '_>)> {
+        let windows: &WinitWindows = &self.windows;
+        self.renderers
+            .iter_mut()
+            .filter_map(move |(entity, renderer)| {
+                let window = windows.get_window(*entity)?;
+                Some((*entity, VulkanoWindowRenderer::new(window, renderer)))
+            })
+    }
+
+    /// Returns the images of an entity's [`OffscreenTarget`]. They are made in [`PreUpdate`] and
+    /// [`PostUpdate`], after the target is inserted.
+    #[inline]
+    pub fn get_offscreen_target(&self, entity: Entity) -> Option<&OffscreenRenderer> {
+        self.offscreen_targets.get(&entity)
+    }
+
     pub fn get_renderer_single(&mut self) -> Option<VulkanoWindowRenderer> {
         if self.windows.windows.len() > 1 || self.renderers.len() > 1 {
             None
@@ -36,8 +78,45 @@ impl<'w> VulkanoRenderers<'w> {
     }
 }
 
+/// Resources that depend on the swapchain format, such as render passes and the pipelines made for
+/// them. Windows can have different swapchain formats, so one is created per format, and shared by
+/// every window using it.
+pub struct PerSwapchainFormat<T> {
+    values: HashMap<Format, T>,
+}
+
+impl<T> Default for PerSwapchainFormat<T> {
+    fn default() -> Self {
+        Self {
+            values: HashMap::default(),
+        }
+    }
+}
+
+impl<T> PerSwapchainFormat<T> {
+    /// Returns the value for `format`, creating it first if this is the first window using it.
+    pub fn get_or_insert_with(
+        &mut self,
+        format: Format,
+        create: impl FnOnce(Format) -> T,
+    ) -> &mut T {
+        self.values.entry(format).or_insert_with(|| create(format))
+    }
+
+    #[inline]
+    pub fn get(&self, format: Format) -> Option<&T> {
+        self.values.get(&format)
+    }
+
+    /// Drops the values of formats no window uses anymore.
+    pub fn retain_formats(&mut self, formats: &[Format]) {
+        self.values.retain(|format, _| formats.contains(format));
+    }
+}
+
 /// When a window is created, we hook vulkano into it.
 pub fn create_renderer(
+    mut commands: Commands,
     context: Res<BevyVulkanoContext>,
     mut renderers: VulkanoRenderers,
     mut windows_created: EventReader<WindowCreated>,
@@ -52,19 +131,33 @@ pub fn create_renderer(
                 continue;
             };
 
-            let present_mode = {
-                let Ok(window) = windows.get(window_entity) else {
-                    error!(
-                        "This shouldn't happen! Somehow a window both exists and doesn't exist!"
-                    );
-                    continue;
-                };
-
-                bevy_to_vulkano_present_mode(window.present_mode)
+            let Ok(bevy_window) = windows.get(window_entity) else {
+                error!("This shouldn't happen! Somehow a window both exists and doesn't exist!");
+                continue;
             };
+            let present_mode = bevy_window.present_mode;
+
+            let mut renderer = VulkanoWindowRendererWithoutWindow::new(
+                &context,
+                window,
+                bevy_to_vulkano_present_modes(present_mode),
+                bevy_to_vulkano_composite_alphas(
+                    bevy_window.transparent,
+                    bevy_window.composite_alpha_mode,
+                ),
+                |_| {},
+            );
 
-            let renderer =
-                VulkanoWindowRendererWithoutWindow::new(&context, window, present_mode, |_| {});
+            let effective_present_mode = renderer.present_mode();
+            warn_present_mode_fallback(present_mode, effective_present_mode);
+            warn_composite_alpha_fallback(
+                window_entity,
+                bevy_window,
+                &VulkanoWindowRenderer::new(window, &mut renderer),
+            );
+            commands
+                .entity(window_entity)
+                .insert(EffectivePresentMode(effective_present_mode));
 
             // Safe, as the if statement already checked if it contained a key.
             renderers
@@ -74,29 +167,210 @@ pub fn create_renderer(
     }
 }
 
-pub fn update_present_mode(
+/// The parts of a [`Window`] that were last applied to its renderer, to tell what changed.
+#[derive(Clone, Copy, PartialEq)]
+struct AppliedWindowState {
+    present_mode: PresentMode,
+    transparent: bool,
+    composite_alpha_mode: CompositeAlphaMode,
+    mode: WindowMode,
+    visible: bool,
+}
+
+impl AppliedWindowState {
+    fn new(window: &Window) -> Self {
+        Self {
+            present_mode: window.present_mode,
+            transparent: window.transparent,
+            composite_alpha_mode: window.composite_alpha_mode,
+            mode: window.mode,
+            visible: window.visible,
+        }
+    }
+}
+
+/// Brings each renderer in line with its [`Window`], changing only what differs from what was
+/// last applied. Every change is a setter that only recreates the swapchain if the swapchain
+/// actually changes, and all of them are applied at once when the next frame is acquired.
+///
+/// - The swapchain is resized when the physical size of the window no longer matches it. bevy
+///   updates [`Window::resolution`] on resizes and scale factor changes, such as moving to
+///   another monitor, and the scale factor events are read too, in case the surface changed
+///   without the window.
+/// - [`Window::present_mode`] is negotiated with the surface, see
+///   [`bevy_to_vulkano_present_modes`], and the result kept in [`EffectivePresentMode`].
+/// - [`Window::transparent`] and [`Window::composite_alpha_mode`] pick the composite alpha mode,
+///   see [`bevy_to_vulkano_composite_alphas`].
+/// - [`Window::mode`] asks for exclusive full-screen access in exclusive fullscreen. bevy applies
+///   the mode, picking the video mode by size, at the end of the frame. The swapchain recreation
+///   that follows switches to the highest refresh rate of that size, see
+///   [`crate::renderer::best_video_mode`] and [`VulkanoWindowRenderer::refresh_rate_millihertz`].
+/// - [`WindowAttachments`] on the window entity are made and recreated with the swapchain, and
+///   dropped when the component is removed.
+/// - Hidden windows aren't resized until they are [`Window::visible`] again.
+pub fn reconcile_renderers(
+    mut commands: Commands,
     mut renderers: VulkanoRenderers,
-    windows: Query<(Entity, &Window), Changed<Window>>,
+    windows: Query<(Entity, Ref<Window>, Option<Ref<WindowAttachments>>)>,
+    mut scale_factor_changes: EventReader<WindowScaleFactorChanged>,
+    mut backend_scale_factor_changes: EventReader<WindowBackendScaleFactorChanged>,
+    mut removed_attachments: RemovedComponents<WindowAttachments>,
+    mut applied_states: Local<EntityHashMap<AppliedWindowState>>,
 ) {
-    for (entity, window) in &windows {
+    applied_states.retain(|entity, _| renderers.renderers.contains_key(entity));
+    let attachments_removed = removed_attachments.read().collect::<Vec<_>>();
+    let scale_factor_changed = scale_factor_changes
+        .read()
+        .map(|event| event.window)
+        .chain(
+            backend_scale_factor_changes
+                .read()
+                .map(|event| event.window),
+        )
+        .collect::<Vec<_>>();
+
+    for (entity, window, attachments) in &windows {
+        let attachments_changed = attachments
+            .as_ref()
+            .is_some_and(|attachments| attachments.is_changed())
+            || attachments_removed.contains(&entity);
+        if !window.is_changed()
+            && !attachments_changed
+            && !scale_factor_changed.contains(&entity)
+            && applied_states.contains_key(&entity)
+        {
+            continue;
+        }
+        // winit creates the window at the end of the frame it was spawned in, so its renderer
+        // can be missing for a frame. It is reconciled once it exists.
         let Some(mut renderer) = renderers.get_renderer(entity) else {
-            error!("A window was found without a renderer!");
             continue;
         };
 
-        // Only triggers a swapchain recreation if it was actually changed, don't worry!
-        renderer.set_present_mode(bevy_to_vulkano_present_mode(window.present_mode));
+        let state = AppliedWindowState::new(&window);
+        let previous = applied_states.insert(entity, state);
+
+        // Only warn when the present mode changed, the renderer was created with it.
+        if previous.map(|previous| previous.present_mode) != Some(state.present_mode) {
+            apply_present_mode(&mut commands, entity, state.present_mode, &mut renderer);
+        }
+
+        if previous.map(|previous| (previous.transparent, previous.composite_alpha_mode))
+            != Some((state.transparent, state.composite_alpha_mode))
+        {
+            if renderer.set_composite_alphas(bevy_to_vulkano_composite_alphas(
+                state.transparent,
+                state.composite_alpha_mode,
+            )) {
+                warn_composite_alpha_fallback(entity, &window, &renderer);
+            }
+        }
+
+        if previous.map(|previous| previous.mode) != Some(state.mode) {
+            renderer.set_full_screen_exclusive(matches!(
+                state.mode,
+                WindowMode::Fullscreen | WindowMode::SizedFullscreen
+            ));
+            // Going between borderless and exclusive fullscreen may keep the size, so the
+            // swapchain isn't always outdated.
+            if previous.is_some() {
+                renderer.resize();
+            }
+        }
+
+        renderer.set_attachments(
+            attachments
+                .map(|attachments| *attachments)
+                .unwrap_or_default(),
+        );
+
+        if state.visible && renderer.is_swapchain_outdated() {
+            renderer.resize();
+        }
+    }
+}
+
+/// Applies [`Window::present_mode`] to the renderers of the windows it changed on.
+/// [`reconcile_renderers`], which [`crate::VulkanoPlugin`] adds, already does this. This is for apps
+/// that update renderers with their own systems.
+pub fn update_present_mode(
+    mut commands: Commands,
+    mut renderers: VulkanoRenderers,
+    windows: Query<(Entity, &Window), Changed<Window>>,
+) {
+    for (entity, window) in &windows {
+        if let Some(mut renderer) = renderers.get_renderer(entity) {
+            apply_present_mode(&mut commands, entity, window.present_mode, &mut renderer);
+        }
+    }
+}
+
+/// Recreates the swapchains of windows whose physical size changed, be it from a resize or from
+/// moving to a monitor with a different scale factor. [`reconcile_renderers`], which
+/// [`crate::VulkanoPlugin`] adds, already does this. This is for apps that update renderers with
+/// their own systems.
+pub fn resize(
+    mut renderers: VulkanoRenderers,
+    mut resized_windows: EventReader<WindowResized>,
+    mut scale_factor_changes: EventReader<WindowScaleFactorChanged>,
+    mut backend_scale_factor_changes: EventReader<WindowBackendScaleFactorChanged>,
+) {
+    let windows = resized_windows
+        .read()
+        .map(|event| event.window)
+        .chain(scale_factor_changes.read().map(|event| event.window))
+        .chain(
+            backend_scale_factor_changes
+                .read()
+                .map(|event| event.window),
+        );
+
+    for window in windows {
+        // A scale factor change often comes with a resize, only recreate once.
+        if let Some(mut renderer) = renderers.get_renderer(window) {
+            if renderer.is_swapchain_outdated() {
+                renderer.resize();
+            }
+        }
     }
 }
 
-pub fn resize(mut renderers: VulkanoRenderers, mut resized_windows: EventReader<WindowResized>) {
-    for resized_window in resized_windows.read() {
-        let Some(mut renderer) = renderers.get_renderer(resized_window.window) else {
-            error!("A window was found without a renderer!");
+/// Keeps [`EffectivePresentMode`] in sync with the renderers. They pick their present mode again
+/// when the swapchain is recreated, as the surface may support other modes by then, for example
+/// after the window moved to another monitor. [`crate::VulkanoPlugin`] adds this in [`Last`].
+pub fn update_effective_present_mode(
+    mut commands: Commands,
+    renderers: NonSend<EntityHashMap<VulkanoWindowRendererWithoutWindow>>,
+    windows: Query<(Entity, &Window, Option<&EffectivePresentMode>)>,
+) {
+    for (entity, window, effective_present_mode) in &windows {
+        let Some(renderer) = renderers.get(&entity) else {
             continue;
         };
+        let present_mode = renderer.present_mode();
+        if effective_present_mode.map(|effective| effective.0) != Some(present_mode) {
+            if effective_present_mode.is_some() {
+                warn_present_mode_fallback(window.present_mode, present_mode);
+            }
+            commands
+                .entity(entity)
+                .insert(EffectivePresentMode(present_mode));
+        }
+    }
+}
 
-        renderer.resize();
+/// Negotiates `present_mode` with the surface, and keeps the result in [`EffectivePresentMode`].
+fn apply_present_mode(
+    commands: &mut Commands,
+    entity: Entity,
+    present_mode: PresentMode,
+    renderer: &mut VulkanoWindowRenderer,
+) {
+    if renderer.set_present_modes(bevy_to_vulkano_present_modes(present_mode)) {
+        warn_present_mode_fallback(present_mode, renderer.present_mode());
+        commands
+            .entity(entity)
+            .insert(EffectivePresentMode(renderer.present_mode()));
     }
 }
 
@@ -109,13 +383,130 @@ pub fn destroy_renderer(
     }
 }
 
+/// Makes the images of new [`OffscreenTarget`]s, remakes them when the target changes, and drops
+/// them when it is removed.
+pub fn update_offscreen_targets(
+    context: Res<BevyVulkanoContext>,
+    mut offscreen_targets: NonSendMut<EntityHashMap<OffscreenRenderer>>,
+    targets: Query<(Entity, &OffscreenTarget), Changed<OffscreenTarget>>,
+    mut removed: RemovedComponents<OffscreenTarget>,
+) {
+    for entity in removed.read() {
+        offscreen_targets.remove(&entity);
+    }
+
+    for (entity, target) in &targets {
+        // Mutably borrowing the target marks it as changed, even if nothing changed.
+        if offscreen_targets
+            .get(&entity)
+            .is_some_and(|renderer| renderer.target() == *target)
+        {
+            continue;
+        }
+        if target.extent.contains(&0) {
+            offscreen_targets.remove(&entity);
+            continue;
+        }
+
+        match OffscreenRenderer::new(
+            context.memory_allocator(),
+            context.device().physical_device(),
+            *target,
+        ) {
+            Ok(renderer) => {
+                offscreen_targets.insert(entity, renderer);
+            }
+            Err(e) => {
+                warn!("Skipping the offscreen target of {entity}: {e}");
+                offscreen_targets.remove(&entity);
+            }
+        }
+    }
+}
+
+/// Returns the preferred vulkano present mode for a bevy one. See [`bevy_to_vulkano_present_modes`]
+/// for what's used when it isn't supported.
 pub fn bevy_to_vulkano_present_mode(present_mode: PresentMode) -> vulkano::swapchain::PresentMode {
+    bevy_to_vulkano_present_modes(present_mode)[0]
+}
+
+/// Returns the vulkano present modes to try, in order, for a bevy one.
+///
+/// The `Auto` modes follow bevy: [`PresentMode::AutoVsync`] tries `FifoRelaxed` then `Fifo`, and
+/// [`PresentMode::AutoNoVsync`] tries `Immediate`, `Mailbox` then `Fifo`. The other modes use
+/// [`present_mode_fallbacks`].
+pub fn bevy_to_vulkano_present_modes(
+    present_mode: PresentMode,
+) -> &'static [vulkano::swapchain::PresentMode] {
     match present_mode {
-        PresentMode::Fifo => vulkano::swapchain::PresentMode::Fifo,
-        PresentMode::Immediate => vulkano::swapchain::PresentMode::Immediate,
-        PresentMode::Mailbox => vulkano::swapchain::PresentMode::Mailbox,
-        PresentMode::AutoNoVsync => vulkano::swapchain::PresentMode::Immediate,
-        PresentMode::AutoVsync => vulkano::swapchain::PresentMode::FifoRelaxed,
-        PresentMode::FifoRelaxed => vulkano::swapchain::PresentMode::FifoRelaxed,
+        PresentMode::Fifo => present_mode_fallbacks(vulkano::swapchain::PresentMode::Fifo),
+        PresentMode::Immediate => {
+            present_mode_fallbacks(vulkano::swapchain::PresentMode::Immediate)
+        }
+        PresentMode::Mailbox => present_mode_fallbacks(vulkano::swapchain::PresentMode::Mailbox),
+        PresentMode::AutoNoVsync => {
+            present_mode_fallbacks(vulkano::swapchain::PresentMode::Immediate)
+        }
+        PresentMode::AutoVsync => {
+            present_mode_fallbacks(vulkano::swapchain::PresentMode::FifoRelaxed)
+        }
+        PresentMode::FifoRelaxed => {
+            present_mode_fallbacks(vulkano::swapchain::PresentMode::FifoRelaxed)
+        }
+    }
+}
+
+/// Returns the vulkano composite alpha modes to try, in order, for a bevy window.
+///
+/// [`CompositeAlphaMode::Auto`] tries `PreMultiplied`, `PostMultiplied` then `Inherit` for
+/// transparent windows, and `Opaque` then `Inherit` for the others. The other modes use
+/// [`composite_alpha_fallbacks`].
+pub fn bevy_to_vulkano_composite_alphas(
+    transparent: bool,
+    composite_alpha_mode: CompositeAlphaMode,
+) -> &'static [CompositeAlpha] {
+    composite_alpha_fallbacks(match composite_alpha_mode {
+        CompositeAlphaMode::Auto if transparent => CompositeAlpha::PreMultiplied,
+        CompositeAlphaMode::Auto | CompositeAlphaMode::Opaque => CompositeAlpha::Opaque,
+        CompositeAlphaMode::PreMultiplied => CompositeAlpha::PreMultiplied,
+        CompositeAlphaMode::PostMultiplied => CompositeAlpha::PostMultiplied,
+        CompositeAlphaMode::Inherit => CompositeAlpha::Inherit,
+    })
+}
+
+/// A transparent window that ends up opaque is worth a warning. Otherwise, like the present mode,
+/// `Auto` is expected to fall back and the other modes are worth a warning.
+fn warn_composite_alpha_fallback(
+    entity: Entity,
+    window: &Window,
+    renderer: &VulkanoWindowRenderer,
+) {
+    let requested =
+        bevy_to_vulkano_composite_alphas(window.transparent, window.composite_alpha_mode)[0];
+    let effective = renderer.composite_alpha();
+
+    if window.transparent && !renderer.is_transparent() {
+        warn!(
+            "Window {entity} is transparent, but the surface doesn't support transparency. It \
+             will be opaque, using composite alpha mode {effective:?}."
+        );
+    } else if window.composite_alpha_mode != CompositeAlphaMode::Auto && effective != requested {
+        warn!(
+            "Window {entity} asked for composite alpha mode {requested:?}, which the surface \
+             doesn't support, using {effective:?} instead."
+        );
+    }
+}
+
+/// The `Auto` modes are expected to fall back, the others are worth a warning.
+fn warn_present_mode_fallback(requested: PresentMode, effective: vulkano::swapchain::PresentMode) {
+    if matches!(requested, PresentMode::AutoVsync | PresentMode::AutoNoVsync)
+        || bevy_to_vulkano_present_mode(requested) == effective
+    {
+        return;
     }
+
+    warn!(
+        "Present mode {requested:?} isn't supported by the surface, using {effective:?} instead."
+    );
 }